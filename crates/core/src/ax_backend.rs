@@ -0,0 +1,604 @@
+#![allow(unsafe_op_in_unsafe_fn)]
+
+use std::{
+    collections::HashMap,
+    ffi::c_void,
+    ptr::NonNull,
+    sync::{
+        Arc, Mutex, MutexGuard,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Sender},
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use core_foundation::{
+    array::{CFArray, CFArrayRef},
+    base::{CFEqual, CFRelease, CFRetain, CFType, TCFType},
+    date::CFAbsoluteTimeGetCurrent,
+    runloop::{
+        CFRunLoop, CFRunLoopSource, CFRunLoopSourceRef, CFRunLoopTimer, CFRunLoopTimerContext,
+        CFRunLoopTimerRef, kCFRunLoopDefaultMode,
+    },
+};
+
+use crate::{
+    doctor::{AccessibilityCheck, Check},
+    frame::{Frame, frame_of, set_frame_of},
+    screen::{self, Screen},
+    window::{
+        __AXUIElement, AXError, AXUIElementCreateApplication, AXUIElementGetPid, AXUIElementRef,
+        AppIdentity, Subscription, WindowBackend, WindowEvent, WindowId, WindowInfo, bundle_id_of,
+        cfstring_ref, copy_attr, copy_string, get_focused_window,
+        get_kAXApplicationActivatedNotification, get_kAXFocusedWindowAttribute,
+        get_kAXFocusedWindowChangedNotification, get_kAXTitleAttribute,
+        get_kAXUIElementDestroyedNotification, get_kAXWindowCreatedNotification,
+        get_kAXWindowMovedNotification, get_kAXWindowResizedNotification, get_kAXWindowsAttribute,
+        running_app_pids,
+    },
+};
+
+/// 新しく起動したアプリを探す間隔（秒）
+const LAUNCH_POLL_INTERVAL: f64 = 1.0;
+/// 起動直後で AX の準備ができていないアプリの監視を試す回数
+const MAX_ATTACH_ATTEMPTS: u32 = 10;
+
+type CFStringRef = *const c_void;
+type AXObserverRef = *const c_void;
+type AXObserverCallback = unsafe extern "C" fn(
+    observer: AXObserverRef,
+    element: AXUIElementRef,
+    notification: CFStringRef,
+    refcon: *mut c_void,
+);
+
+unsafe extern "C" {
+    fn AXObserverCreate(
+        pid: i32,
+        callback: AXObserverCallback,
+        observer: *mut AXObserverRef,
+    ) -> AXError;
+    fn AXObserverAddNotification(
+        observer: AXObserverRef,
+        element: AXUIElementRef,
+        notification: CFStringRef,
+        refcon: *mut c_void,
+    ) -> AXError;
+    fn AXObserverGetRunLoopSource(observer: AXObserverRef) -> CFRunLoopSourceRef;
+    fn AXUIElementSetMessagingTimeout(element: AXUIElementRef, timeout: f32) -> AXError;
+}
+
+/// 保持している AX 要素。落とすと解放する
+struct Element(NonNull<__AXUIElement>);
+
+// AXUIElement は CF オブジェクトで、どのスレッドからでも使える
+unsafe impl Send for Element {}
+
+impl Element {
+    unsafe fn retain(element: AXUIElementRef) -> Option<Self> {
+        NonNull::new(element as *mut __AXUIElement).map(|element| {
+            CFRetain(element.as_ptr() as _);
+            Self(element)
+        })
+    }
+
+    fn as_ptr(&self) -> AXUIElementRef {
+        self.0.as_ptr()
+    }
+}
+
+impl Drop for Element {
+    fn drop(&mut self) {
+        unsafe {
+            CFRelease(self.0.as_ptr() as _);
+        }
+    }
+}
+
+/// 監視で見つけたウィンドウと、振った番号
+#[derive(Default)]
+struct Windows {
+    last: WindowId,
+    elements: HashMap<WindowId, Element>,
+}
+
+impl Windows {
+    fn insert(&mut self, element: Element) -> WindowId {
+        self.last += 1;
+        self.elements.insert(self.last, element);
+        self.last
+    }
+
+    unsafe fn find(&self, element: AXUIElementRef) -> Option<WindowId> {
+        self.elements
+            .iter()
+            .find(|(_, known)| CFEqual(known.as_ptr() as _, element as _) != 0)
+            .map(|(window, _)| *window)
+    }
+
+    /// `element` の番号。まだ振っていなければ振る
+    unsafe fn id_of(&mut self, element: AXUIElementRef) -> Option<WindowId> {
+        match self.find(element) {
+            Some(window) => Some(window),
+            None => Element::retain(element).map(|element| self.insert(element)),
+        }
+    }
+}
+
+/// AX API を使うバックエンド
+///
+/// 監視では起動しているアプリごとに AXObserver を作り、専用のスレッドの run loop で通知を受ける。
+/// ウィンドウの番号は監視や一覧、フォーカスの取得で見つけたウィンドウに振る
+#[derive(Clone, Default)]
+pub struct AxBackend {
+    windows: Arc<Mutex<Windows>>,
+    /// ウィンドウの読み書きで応答を待つ時間。`None` なら OS の既定
+    timeout: Option<Duration>,
+}
+
+impl AxBackend {
+    /// ウィンドウの読み書きで、応答しないアプリを `timeout` までしか待たないバックエンド
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..Self::default()
+        }
+    }
+
+    fn table(&self) -> MutexGuard<'_, Windows> {
+        self.windows.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// `window` の要素を保持して返す
+    ///
+    /// 応答の遅いアプリへの AX の呼び出しで監視や他の操作を止めないよう、表のロックはすぐに外す
+    fn element(&self, window: WindowId) -> anyhow::Result<Element> {
+        let windows = self.table();
+        let element = windows
+            .elements
+            .get(&window)
+            .ok_or_else(|| anyhow!("No window {window}"))?;
+        let element = unsafe { Element::retain(element.as_ptr()) }
+            .ok_or_else(|| anyhow!("No window {window}"))?;
+        if let Some(timeout) = self.timeout {
+            unsafe {
+                AXUIElementSetMessagingTimeout(element.as_ptr(), timeout.as_secs_f32());
+            }
+        }
+        Ok(element)
+    }
+}
+
+impl WindowBackend for AxBackend {
+    fn screens(&self) -> anyhow::Result<Vec<Screen>> {
+        // CGGetActiveDisplayList の先頭はメインディスプレイ
+        screen::screens()
+    }
+
+    fn frame(&self, window: WindowId) -> anyhow::Result<Frame> {
+        let element = self.element(window)?;
+        unsafe { frame_of(element.as_ptr()) }
+    }
+
+    fn set_frame(&self, window: WindowId, frame: Frame) -> anyhow::Result<()> {
+        let element = self.element(window)?;
+        unsafe { set_frame_of(element.as_ptr(), frame) }
+    }
+
+    fn windows(&self) -> anyhow::Result<Vec<WindowInfo>> {
+        let own = std::process::id() as i32;
+        // 一覧を作る間に監視などで見つかったウィンドウは残す
+        let known = self.table().last;
+        let mut listed = Vec::new();
+
+        for pid in unsafe { running_app_pids() } {
+            if pid == own {
+                continue;
+            }
+            unsafe {
+                let Some(app) =
+                    NonNull::new(AXUIElementCreateApplication(pid) as *mut __AXUIElement)
+                        .map(Element)
+                else {
+                    continue;
+                };
+                let Some(elements) = copy_attr(app.as_ptr(), get_kAXWindowsAttribute()) else {
+                    continue;
+                };
+                let identity = AppIdentity {
+                    bundle_id: bundle_id_of(pid),
+                    name: copy_string(app.as_ptr(), get_kAXTitleAttribute()),
+                    title: None,
+                };
+                let elements = CFArray::<CFType>::wrap_under_create_rule(elements as CFArrayRef);
+                for element in elements.iter() {
+                    let element = element.as_CFTypeRef() as AXUIElementRef;
+                    // 表のロックは番号を振る間だけ持ち、AX の呼び出しの間は外す
+                    let Ok(frame) = frame_of(element) else {
+                        continue;
+                    };
+                    let Some(window) = self.table().id_of(element) else {
+                        continue;
+                    };
+                    listed.push(WindowInfo {
+                        window,
+                        app: AppIdentity {
+                            title: copy_string(element, get_kAXTitleAttribute()),
+                            ..identity.clone()
+                        },
+                        frame,
+                    });
+                }
+            }
+        }
+
+        // 一覧に出てこなかったウィンドウは閉じられている
+        self.table().elements.retain(|window, _| {
+            *window > known || listed.iter().any(|info| info.window == *window)
+        });
+        Ok(listed)
+    }
+
+    fn focused(&self) -> anyhow::Result<WindowId> {
+        unsafe {
+            let element = get_focused_window()
+                .and_then(|window| NonNull::new(window as *mut __AXUIElement))
+                .map(Element)
+                .ok_or_else(|| anyhow!("No focused window"))?;
+            self.table()
+                .id_of(element.as_ptr())
+                .ok_or_else(|| anyhow!("No focused window"))
+        }
+    }
+
+    fn subscribe(&self, events: Sender<WindowEvent>) -> anyhow::Result<Subscription> {
+        let (ready, started) = mpsc::channel();
+        let windows = Arc::clone(&self.windows);
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stop = Arc::clone(&stopped);
+
+        thread::spawn(move || unsafe { observe(windows, events, stop, ready) });
+        let run_loop = started
+            .recv()
+            .map_err(|_| anyhow!("Failed to start the window observer"))?;
+
+        Ok(Subscription::new(move || {
+            stopped.store(true, Ordering::SeqCst);
+            run_loop.stop();
+        }))
+    }
+
+    fn checks(&self) -> Vec<Box<dyn Check + '_>> {
+        vec![Box::new(AccessibilityCheck)]
+    }
+}
+
+/// 監視しているアプリ。落とすと監視をやめる
+struct ObservedApp {
+    /// 通知を受ける間は保持しておく
+    _element: Element,
+    observer: AXObserverRef,
+    source: CFRunLoopSource,
+    run_loop: CFRunLoop,
+    /// `title` は持たない
+    identity: AppIdentity,
+}
+
+impl Drop for ObservedApp {
+    fn drop(&mut self) {
+        unsafe {
+            self.run_loop
+                .remove_source(&self.source, kCFRunLoopDefaultMode);
+            CFRelease(self.observer);
+        }
+    }
+}
+
+/// 監視のスレッドの状態。AX の通知とタイマーに渡す
+struct Observer {
+    windows: Arc<Mutex<Windows>>,
+    events: Sender<WindowEvent>,
+    stopped: Arc<AtomicBool>,
+    run_loop: CFRunLoop,
+    apps: HashMap<i32, ObservedApp>,
+    /// まだ監視できていないアプリと、起動したばかりかどうか・試した回数
+    pending: HashMap<i32, (bool, u32)>,
+}
+
+unsafe fn observe(
+    windows: Arc<Mutex<Windows>>,
+    events: Sender<WindowEvent>,
+    stopped: Arc<AtomicBool>,
+    ready: Sender<CFRunLoop>,
+) {
+    let run_loop = CFRunLoop::get_current();
+    let mut observer = Box::new(Observer {
+        windows,
+        events,
+        stopped,
+        run_loop: run_loop.clone(),
+        apps: HashMap::new(),
+        pending: HashMap::new(),
+    });
+
+    // すでに起動しているアプリのウィンドウは知らせない
+    for pid in running_app_pids() {
+        if pid != std::process::id() as i32 && !observer.attach(pid, false) {
+            observer.pending.insert(pid, (false, 1));
+        }
+    }
+
+    let mut context = CFRunLoopTimerContext {
+        version: 0,
+        info: (&mut *observer as *mut Observer).cast(),
+        retain: None,
+        release: None,
+        copyDescription: None,
+    };
+    let timer = CFRunLoopTimer::new(
+        CFAbsoluteTimeGetCurrent() + LAUNCH_POLL_INTERVAL,
+        LAUNCH_POLL_INTERVAL,
+        0,
+        0,
+        poll_launches,
+        &mut context,
+    );
+    run_loop.add_timer(&timer, kCFRunLoopDefaultMode);
+
+    if ready.send(run_loop.clone()).is_err() {
+        return;
+    }
+    CFRunLoop::run_current();
+
+    run_loop.remove_timer(&timer, kCFRunLoopDefaultMode);
+    drop(observer);
+}
+
+impl Observer {
+    /// `pid` のアプリの監視を始める。`launched` なら起動と既存のウィンドウを知らせる
+    ///
+    /// アプリの準備ができていなければ `false` を返す
+    unsafe fn attach(&mut self, pid: i32, launched: bool) -> bool {
+        let Some(app) =
+            NonNull::new(AXUIElementCreateApplication(pid) as *mut __AXUIElement).map(Element)
+        else {
+            return false;
+        };
+
+        let mut observer: AXObserverRef = std::ptr::null();
+        if AXObserverCreate(pid, on_notification, &mut observer) != AXError::SUCCESS {
+            log::debug!("Cannot observe the app {pid} yet");
+            return false;
+        }
+        let refcon = (self as *mut Observer).cast();
+        if AXObserverAddNotification(
+            observer,
+            app.as_ptr(),
+            get_kAXWindowCreatedNotification(),
+            refcon,
+        ) != AXError::SUCCESS
+        {
+            CFRelease(observer);
+            return false;
+        }
+        // アプリの要素に登録すれば、そのアプリのすべてのウィンドウについて届く
+        for notification in [
+            get_kAXWindowMovedNotification(),
+            get_kAXWindowResizedNotification(),
+            get_kAXFocusedWindowChangedNotification(),
+            get_kAXApplicationActivatedNotification(),
+        ] {
+            AXObserverAddNotification(observer, app.as_ptr(), notification, refcon);
+        }
+
+        let source = CFRunLoopSource::wrap_under_get_rule(AXObserverGetRunLoopSource(observer));
+        self.run_loop.add_source(&source, kCFRunLoopDefaultMode);
+        let identity = AppIdentity {
+            bundle_id: bundle_id_of(pid),
+            name: copy_string(app.as_ptr(), get_kAXTitleAttribute()),
+            title: None,
+        };
+        let windows = if launched {
+            copy_attr(app.as_ptr(), get_kAXWindowsAttribute())
+        } else {
+            None
+        };
+        self.apps.insert(
+            pid,
+            ObservedApp {
+                _element: app,
+                observer,
+                source,
+                run_loop: self.run_loop.clone(),
+                identity: identity.clone(),
+            },
+        );
+
+        if launched {
+            self.send(WindowEvent::AppLaunched { app: identity });
+            // 監視を始める前に作られたウィンドウ
+            if let Some(windows) = windows {
+                let windows = CFArray::<CFType>::wrap_under_create_rule(windows as CFArrayRef);
+                for window in windows.iter() {
+                    self.window_created(window.as_CFTypeRef() as AXUIElementRef);
+                }
+            }
+        }
+        true
+    }
+
+    /// 新しいウィンドウに番号を振って知らせる
+    unsafe fn window_created(&mut self, element: AXUIElementRef) {
+        if let Some((window, app)) = self.describe(element) {
+            let frame = frame_of(element).ok();
+            self.send(WindowEvent::WindowCreated { window, app, frame });
+        }
+    }
+
+    unsafe fn window_destroyed(&mut self, element: AXUIElementRef) {
+        let window = {
+            let mut windows = self.windows.lock().unwrap_or_else(|err| err.into_inner());
+            let Some(window) = windows.find(element) else {
+                return;
+            };
+            windows.elements.remove(&window);
+            window
+        };
+        // 閉じたウィンドウのタイトルは読めないのでアプリだけを知らせる
+        let mut pid = 0;
+        let app = if AXUIElementGetPid(element, &mut pid) == AXError::SUCCESS {
+            self.apps.get(&pid).map(|app| app.identity.clone())
+        } else {
+            None
+        };
+        self.send(WindowEvent::WindowDestroyed {
+            window,
+            app: app.unwrap_or_default(),
+        });
+    }
+
+    /// ウィンドウが動いた・大きさが変わった
+    unsafe fn window_changed(&mut self, element: AXUIElementRef, resized: bool) {
+        let Some((window, app)) = self.describe(element) else {
+            return;
+        };
+        let Ok(frame) = frame_of(element) else {
+            return;
+        };
+        self.send(if resized {
+            WindowEvent::WindowResized { window, app, frame }
+        } else {
+            WindowEvent::WindowMoved { window, app, frame }
+        });
+    }
+
+    /// フォーカスされたウィンドウが変わった。`element` がアプリならその最前面のウィンドウ
+    unsafe fn focus_changed(&mut self, element: AXUIElementRef, activated: bool) {
+        let focused = if activated {
+            match copy_attr(element, get_kAXFocusedWindowAttribute())
+                .and_then(|window| NonNull::new(window as *mut __AXUIElement))
+            {
+                Some(window) => Element(window),
+                None => return,
+            }
+        } else {
+            match Element::retain(element) {
+                Some(window) => window,
+                None => return,
+            }
+        };
+        if let Some((window, app)) = self.describe(focused.as_ptr()) {
+            let frame = frame_of(focused.as_ptr()).ok();
+            self.send(WindowEvent::FocusChanged { window, app, frame });
+        }
+    }
+
+    /// ウィンドウの番号とアプリを返す。初めて見るウィンドウには番号を振り、閉じられたときの通知を受ける
+    unsafe fn describe(&mut self, element: AXUIElementRef) -> Option<(WindowId, AppIdentity)> {
+        let mut pid = 0;
+        if AXUIElementGetPid(element, &mut pid) != AXError::SUCCESS {
+            return None;
+        }
+        let (observer, identity) = self
+            .apps
+            .get(&pid)
+            .map(|app| (app.observer, app.identity.clone()))?;
+
+        let known = self
+            .windows
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .find(element);
+        let window = match known {
+            Some(window) => window,
+            None => {
+                let retained = Element::retain(element)?;
+                let refcon = (self as *mut Observer).cast();
+                AXObserverAddNotification(
+                    observer,
+                    element,
+                    get_kAXUIElementDestroyedNotification(),
+                    refcon,
+                );
+                self.windows
+                    .lock()
+                    .unwrap_or_else(|err| err.into_inner())
+                    .insert(retained)
+            }
+        };
+        let app = AppIdentity {
+            title: copy_string(element, get_kAXTitleAttribute()),
+            ..identity
+        };
+        Some((window, app))
+    }
+
+    /// 終了したアプリの監視をやめ、新しく起動したアプリの監視を始める
+    unsafe fn poll(&mut self) {
+        let own = std::process::id() as i32;
+        let pids: Vec<i32> = running_app_pids()
+            .into_iter()
+            .filter(|pid| *pid != own)
+            .collect();
+        self.apps.retain(|pid, _| pids.contains(pid));
+        self.pending.retain(|pid, _| pids.contains(pid));
+
+        for pid in pids {
+            if self.apps.contains_key(&pid) {
+                continue;
+            }
+            let (launched, attempts) = self.pending.get(&pid).copied().unwrap_or((true, 0));
+            if attempts >= MAX_ATTACH_ATTEMPTS {
+                continue;
+            }
+            if self.attach(pid, launched) {
+                self.pending.remove(&pid);
+            } else {
+                self.pending.insert(pid, (launched, attempts + 1));
+            }
+        }
+    }
+
+    /// 受け取る側がいなくなっていれば監視を止める
+    fn send(&self, event: WindowEvent) {
+        if self.events.send(event).is_err() {
+            self.stopped.store(true, Ordering::SeqCst);
+            self.run_loop.stop();
+        }
+    }
+}
+
+unsafe extern "C" fn on_notification(
+    _observer: AXObserverRef,
+    element: AXUIElementRef,
+    notification: CFStringRef,
+    refcon: *mut c_void,
+) {
+    let observer = &mut *(refcon as *mut Observer);
+    let notification = cfstring_ref(notification);
+
+    if notification == cfstring_ref(get_kAXWindowCreatedNotification()) {
+        observer.window_created(element);
+    } else if notification == cfstring_ref(get_kAXUIElementDestroyedNotification()) {
+        observer.window_destroyed(element);
+    } else if notification == cfstring_ref(get_kAXWindowMovedNotification()) {
+        observer.window_changed(element, false);
+    } else if notification == cfstring_ref(get_kAXWindowResizedNotification()) {
+        observer.window_changed(element, true);
+    } else if notification == cfstring_ref(get_kAXFocusedWindowChangedNotification()) {
+        observer.focus_changed(element, false);
+    } else if notification == cfstring_ref(get_kAXApplicationActivatedNotification()) {
+        observer.focus_changed(element, true);
+    }
+}
+
+extern "C" fn poll_launches(_timer: CFRunLoopTimerRef, info: *mut c_void) {
+    let observer = unsafe { &mut *(info as *mut Observer) };
+    if observer.stopped.load(Ordering::SeqCst) {
+        observer.run_loop.stop();
+        return;
+    }
+    unsafe { observer.poll() }
+}
@@ -13,6 +13,34 @@ CFStringRef get_kAXPositionAttribute(void) { return kAXPositionAttribute; }
 
 CFStringRef get_kAXSizeAttribute(void) { return kAXSizeAttribute; }
 
+CFStringRef get_kAXTitleAttribute(void) { return kAXTitleAttribute; }
+
+CFStringRef get_kAXWindowsAttribute(void) { return kAXWindowsAttribute; }
+
+CFStringRef get_kAXWindowCreatedNotification(void) {
+  return kAXWindowCreatedNotification;
+}
+
+CFStringRef get_kAXUIElementDestroyedNotification(void) {
+  return kAXUIElementDestroyedNotification;
+}
+
 CFStringRef get_kAXTrustedCheckOptionPrompt(void) {
   return kAXTrustedCheckOptionPrompt;
 }
+
+CFStringRef get_kAXWindowMovedNotification(void) {
+  return kAXWindowMovedNotification;
+}
+
+CFStringRef get_kAXWindowResizedNotification(void) {
+  return kAXWindowResizedNotification;
+}
+
+CFStringRef get_kAXFocusedWindowChangedNotification(void) {
+  return kAXFocusedWindowChangedNotification;
+}
+
+CFStringRef get_kAXApplicationActivatedNotification(void) {
+  return kAXApplicationActivatedNotification;
+}
@@ -0,0 +1,544 @@
+use std::{fmt, ops::Range, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    frame::{Direction, Edge},
+    operation::{Category, Operation, REGISTRY},
+    outcome::OperationOutcome,
+    preferences::{Binding, Preferences, suggest},
+    screen::ScreenSelector,
+    step::Step,
+    window::{WindowBackend, WindowId},
+};
+
+/// コマンドの先頭に書くキーワード
+const KEYWORDS: &[&str] = &["move", "resize", "place", "cycle", "screen"];
+
+/// コマンド言語の 1 コマンド
+///
+/// 量を省略した `move` / `resize` は設定の `move_step` / `resize_step` を使う
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// `move <direction> [<step>]`
+    Move {
+        direction: Direction,
+        step: Option<Step>,
+    },
+    /// `resize <edge> [+|-<step>]`
+    ///
+    /// 正の量は辺を外側へ動かして広げ、負の量は内側へ動かして縮める
+    Resize { edge: Edge, delta: Option<Step> },
+    /// `place <preset>`
+    Place(String),
+    /// `cycle`
+    Cycle,
+    /// `screen <screen>`
+    Screen(ScreenSelector),
+    /// `move_left` など `REGISTRY` の操作名
+    Operation(Operation),
+}
+
+impl Command {
+    /// 実行する操作と、設定の代わりに使う量
+    pub fn operation(&self) -> (Operation, Option<Step>) {
+        match self {
+            Command::Move { direction, step } => {
+                let operation = match direction {
+                    Direction::Left => Operation::MoveLeft,
+                    Direction::Right => Operation::MoveRight,
+                    Direction::Up => Operation::MoveUp,
+                    Direction::Down => Operation::MoveDown,
+                };
+                (operation, *step)
+            }
+            Command::Resize { edge, delta } => {
+                let shrink = delta.is_some_and(Step::is_negative);
+                let operation = match (edge, shrink) {
+                    (Edge::Left, false) => Operation::ResizeLeftToLeft,
+                    (Edge::Left, true) => Operation::ResizeLeftToRight,
+                    (Edge::Right, false) => Operation::ResizeRightToRight,
+                    (Edge::Right, true) => Operation::ResizeRightToLeft,
+                    (Edge::Top, false) => Operation::ResizeTopToTop,
+                    (Edge::Top, true) => Operation::ResizeTopToBottom,
+                    (Edge::Bottom, false) => Operation::ResizeBottomToBottom,
+                    (Edge::Bottom, true) => Operation::ResizeBottomToTop,
+                };
+                (operation, delta.map(Step::abs))
+            }
+            Command::Place(name) => (Operation::ApplyPreset(name.clone()), None),
+            Command::Cycle => (Operation::CyclePresets, None),
+            Command::Screen(selector) => (Operation::MoveToScreen(*selector), None),
+            Command::Operation(operation) => (operation.clone(), None),
+        }
+    }
+
+    /// `binding` の設定（省略時はグローバル設定）で `backend` を通して `window` に実行する
+    pub fn execute(
+        &self,
+        backend: &dyn WindowBackend,
+        window: WindowId,
+        preferences: &Preferences,
+        binding: Option<&Binding>,
+    ) -> anyhow::Result<OperationOutcome> {
+        self.execute_times(backend, window, preferences, binding, 1)
+    }
+
+    /// 移動量・リサイズ量を `count` 倍して実行する
+    ///
+    /// `count` 回繰り返さず、1 回のフレーム更新で済ませる。量を使わないコマンドは 1 回だけ実行される
+    pub fn execute_times(
+        &self,
+        backend: &dyn WindowBackend,
+        window: WindowId,
+        preferences: &Preferences,
+        binding: Option<&Binding>,
+        count: u32,
+    ) -> anyhow::Result<OperationOutcome> {
+        let (operation, step) = self.operation();
+        let mut options = preferences.options_for(&operation, binding);
+        if let Some(step) = step {
+            options.step = step;
+        }
+        options.step = options.step.times(count);
+        operation.execute(backend, window, &preferences.presets, &options)
+    }
+
+    /// `repeat_last` かどうか
+    pub fn is_repeat_last(&self) -> bool {
+        matches!(self, Command::Operation(Operation::RepeatLast))
+    }
+
+    /// 量を足し合わせられる（2 回の実行を `execute_times` の 1 回にまとめられる）移動・リサイズか
+    pub fn accumulates(&self) -> bool {
+        match self {
+            Command::Move { .. } | Command::Resize { .. } => true,
+            Command::Operation(operation) => {
+                !matches!(operation, Operation::MoveToScreen(_))
+                    && matches!(operation.info().category, Category::Move | Category::Resize)
+            }
+            Command::Place(_) | Command::Cycle | Command::Screen(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Move { direction, step } => {
+                write!(f, "move {direction}")?;
+                if let Some(step) = step {
+                    write!(f, " {step}")?;
+                }
+                Ok(())
+            }
+            Command::Resize { edge, delta } => {
+                write!(f, "resize {edge}")?;
+                if let Some(delta) = delta {
+                    write!(f, " {delta}")?;
+                }
+                Ok(())
+            }
+            Command::Place(name) => write!(f, "place {name}"),
+            Command::Cycle => f.write_str("cycle"),
+            Command::Screen(selector) => write!(f, "screen {selector}"),
+            Command::Operation(operation) => write!(f, "{operation}"),
+        }
+    }
+}
+
+/// `;` で区切ったコマンドの並び
+///
+/// 例: `move left 50; resize right +10%; place left_half; screen next`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Script(pub Vec<Command>);
+
+impl Script {
+    /// コマンドを順に実行し、失敗したところで止める。結果はコマンドの順に返す
+    pub fn execute(
+        &self,
+        backend: &dyn WindowBackend,
+        window: WindowId,
+        preferences: &Preferences,
+        binding: Option<&Binding>,
+    ) -> anyhow::Result<Vec<OperationOutcome>> {
+        self.execute_times(backend, window, preferences, binding, 1)
+    }
+
+    /// 各コマンドの量を `count` 倍して順に実行する（`Command::execute_times` を参照）
+    pub fn execute_times(
+        &self,
+        backend: &dyn WindowBackend,
+        window: WindowId,
+        preferences: &Preferences,
+        binding: Option<&Binding>,
+        count: u32,
+    ) -> anyhow::Result<Vec<OperationOutcome>> {
+        self.0
+            .iter()
+            .map(|command| command.execute_times(backend, window, preferences, binding, count))
+            .collect()
+    }
+
+    /// すべてのコマンドの量を足し合わせられるか（`Command::accumulates` を参照）
+    pub fn accumulates(&self) -> bool {
+        !self.0.is_empty() && self.0.iter().all(Command::accumulates)
+    }
+}
+
+impl From<Operation> for Script {
+    fn from(operation: Operation) -> Self {
+        Script(vec![Command::Operation(operation)])
+    }
+}
+
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, command) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{command}")?;
+        }
+        Ok(())
+    }
+}
+
+/// コマンドが解釈できなかったときのエラー
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// 問題のある範囲（バイト単位）
+    pub span: Range<usize>,
+    /// 範囲の始まりの 1 始まりの列番号（文字単位）
+    pub column: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(span: Range<usize>, message: impl Into<String>) -> Self {
+        Self {
+            span,
+            column: 0,
+            message: message.into(),
+        }
+    }
+
+    /// 解析した `source` から列番号を求める
+    fn located(mut self, source: &str) -> Self {
+        self.column = source[..self.span.start.min(source.len())].chars().count() + 1;
+        self
+    }
+
+    /// `source` の問題のある範囲に `^` で印をつけた 2 行
+    pub fn annotate(&self, source: &str) -> String {
+        let start = source[..self.span.start.min(source.len())].chars().count();
+        let width = source
+            .get(self.span.clone())
+            .map_or(0, |text| text.chars().count())
+            .max(1);
+        format!("{source}\n{}{}", " ".repeat(start), "^".repeat(width))
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.column)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl FromStr for Script {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_script(s).map_err(|err| err.located(s))
+    }
+}
+
+impl FromStr for Command {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut script: Script = s.parse()?;
+        match script.0.len() {
+            1 => Ok(script.0.remove(0)),
+            _ => Err(ParseError::new(0..s.len(), "expected a single command").located(s)),
+        }
+    }
+}
+
+impl Serialize for Script {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Script {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+fn parse_script(source: &str) -> Result<Script, ParseError> {
+    let statements = tokenize(source);
+    let last = statements.len() - 1;
+    let mut commands = Vec::new();
+
+    for (i, (tokens, end)) in statements.iter().enumerate() {
+        if tokens.is_empty() {
+            // 末尾の `;` は許す
+            if i == last && !commands.is_empty() {
+                break;
+            }
+            return Err(ParseError::new(end.clone(), "expected a command"));
+        }
+        commands.push(parse_command(tokens)?);
+    }
+
+    Ok(Script(commands))
+}
+
+struct Token<'a> {
+    text: &'a str,
+    span: Range<usize>,
+}
+
+/// `;` ごとに、空白で区切った字句と、区切り（または末尾）の位置に分ける
+fn tokenize(source: &str) -> Vec<(Vec<Token<'_>>, Range<usize>)> {
+    let mut statements = Vec::new();
+    let mut tokens = Vec::new();
+    let mut start: Option<usize> = None;
+
+    for (i, c) in source.char_indices() {
+        if c.is_whitespace() || c == ';' {
+            if let Some(begin) = start.take() {
+                tokens.push(Token {
+                    text: &source[begin..i],
+                    span: begin..i,
+                });
+            }
+            if c == ';' {
+                statements.push((std::mem::take(&mut tokens), i..i + 1));
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(begin) = start {
+        tokens.push(Token {
+            text: &source[begin..],
+            span: begin..source.len(),
+        });
+    }
+    statements.push((tokens, source.len()..source.len()));
+    statements
+}
+
+fn parse_command(tokens: &[Token<'_>]) -> Result<Command, ParseError> {
+    let mut args = Args {
+        tokens: &tokens[1..],
+        end: tokens[tokens.len() - 1].span.end,
+    };
+    let head = &tokens[0];
+
+    let command = match head.text.to_ascii_lowercase().as_str() {
+        "move" => Command::Move {
+            direction: args.required("a direction (left, right, up, down)", str::parse)?,
+            step: args.optional(str::parse)?,
+        },
+        "resize" => Command::Resize {
+            edge: args.required("an edge (left, right, top, bottom)", str::parse)?,
+            delta: args.optional(|text| text.strip_prefix('+').unwrap_or(text).parse())?,
+        },
+        "place" => Command::Place(
+            args.required("a preset name", |text| Ok::<_, String>(text.to_string()))?,
+        ),
+        "cycle" => Command::Cycle,
+        "screen" => Command::Screen(args.required(
+            "a screen (current, main, next, previous or a number)",
+            str::parse,
+        )?),
+        _ => match head.text.parse::<Operation>() {
+            Ok(operation) => Command::Operation(operation),
+            Err(_) => {
+                let candidates = KEYWORDS
+                    .iter()
+                    .copied()
+                    .chain(REGISTRY.iter().map(|info| info.id));
+                let mut message = format!("unknown command `{}`", head.text);
+                if let Some(suggestion) = suggest(head.text, candidates) {
+                    message.push_str(&format!(" (did you mean `{suggestion}`?)"));
+                }
+                return Err(ParseError::new(head.span.clone(), message));
+            }
+        },
+    };
+
+    args.finish()?;
+    Ok(command)
+}
+
+/// コマンド名に続く引数
+struct Args<'t, 'a> {
+    tokens: &'t [Token<'a>],
+    /// コマンドの末尾の位置。引数が足りないときのエラー位置に使う
+    end: usize,
+}
+
+impl Args<'_, '_> {
+    fn required<T>(
+        &mut self,
+        expected: &str,
+        parse: impl Fn(&str) -> Result<T, String>,
+    ) -> Result<T, ParseError> {
+        match self.optional(parse)? {
+            Some(value) => Ok(value),
+            None => Err(ParseError::new(
+                self.end..self.end,
+                format!("expected {expected}"),
+            )),
+        }
+    }
+
+    fn optional<T>(
+        &mut self,
+        parse: impl Fn(&str) -> Result<T, String>,
+    ) -> Result<Option<T>, ParseError> {
+        let Some((token, rest)) = self.tokens.split_first() else {
+            return Ok(None);
+        };
+        self.tokens = rest;
+        parse(token.text)
+            .map(Some)
+            .map_err(|message| ParseError::new(token.span.clone(), message))
+    }
+
+    fn finish(&self) -> Result<(), ParseError> {
+        match self.tokens.first() {
+            Some(token) => Err(ParseError::new(
+                token.span.clone(),
+                format!("unexpected `{}`", token.text),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<Vec<String>, ParseError> {
+        let script: Script = source.parse()?;
+        Ok(script.0.iter().map(ToString::to_string).collect())
+    }
+
+    #[test]
+    fn splits_on_separators() {
+        let cases: &[(&str, &[&str])] = &[
+            ("move left", &["move left"]),
+            (
+                "move left 50; resize right +10%",
+                &["move left 50pt", "resize right 10%"],
+            ),
+            (
+                "  move left;resize top -5  ",
+                &["move left", "resize top -5pt"],
+            ),
+            ("cycle;", &["cycle"]),
+            (
+                "place left_half; screen next",
+                &["place left_half", "screen next"],
+            ),
+            ("MOVE up 1/12", &["move up 1/12"]),
+            ("move_left; repeat_last", &["move_left", "repeat_last"]),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(
+                parse(source),
+                Ok(expected.iter().map(|s| s.to_string()).collect()),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn reports_errors_with_their_position() {
+        // (入力, 範囲, 列, メッセージの始まり)
+        let cases = [
+            ("", 0..0, 1, "expected a command"),
+            (";", 0..1, 1, "expected a command"),
+            ("cycle;; cycle", 6..7, 7, "expected a command"),
+            ("move", 4..4, 5, "expected a direction"),
+            ("move sideways", 5..13, 6, "invalid dir"),
+            ("resize left +10 extra", 16..21, 17, "unexpected `extra`"),
+            (
+                "mvoe left",
+                0..4,
+                1,
+                "unknown command `mvoe` (did you mean `move`?)",
+            ),
+            ("cycle; place", 12..12, 13, "expected a preset name"),
+        ];
+        for (source, span, column, message) in cases {
+            let err = source.parse::<Script>().unwrap_err();
+            assert_eq!(err.span, span, "{source}");
+            assert_eq!(err.column, column, "{source}");
+            assert!(
+                err.message.starts_with(message),
+                "{source}: {}",
+                err.message
+            );
+        }
+    }
+
+    #[test]
+    fn counts_columns_in_characters() {
+        let source = "place 左半分; 移動 left";
+        let err = source.parse::<Script>().unwrap_err();
+        assert_eq!(&source[err.span.clone()], "移動");
+        assert_eq!(err.column, 12);
+        assert!(err.to_string().ends_with("at column 12"));
+        assert_eq!(
+            err.annotate(source),
+            format!("{source}\n{}^^", " ".repeat(11))
+        );
+    }
+
+    #[test]
+    fn repeat_last_is_not_accumulated() {
+        let script: Script = "repeat_last".parse().unwrap();
+        assert!(script.0.iter().all(Command::is_repeat_last));
+        assert!(!script.accumulates());
+
+        let cases = [
+            ("move left", true),
+            ("move left 10; resize right +5%", true),
+            ("move_left; resize_right_to_right", true),
+            ("move left; place left_half", false),
+            ("screen next", false),
+            ("move_to_screen:next", false),
+            ("move left; repeat_last", false),
+        ];
+        for (source, expected) in cases {
+            let script: Script = source.parse().unwrap();
+            assert_eq!(script.accumulates(), expected, "{source}");
+        }
+    }
+
+    #[test]
+    fn single_command() {
+        assert_eq!(
+            "move left 5".parse::<Command>(),
+            Ok(Command::Move {
+                direction: Direction::Left,
+                step: Some(Step::Points(5.0)),
+            })
+        );
+        let err = "cycle; cycle".parse::<Command>().unwrap_err();
+        assert_eq!((err.span, err.column), (0..12, 1));
+    }
+}
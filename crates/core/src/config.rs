@@ -0,0 +1,443 @@
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+use serde::de::DeserializeOwned;
+
+use crate::{layer::PreferencesLayer, preferences::Preferences};
+
+/// ユーザー設定のファイル名
+///
+/// 複数見つかった場合は先にあるものだけを読み込む
+pub const SETTINGS_FILE_NAMES: &[&str] = &["settings.json", "settings.jsonc", "settings.toml"];
+
+/// 設定ファイルを読み込めなかったときのエラー
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub path: PathBuf,
+    /// 1 始まりの行番号。位置を特定できない場合は `None`
+    pub line: Option<usize>,
+    /// 1 始まりの列番号。位置を特定できない場合は `None`
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl ConfigError {
+    fn io(path: &Path, err: &io::Error) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            line: None,
+            column: None,
+            message: err.to_string(),
+        }
+    }
+
+    fn merge(path: &Path, message: String) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            line: None,
+            column: None,
+            message,
+        }
+    }
+
+    fn toml(path: &Path, text: &str, err: &toml::de::Error) -> Self {
+        let position = err.span().map(|span| line_and_column(text, span.start));
+        Self {
+            path: path.to_path_buf(),
+            line: position.map(|(line, _)| line),
+            column: position.map(|(_, column)| column),
+            message: err.message().to_string(),
+        }
+    }
+
+    fn json(path: &Path, err: &serde_json::Error) -> Self {
+        // serde_json は位置が分からないとき 0 を返す
+        let position = (err.line() > 0).then(|| (err.line(), err.column()));
+        Self {
+            path: path.to_path_buf(),
+            line: position.map(|(line, _)| line),
+            column: position.map(|(_, column)| column),
+            message: strip_position(&err.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{line}")?;
+            if let Some(column) = self.column {
+                write!(f, ":{column}")?;
+            }
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// serde_json のメッセージ末尾の " at line X column Y" を取り除く
+fn strip_position(message: &str) -> String {
+    match message.rfind(" at line ") {
+        Some(index) => message[..index].to_string(),
+        None => message.to_string(),
+    }
+}
+
+/// バイト位置 `offset` の 1 始まりの行番号と列番号
+fn line_and_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rfind('\n')
+        .map_or(before.len(), |i| before.len() - i - 1)
+        + 1;
+    (line, column)
+}
+
+/// 設定ファイルを指定する環境変数
+pub const CONFIG_ENV: &str = "RESIZE_KEY_CONFIG";
+
+/// プロファイルを指定する環境変数
+pub const PROFILE_ENV: &str = "RESIZE_KEY_PROFILE";
+
+/// 設定の読み込み方
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// `--config` で指定されたファイルまたはディレクトリ
+    pub config: Option<PathBuf>,
+    /// `--profile` で指定されたプロファイル名。省略時は `$RESIZE_KEY_PROFILE`
+    pub profile: Option<String>,
+}
+
+impl LoadOptions {
+    /// 使うプロファイル名
+    pub fn profile(&self) -> Option<String> {
+        self.profile.clone().or_else(|| {
+            std::env::var(PROFILE_ENV)
+                .ok()
+                .filter(|profile| !profile.is_empty())
+        })
+    }
+}
+
+/// 設定ファイルがどこで見つかったか
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigSource {
+    /// `--config` で指定された
+    Flag,
+    /// `$RESIZE_KEY_CONFIG` で指定された
+    Env,
+    /// `$XDG_CONFIG_HOME/resize-key/`
+    XdgConfigHome,
+    /// `~/.config/resize-key/`
+    HomeConfig,
+    /// `~/Library/Application Support/ResizeKey/`
+    ApplicationSupport,
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let source = match self {
+            ConfigSource::Flag => "--config",
+            ConfigSource::Env => "$RESIZE_KEY_CONFIG",
+            ConfigSource::XdgConfigHome => "$XDG_CONFIG_HOME/resize-key",
+            ConfigSource::HomeConfig => "~/.config/resize-key",
+            ConfigSource::ApplicationSupport => "~/Library/Application Support/ResizeKey",
+        };
+        f.write_str(source)
+    }
+}
+
+/// 読み込むユーザー設定ファイル
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedConfig {
+    pub path: PathBuf,
+    pub source: ConfigSource,
+    /// 見つかったが、優先度が低いため読み込まれないファイル
+    pub ignored: Vec<PathBuf>,
+}
+
+/// 設定ファイルを探すディレクトリを優先順に並べたもの
+pub fn search_dirs() -> Vec<(ConfigSource, PathBuf)> {
+    let home = env_path("HOME");
+    let candidates = [
+        (
+            ConfigSource::XdgConfigHome,
+            env_path("XDG_CONFIG_HOME").map(|dir| dir.join("resize-key")),
+        ),
+        (
+            ConfigSource::HomeConfig,
+            home.as_ref().map(|home| home.join(".config/resize-key")),
+        ),
+        (
+            ConfigSource::ApplicationSupport,
+            home.as_ref()
+                .map(|home| home.join("Library/Application Support/ResizeKey")),
+        ),
+    ];
+
+    let mut dirs: Vec<(ConfigSource, PathBuf)> = Vec::new();
+    for (source, dir) in candidates {
+        // `$XDG_CONFIG_HOME` が `~/.config` の場合などに同じディレクトリを二度見ない
+        if let Some(dir) = dir
+            && !dirs.iter().any(|(_, seen)| *seen == dir)
+        {
+            dirs.push((source, dir));
+        }
+    }
+    dirs
+}
+
+/// 読み込むユーザー設定ファイルを決める
+///
+/// `explicit`（`--config`）、`$RESIZE_KEY_CONFIG`、`search_dirs` の順に探す。
+/// 明示されたパスがディレクトリならその中から探し、ファイルがなくてもそのパスを返す
+pub fn resolve(explicit: Option<&Path>) -> Option<ResolvedConfig> {
+    if let Some(path) = explicit {
+        return Some(resolve_explicit(path, ConfigSource::Flag));
+    }
+    if let Some(path) = env_path(CONFIG_ENV) {
+        return Some(resolve_explicit(&path, ConfigSource::Env));
+    }
+
+    let mut found = search_dirs().into_iter().flat_map(|(source, dir)| {
+        SETTINGS_FILE_NAMES
+            .iter()
+            .map(move |name| (source, dir.join(name)))
+            .filter(|(_, path)| path.is_file())
+    });
+    let (source, path) = found.next()?;
+    Some(ResolvedConfig {
+        path,
+        source,
+        ignored: found.map(|(_, path)| path).collect(),
+    })
+}
+
+fn resolve_explicit(path: &Path, source: ConfigSource) -> ResolvedConfig {
+    if !path.is_dir() {
+        return ResolvedConfig {
+            path: path.to_path_buf(),
+            source,
+            ignored: Vec::new(),
+        };
+    }
+
+    let mut found = SETTINGS_FILE_NAMES
+        .iter()
+        .map(|name| path.join(name))
+        .filter(|path| path.is_file());
+    ResolvedConfig {
+        path: found
+            .next()
+            .unwrap_or_else(|| path.join(SETTINGS_FILE_NAMES[0])),
+        source,
+        ignored: found.collect(),
+    }
+}
+
+/// 空でない環境変数をパスとして読む
+fn env_path(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+/// プロファイル `profile` の設定ファイル
+///
+/// ユーザー設定と同じディレクトリの `profiles/<profile>.json` などを探す。
+/// 見つからなければ `profiles/<profile>.json` を返す
+pub fn profile_path(user: Option<&ResolvedConfig>, profile: &str) -> Option<PathBuf> {
+    let dir = match user {
+        Some(resolved) => resolved.path.parent()?.to_path_buf(),
+        None => search_dirs().into_iter().next()?.1,
+    }
+    .join("profiles");
+
+    let candidates: Vec<PathBuf> = SETTINGS_FILE_NAMES
+        .iter()
+        .filter_map(|name| Path::new(name).extension())
+        .map(|extension| dir.join(profile).with_extension(extension))
+        .collect();
+    candidates
+        .iter()
+        .find(|path| path.is_file())
+        .or(candidates.first())
+        .cloned()
+}
+
+/// 既定値の上に重ねる設定ファイルを、優先度の低い順に並べたもの
+pub fn layer_paths(options: &LoadOptions) -> Vec<PathBuf> {
+    let user = resolve(options.config.as_deref());
+    let profile = options
+        .profile()
+        .and_then(|profile| profile_path(user.as_ref(), &profile));
+    user.map(|resolved| resolved.path)
+        .into_iter()
+        .chain(profile)
+        .collect()
+}
+
+/// 同梱の既定値にユーザー設定とプロファイルを重ねた設定を読み込む
+pub fn load(options: &LoadOptions) -> Result<Preferences, ConfigError> {
+    let mut preferences = Preferences::default();
+    for path in layer_paths(options) {
+        load_layer(&path)?
+            .apply_to(&mut preferences)
+            .map_err(|message| ConfigError::merge(&path, message))?;
+    }
+    Ok(preferences)
+}
+
+/// `path` の設定ファイルを 1 層分として読み込む
+///
+/// 拡張子が `.toml` なら TOML、それ以外は JSONC として解釈する
+pub fn load_layer(path: &Path) -> Result<PreferencesLayer, ConfigError> {
+    let text = std::fs::read_to_string(path).map_err(|err| ConfigError::io(path, &err))?;
+
+    if path.extension().is_some_and(|ext| ext == "toml") {
+        toml::from_str(&text).map_err(|err| ConfigError::toml(path, &text, &err))
+    } else {
+        from_jsonc(&text).map_err(|err| ConfigError::json(path, &err))
+    }
+}
+
+/// コメントと末尾のカンマを許す JSON (JSONC) を読み込む
+pub fn from_jsonc<T: DeserializeOwned>(text: &str) -> Result<T, serde_json::Error> {
+    serde_json::from_str(&strip_jsonc(text))
+}
+
+/// コメントと末尾のカンマを空白に置き換える
+///
+/// エラー位置がずれないよう、改行とバイト数はそのまま残す
+fn strip_jsonc(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    // 直前のカンマの `out` 内での位置。値が続かなければ空白に置き換える
+    let mut pending_comma: Option<usize> = None;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                pending_comma = None;
+                out.push(c);
+                while let Some(c) = chars.next() {
+                    out.push(c);
+                    match c {
+                        '\\' => {
+                            if let Some(escaped) = chars.next() {
+                                out.push(escaped);
+                            }
+                        }
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                out.push(' ');
+                while let Some(&c) = chars.peek() {
+                    if c == '\n' {
+                        break;
+                    }
+                    chars.next();
+                    blank(&mut out, c);
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                out.push(' ');
+                out.push(' ');
+                chars.next();
+                let mut previous = '\0';
+                for c in chars.by_ref() {
+                    blank(&mut out, c);
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            ',' => {
+                pending_comma = Some(out.len());
+                out.push(c);
+            }
+            ']' | '}' => {
+                if let Some(index) = pending_comma.take() {
+                    out.replace_range(index..=index, " ");
+                }
+                out.push(c);
+            }
+            c if c.is_whitespace() => out.push(c),
+            c => {
+                pending_comma = None;
+                out.push(c);
+            }
+        }
+    }
+
+    out
+}
+
+/// 改行はそのまま、それ以外は同じバイト数の空白を書き出す
+fn blank(out: &mut String, c: char) {
+    if c == '\n' {
+        out.push('\n');
+    } else {
+        out.extend(std::iter::repeat_n(' ', c.len_utf8()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_comments_and_trailing_commas() {
+        let cases = [
+            ("{\"a\": 1} // note", "{\"a\": 1}        "),
+            ("{\"a\": /* x */ 1}", "{\"a\":         1}"),
+            ("[1, 2,]", "[1, 2 ]"),
+            ("{\"a\": 1,\n}", "{\"a\": 1 \n}"),
+            ("[1, /* c */ ]", "[1          ]"),
+            ("{\"a\": [1,], }", "{\"a\": [1 ]  }"),
+            ("// a\n{}", "    \n{}"),
+            ("/* a\nb */{}", "    \n    {}"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(strip_jsonc(input), expected, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn keeps_strings_intact() {
+        let cases = [
+            r#"{"url": "https://example.com"}"#,
+            r#"{"a": "/* not a comment */"}"#,
+            r#"{"a": "x,]"}"#,
+            r#"{"a": "quote \" // still a string"}"#,
+            r#"{"a": "back\\", "b": 1}"#,
+        ];
+        for input in cases {
+            assert_eq!(strip_jsonc(input), input);
+        }
+    }
+
+    #[test]
+    fn unterminated_block_comment_runs_to_the_end() {
+        assert_eq!(strip_jsonc("{} /* open\n{"), "{}        \n ");
+    }
+
+    #[test]
+    fn keeps_byte_offsets() {
+        let input = "{\n  // 日本語のコメント\n  \"a\": 1, /* ｘ */\n}";
+        let stripped = strip_jsonc(input);
+        assert_eq!(stripped.len(), input.len());
+        assert_eq!(stripped.lines().count(), input.lines().count());
+        let value: serde_json::Value = serde_json::from_str(&stripped).unwrap();
+        assert_eq!(value["a"], 1);
+    }
+}
@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+use crate::frame::Frame;
+
+/// 操作後のフレームに適用する制約
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Constraint {
+    /// 制約なし（画面外へのはみ出しも許す）
+    #[default]
+    None,
+    /// 画面の可視領域内に収める
+    Screen,
+}
+
+impl Constraint {
+    pub fn apply(self, frame: Frame, visible: &Frame) -> Frame {
+        match self {
+            Constraint::None => frame,
+            Constraint::Screen => {
+                let w = frame.w.min(visible.w);
+                let h = frame.h.min(visible.h);
+                Frame::new(
+                    frame.x.clamp(visible.x, visible.x + visible.w - w),
+                    frame.y.clamp(visible.y, visible.y + visible.h - h),
+                    w,
+                    h,
+                )
+            }
+        }
+    }
+}
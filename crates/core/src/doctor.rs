@@ -0,0 +1,503 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::{
+    config::{self, LoadOptions},
+    ipc::{Client, Method, Reply},
+    preferences::Preferences,
+    validation::{validate, validate_modal, validate_rules},
+    window::{self, WindowBackend},
+};
+
+/// 読み直したフレームが書いたフレームと同じとみなす差（ポイント）
+const FRAME_TOLERANCE: f64 = 1.0;
+
+/// 診断の結果の分類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Pass,
+    /// 動くが注意が必要
+    Warn,
+    Fail,
+    /// 前提が満たされず診断できなかった
+    Skip,
+}
+
+/// 1 つの診断の結果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnosis {
+    pub status: Status,
+    pub detail: String,
+    /// 直し方
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl Diagnosis {
+    pub fn pass(detail: impl Into<String>) -> Self {
+        Self::new(Status::Pass, detail)
+    }
+
+    pub fn warn(detail: impl Into<String>) -> Self {
+        Self::new(Status::Warn, detail)
+    }
+
+    pub fn fail(detail: impl Into<String>) -> Self {
+        Self::new(Status::Fail, detail)
+    }
+
+    pub fn skip(detail: impl Into<String>) -> Self {
+        Self::new(Status::Skip, detail)
+    }
+
+    pub fn hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    fn new(status: Status, detail: impl Into<String>) -> Self {
+        Self {
+            status,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+}
+
+/// 診断の 1 項目
+///
+/// バックエンドは `WindowBackend::checks` で自分の診断を加えられる
+pub trait Check {
+    /// 結果の見出し
+    fn name(&self) -> &str;
+    fn run(&self) -> Diagnosis;
+}
+
+/// 名前を付けた診断の結果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub name: String,
+    #[serde(flatten)]
+    pub diagnosis: Diagnosis,
+}
+
+/// すべての診断の結果
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Report {
+    pub findings: Vec<Finding>,
+}
+
+impl Report {
+    /// `checks` を順に行う
+    pub fn run(checks: &[Box<dyn Check + '_>]) -> Self {
+        Self {
+            findings: checks
+                .iter()
+                .map(|check| Finding {
+                    name: check.name().to_string(),
+                    diagnosis: check.run(),
+                })
+                .collect(),
+        }
+    }
+
+    /// 失敗した診断の数
+    pub fn failures(&self) -> usize {
+        self.findings
+            .iter()
+            .filter(|finding| finding.diagnosis.status == Status::Fail)
+            .count()
+    }
+}
+
+/// このプロセスに Accessibility の許可があるか。許可ダイアログは出さない
+pub struct AccessibilityCheck;
+
+impl Check for AccessibilityCheck {
+    fn name(&self) -> &str {
+        "Accessibility"
+    }
+
+    fn run(&self) -> Diagnosis {
+        if window::is_trusted() {
+            Diagnosis::pass("This process is trusted")
+        } else {
+            Diagnosis::fail("This process is not trusted").hint(
+                "Allow it in System Settings > Privacy & Security > Accessibility, \
+                 or start ResizeKey so the CLI can use its permission",
+            )
+        }
+    }
+}
+
+/// 設定ファイルが見つかり、読み込めるか
+pub struct ConfigCheck {
+    options: LoadOptions,
+}
+
+impl ConfigCheck {
+    pub fn new(options: LoadOptions) -> Self {
+        Self { options }
+    }
+}
+
+impl Check for ConfigCheck {
+    fn name(&self) -> &str {
+        "Settings"
+    }
+
+    fn run(&self) -> Diagnosis {
+        let resolved = config::resolve(self.options.config.as_deref());
+        if let Err(err) = Preferences::load(&self.options) {
+            return Diagnosis::fail(err.to_string())
+                .hint("Fix the settings file; `resize config check` shows the details");
+        }
+
+        let Some(resolved) = resolved else {
+            return Diagnosis::pass("No user settings, using the built-in defaults");
+        };
+        let detail = format!("{} (from {})", resolved.path.display(), resolved.source);
+        if !resolved.path.is_file() {
+            return Diagnosis::warn(format!("{detail} does not exist"))
+                .hint("Create it, or check --config and $RESIZE_KEY_CONFIG");
+        }
+        match resolved.ignored.as_slice() {
+            [] => Diagnosis::pass(detail),
+            ignored => Diagnosis::warn(format!(
+                "{detail}; ignored {}",
+                ignored
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+            .hint("Merge the ignored files into the loaded one and remove them"),
+        }
+    }
+}
+
+/// バインディングに重複したキーや、他の並びと衝突するキーの並びがないか
+pub struct HotkeyCheck {
+    options: LoadOptions,
+}
+
+impl HotkeyCheck {
+    pub fn new(options: LoadOptions) -> Self {
+        Self { options }
+    }
+}
+
+impl Check for HotkeyCheck {
+    fn name(&self) -> &str {
+        "Hotkeys"
+    }
+
+    fn run(&self) -> Diagnosis {
+        let Ok(preferences) = Preferences::load(&self.options) else {
+            return Diagnosis::skip("Settings could not be loaded");
+        };
+
+        let (bindings, mut report) = validate(&preferences);
+        let count = bindings.len();
+        report.issues.extend(validate_rules(&preferences).1.issues);
+        report.issues.extend(validate_modal(&preferences).1.issues);
+        if report.is_empty() {
+            return Diagnosis::pass(format!("{count} binding(s), no conflicts"));
+        }
+
+        let issues: Vec<String> = report.issues.iter().map(ToString::to_string).collect();
+        Diagnosis::fail(issues.join("\n"))
+            .hint("Give each binding its own key, and don't start a key sequence with another binding's key")
+    }
+}
+
+/// フォーカスされているウィンドウのフレームを読み、同じフレームを書き戻せるか
+pub struct WindowAccessCheck<'a> {
+    backend: &'a dyn WindowBackend,
+}
+
+impl<'a> WindowAccessCheck<'a> {
+    pub fn new(backend: &'a dyn WindowBackend) -> Self {
+        Self { backend }
+    }
+}
+
+impl Check for WindowAccessCheck<'_> {
+    fn name(&self) -> &str {
+        "Focused window"
+    }
+
+    fn run(&self) -> Diagnosis {
+        let window = match self.backend.focused() {
+            Ok(window) => window,
+            Err(err) => {
+                return Diagnosis::fail(format!("{err:#}")).hint(
+                    "Focus a normal window and run again; check the Accessibility permission",
+                );
+            }
+        };
+        let frame = match self.backend.frame(window) {
+            Ok(frame) => frame,
+            Err(err) => {
+                return Diagnosis::fail(format!("Cannot read window {window}: {err:#}"))
+                    .hint("Check the Accessibility permission");
+            }
+        };
+        if let Err(err) = self.backend.set_frame(window, frame) {
+            return Diagnosis::fail(format!("Cannot move window {window}: {err:#}"))
+                .hint("Some apps refuse to be moved; try another window");
+        }
+
+        match self.backend.frame(window) {
+            Ok(after) if after.approx_eq(&frame, FRAME_TOLERANCE) => {
+                Diagnosis::pass(format!("Read and wrote window {window} at {frame}"))
+            }
+            Ok(after) => Diagnosis::warn(format!(
+                "Window {window} moved from {frame} to {after} when written back"
+            ))
+            .hint("The app limits its position or size"),
+            Err(err) => Diagnosis::fail(format!("Cannot read window {window} back: {err:#}")),
+        }
+    }
+}
+
+/// 起動している ResizeKey のソケットが応答するか
+pub struct AppCheck {
+    path: PathBuf,
+}
+
+impl AppCheck {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl Check for AppCheck {
+    fn name(&self) -> &str {
+        "ResizeKey app"
+    }
+
+    fn run(&self) -> Diagnosis {
+        let mut client = match Client::connect(&self.path) {
+            Ok(client) => client,
+            Err(_) => {
+                return Diagnosis::warn(format!(
+                    "Not running (no socket at {})",
+                    self.path.display()
+                ))
+                .hint("Start ResizeKey to use hotkeys; the CLI also works without it");
+            }
+        };
+        match client.request(Method::ListBindings) {
+            Ok(Reply::Bindings { bindings }) => Diagnosis::pass(format!(
+                "Responding at {} with {} binding(s)",
+                self.path.display(),
+                bindings.len()
+            )),
+            Ok(reply) => Diagnosis::fail(format!("Unexpected response: {reply:?}"))
+                .hint("Update the CLI and ResizeKey to the same version"),
+            Err(err) => Diagnosis::fail(format!("{err:#}")).hint("Quit and restart ResizeKey"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process, sync::mpsc::Sender};
+
+    use super::*;
+    use crate::{
+        frame::Frame,
+        mock_backend::MockBackend,
+        screen::Screen,
+        window::{Subscription, WindowEvent, WindowId, WindowInfo},
+    };
+
+    struct Fixed(&'static str, Status);
+
+    impl Check for Fixed {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn run(&self) -> Diagnosis {
+            Diagnosis::new(self.1, "fixed")
+        }
+    }
+
+    /// 書いたフレームの幅を 500 までに抑えるアプリのようにふるまう
+    struct Clamping(MockBackend);
+
+    impl WindowBackend for Clamping {
+        fn screens(&self) -> anyhow::Result<Vec<Screen>> {
+            self.0.screens()
+        }
+
+        fn frame(&self, window: WindowId) -> anyhow::Result<Frame> {
+            self.0.frame(window)
+        }
+
+        fn set_frame(&self, window: WindowId, frame: Frame) -> anyhow::Result<()> {
+            self.0.set_frame(
+                window,
+                Frame::new(frame.x, frame.y, frame.w.min(500.0), frame.h),
+            )
+        }
+
+        fn windows(&self) -> anyhow::Result<Vec<WindowInfo>> {
+            self.0.windows()
+        }
+
+        fn focused(&self) -> anyhow::Result<WindowId> {
+            self.0.focused()
+        }
+
+        fn subscribe(&self, events: Sender<WindowEvent>) -> anyhow::Result<Subscription> {
+            self.0.subscribe(events)
+        }
+    }
+
+    /// テスト用の設定ファイル。落とすと消す
+    struct SettingsFile(PathBuf);
+
+    impl SettingsFile {
+        fn new(name: &str, text: &str) -> Self {
+            let path = env::temp_dir().join(format!("rk-doctor-{}-{name}.jsonc", process::id()));
+            fs::write(&path, text).unwrap();
+            Self(path)
+        }
+
+        /// このファイルを読み込むオプション
+        fn options(&self) -> LoadOptions {
+            LoadOptions {
+                config: Some(self.0.clone()),
+                profile: None,
+            }
+        }
+    }
+
+    impl Drop for SettingsFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn runs_checks_in_order_and_counts_failures() {
+        let checks: Vec<Box<dyn Check>> = vec![
+            Box::new(Fixed("a", Status::Pass)),
+            Box::new(Fixed("b", Status::Fail)),
+            Box::new(Fixed("c", Status::Warn)),
+            Box::new(Fixed("d", Status::Skip)),
+            Box::new(Fixed("e", Status::Fail)),
+        ];
+        let report = Report::run(&checks);
+        let names: Vec<&str> = report.findings.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["a", "b", "c", "d", "e"]);
+        assert_eq!(report.failures(), 2);
+        assert_eq!(Report::default().failures(), 0);
+    }
+
+    #[test]
+    fn serializes_findings_flat() {
+        let finding = Finding {
+            name: "Settings".to_string(),
+            diagnosis: Diagnosis::warn("missing").hint("create it"),
+        };
+        assert_eq!(
+            serde_json::to_value(&finding).unwrap(),
+            serde_json::json!({
+                "name": "Settings",
+                "status": "warn",
+                "detail": "missing",
+                "hint": "create it",
+            })
+        );
+
+        let finding = Finding {
+            name: "Hotkeys".to_string(),
+            diagnosis: Diagnosis::pass("ok"),
+        };
+        assert_eq!(
+            serde_json::to_value(&finding).unwrap(),
+            serde_json::json!({"name": "Hotkeys", "status": "pass", "detail": "ok"})
+        );
+    }
+
+    #[test]
+    fn checks_window_access() {
+        let backend = MockBackend::one_screen();
+        let diagnosis = WindowAccessCheck::new(&backend).run();
+        assert_eq!(diagnosis.status, Status::Fail);
+        assert!(diagnosis.hint.is_some());
+
+        let window = backend.add_window(Default::default(), Frame::new(0.0, 25.0, 800.0, 600.0));
+        let diagnosis = WindowAccessCheck::new(&backend).run();
+        assert_eq!(diagnosis.status, Status::Pass, "{}", diagnosis.detail);
+        assert!(diagnosis.detail.contains(&format!("window {window}")));
+
+        let clamping = Clamping(backend);
+        let diagnosis = WindowAccessCheck::new(&clamping).run();
+        assert_eq!(diagnosis.status, Status::Warn, "{}", diagnosis.detail);
+    }
+
+    #[test]
+    fn runs_backend_checks() {
+        let report = Report::run(&MockBackend::new(Vec::new()).checks());
+        assert_eq!(report.findings[0].name, "Mock backend");
+        assert_eq!(report.failures(), 1);
+
+        let backend = MockBackend::one_screen();
+        backend.add_window(Default::default(), Frame::new(0.0, 25.0, 800.0, 600.0));
+        let report = Report::run(&backend.checks());
+        assert_eq!(report.failures(), 0);
+        assert_eq!(
+            report.findings[0].diagnosis.detail,
+            "1 screen(s), 1 window(s)"
+        );
+    }
+
+    #[test]
+    fn checks_settings_and_hotkeys() {
+        let valid = SettingsFile::new(
+            "valid",
+            r#"{"bindings": [{"operation": "move_left", "key": "alt+h"}]}"#,
+        );
+        assert_eq!(ConfigCheck::new(valid.options()).run().status, Status::Pass);
+        assert_eq!(HotkeyCheck::new(valid.options()).run().status, Status::Pass);
+
+        let conflicting = SettingsFile::new(
+            "conflicting",
+            r#"{"bindings": [
+                {"operation": "move_left", "key": "alt+h"},
+                {"operation": "move_right", "key": "alt+h"},
+            ]}"#,
+        );
+        assert_eq!(
+            ConfigCheck::new(conflicting.options()).run().status,
+            Status::Pass
+        );
+        let diagnosis = HotkeyCheck::new(conflicting.options()).run();
+        assert_eq!(diagnosis.status, Status::Fail);
+        assert!(diagnosis.detail.contains("alt+h"), "{}", diagnosis.detail);
+
+        let broken = SettingsFile::new("broken", r#"{"bindings": ["#);
+        assert_eq!(
+            ConfigCheck::new(broken.options()).run().status,
+            Status::Fail
+        );
+        assert_eq!(
+            HotkeyCheck::new(broken.options()).run().status,
+            Status::Skip
+        );
+    }
+
+    #[test]
+    fn warns_when_the_app_is_not_running() {
+        let path = env::temp_dir().join(format!("rk-doctor-{}-missing.sock", process::id()));
+        let diagnosis = AppCheck::new(path).run();
+        assert_eq!(diagnosis.status, Status::Warn);
+    }
+}
@@ -0,0 +1,595 @@
+use std::{
+    collections::VecDeque,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        Arc, Condvar, Mutex, MutexGuard,
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail};
+
+use crate::{
+    doctor::Check,
+    frame::Frame,
+    outcome::OperationOutcome,
+    screen::Screen,
+    window::{Subscription, WindowBackend, WindowEvent, WindowId, WindowInfo},
+};
+
+/// 1 つの仕事の実行結果。まとめられた仕事を待つすべての相手に送るので、エラーは文字列にする
+type TaskResult = Result<Vec<OperationOutcome>, String>;
+
+/// 実行する処理。引数はウィンドウの読み書きに使うバックエンドと、まとめられた回数（量の倍数）
+///
+/// バックエンドは `timeout` を過ぎると呼び出しを行わずにエラーを返すので、
+/// 時間切れになった処理はその後のウィンドウの読み書きで止まる
+pub type Task = Box<
+    dyn FnOnce(&dyn WindowBackend, u32) -> anyhow::Result<Vec<OperationOutcome>> + Send + 'static,
+>;
+
+/// `Executor` の設定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExecutorOptions {
+    /// 1 つの仕事を実行する時間の上限。過ぎたら失敗として結果を捨てる
+    pub timeout: Duration,
+    /// キューで待つ間にこれより古くなった仕事は実行せずに捨てる
+    pub max_age: Duration,
+}
+
+impl Default for ExecutorOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(2),
+            max_age: Duration::from_secs(1),
+        }
+    }
+}
+
+/// 実行の統計
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Metrics {
+    pub submitted: u64,
+    /// 前の仕事にまとめた数
+    pub coalesced: u64,
+    /// 古くなって捨てた数
+    pub dropped: u64,
+    pub timed_out: u64,
+    pub failed: u64,
+    pub completed: u64,
+    /// キューで待った時間（投入から実行の開始まで）
+    pub total_wait: Duration,
+    pub max_wait: Duration,
+    /// 実行にかかった時間。時間切れの仕事は含めない
+    pub total_run: Duration,
+    pub max_run: Duration,
+}
+
+impl Metrics {
+    /// 実行を始めた仕事の平均の待ち時間
+    pub fn mean_wait(&self) -> Duration {
+        mean(
+            self.total_wait,
+            self.completed + self.failed + self.timed_out,
+        )
+    }
+
+    /// 終わった仕事の平均の実行時間
+    pub fn mean_run(&self) -> Duration {
+        mean(self.total_run, self.completed + self.failed)
+    }
+}
+
+fn mean(total: Duration, count: u64) -> Duration {
+    match u32::try_from(count) {
+        Ok(0) | Err(_) => Duration::ZERO,
+        Ok(count) => total / count,
+    }
+}
+
+/// 投入した仕事の結果を待つ
+pub struct Pending(Receiver<TaskResult>);
+
+impl Pending {
+    /// 実行が終わるまで待つ。捨てられたり時間切れになったりした仕事はエラーになる
+    pub fn wait(self) -> anyhow::Result<Vec<OperationOutcome>> {
+        match self.0.recv() {
+            Ok(result) => result.map_err(|message| anyhow!(message)),
+            Err(_) => Err(anyhow!("The operation was dropped or timed out")),
+        }
+    }
+}
+
+/// キューで待っている仕事
+struct Job<K> {
+    /// 同じキーの仕事が続けて投入されたら 1 つにまとめる。`None` ならまとめない
+    key: Option<K>,
+    label: String,
+    count: u32,
+    /// 最初に投入された時刻。待ち時間はこれから測る
+    submitted: Instant,
+    /// 最後にまとめた仕事が投入された時刻。古くなったかはこれで決める
+    latest: Instant,
+    task: Task,
+    waiters: Vec<Sender<TaskResult>>,
+}
+
+struct Shared<K> {
+    queue: Mutex<Queue<K>>,
+    ready: Condvar,
+    metrics: Mutex<Metrics>,
+    options: ExecutorOptions,
+}
+
+struct Queue<K> {
+    jobs: VecDeque<Job<K>>,
+    closed: bool,
+}
+
+/// 操作を投入された順に 1 つのスレッドで実行する
+///
+/// 投入する側（ホットキーのスレッドなど）は実行を待たない。まだ始まっていない最後の仕事と
+/// 同じキーの仕事が投入されたら回数を足して 1 回の実行にまとめ、`max_age` より古くなった仕事は捨てる。
+/// 1 つの仕事が `timeout` を過ぎても終わらなければ失敗として待つ相手に知らせ、遅れて出た結果は捨てる。
+/// 時間切れの仕事が終わるのはさらに `timeout` まで待ち、その間は次の仕事を始めない。
+/// それでも終わらない仕事はスレッドごと見捨てる（期限を過ぎた仕事はバックエンドを呼べない）
+pub struct Executor<K> {
+    shared: Arc<Shared<K>>,
+}
+
+impl<K: PartialEq + Send + 'static> Executor<K> {
+    /// 仕事には `backend` を渡す
+    pub fn new(backend: Arc<dyn WindowBackend>, options: ExecutorOptions) -> Self {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                jobs: VecDeque::new(),
+                closed: false,
+            }),
+            ready: Condvar::new(),
+            metrics: Mutex::new(Metrics::default()),
+            options,
+        });
+        let supervisor = Arc::clone(&shared);
+        thread::spawn(move || supervise(&supervisor, backend));
+        Self { shared }
+    }
+
+    /// `task` をキューに入れる。`label` はログに使う
+    pub fn submit(
+        &self,
+        key: Option<K>,
+        label: impl Into<String>,
+        count: u32,
+        task: Task,
+    ) -> Pending {
+        let (sender, receiver) = mpsc::channel();
+        let mut queue = lock(&self.shared.queue);
+        self.shared.metrics().submitted += 1;
+
+        if let Some(last) = queue.jobs.back_mut()
+            && key.is_some()
+            && last.key == key
+        {
+            last.count = last.count.saturating_add(count);
+            last.latest = Instant::now();
+            last.waiters.push(sender);
+            self.shared.metrics().coalesced += 1;
+            log::debug!("Coalesced {} (x{})", last.label, last.count);
+            return Pending(receiver);
+        }
+
+        let now = Instant::now();
+        queue.jobs.push_back(Job {
+            key,
+            label: label.into(),
+            count,
+            submitted: now,
+            latest: now,
+            task,
+            waiters: vec![sender],
+        });
+        self.shared.ready.notify_one();
+        Pending(receiver)
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        *self.shared.metrics()
+    }
+}
+
+impl<K> Drop for Executor<K> {
+    fn drop(&mut self) {
+        lock(&self.shared.queue).closed = true;
+        self.shared.ready.notify_one();
+    }
+}
+
+impl<K> Shared<K> {
+    fn metrics(&self) -> MutexGuard<'_, Metrics> {
+        lock(&self.metrics)
+    }
+
+    /// 次の仕事を待って取り出す。閉じられたら `None`
+    fn next(&self) -> Option<Job<K>> {
+        let mut queue = lock(&self.queue);
+        loop {
+            if let Some(job) = queue.jobs.pop_front() {
+                return Some(job);
+            }
+            if queue.closed {
+                return None;
+            }
+            queue = self
+                .ready
+                .wait(queue)
+                .unwrap_or_else(|err| err.into_inner());
+        }
+    }
+}
+
+/// 実行するスレッドに渡す仕事。期限を過ぎたらバックエンドを呼ばせない。結果と実行時間を `done` に返す
+type Run = (
+    Task,
+    u32,
+    Instant,
+    Sender<(anyhow::Result<Vec<OperationOutcome>>, Duration)>,
+);
+
+/// キューから仕事を取り出し、実行するスレッドに渡して時間切れを見張る
+fn supervise<K>(shared: &Shared<K>, backend: Arc<dyn WindowBackend>) {
+    let mut worker = spawn_worker(Arc::clone(&backend));
+
+    while let Some(job) = shared.next() {
+        let waited = job.submitted.elapsed();
+        if job.latest.elapsed() > shared.options.max_age {
+            shared.metrics().dropped += 1;
+            log::warn!("Dropped {} after waiting {waited:?}", job.label);
+            continue;
+        }
+
+        let (done, result) = mpsc::channel();
+        let deadline = Instant::now() + shared.options.timeout;
+        if let Err(mpsc::SendError((task, count, deadline, done))) =
+            worker.send((job.task, job.count, deadline, done))
+        {
+            // 実行するスレッドは終わっている。同時に動くスレッドは 1 つのまま
+            worker = spawn_worker(Arc::clone(&backend));
+            let _ = worker.send((task, count, deadline, done));
+        }
+
+        let result = match result.recv_timeout(shared.options.timeout) {
+            Ok((result, ran)) => {
+                let mut metrics = shared.metrics();
+                record(&mut metrics, waited, Some(ran));
+                match &result {
+                    Ok(_) => metrics.completed += 1,
+                    Err(_) => metrics.failed += 1,
+                }
+                drop(metrics);
+                log::debug!(
+                    "{} x{}: waited {waited:?}, ran {ran:?}",
+                    job.label,
+                    job.count
+                );
+                result.map_err(|err| format!("{}: {err:#}", job.label))
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                {
+                    let mut metrics = shared.metrics();
+                    metrics.timed_out += 1;
+                    record(&mut metrics, waited, None);
+                }
+                let message = format!(
+                    "{} did not finish within {:?}",
+                    job.label, shared.options.timeout
+                );
+                log::error!("{message}");
+                for waiter in job.waiters {
+                    let _ = waiter.send(Err(message.clone()));
+                }
+                // 次の仕事と重ならないよう、止まった仕事が終わるのを待って結果を捨てる。
+                // バックエンドの呼び出しも `timeout` ほどで戻るはずなので、それより長くは待たず、
+                // 終わらなければ実行するスレッドごと見捨てる。待つ間に古くなった仕事は次の周で捨てる
+                match result.recv_timeout(shared.options.timeout) {
+                    Ok((_, ran)) => log::warn!(
+                        "{} finished after {ran:?}; the result was discarded",
+                        job.label
+                    ),
+                    Err(RecvTimeoutError::Timeout) => {
+                        log::error!("{} is still running; abandoning it", job.label);
+                        worker = spawn_worker(Arc::clone(&backend));
+                    }
+                    Err(RecvTimeoutError::Disconnected) => {}
+                }
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                let mut metrics = shared.metrics();
+                metrics.failed += 1;
+                record(&mut metrics, waited, None);
+                Err(format!("{} was not run", job.label))
+            }
+        };
+
+        if let Err(message) = &result {
+            log::error!("{message}");
+        }
+        for waiter in job.waiters {
+            let _ = waiter.send(result.clone());
+        }
+    }
+}
+
+fn spawn_worker(backend: Arc<dyn WindowBackend>) -> Sender<Run> {
+    let (runs, received) = mpsc::channel::<Run>();
+    thread::spawn(move || {
+        for (task, count, deadline, done) in received {
+            let started = Instant::now();
+            let bounded = Bounded {
+                backend: backend.as_ref(),
+                deadline,
+            };
+            // パニックしてもスレッドは残し、次の仕事を実行する
+            let result = panic::catch_unwind(AssertUnwindSafe(|| task(&bounded, count)))
+                .unwrap_or_else(|_| Err(anyhow!("panicked")));
+            let _ = done.send((result, started.elapsed()));
+        }
+    });
+    runs
+}
+
+/// 仕事に渡すバックエンド。期限を過ぎたら呼び出しを行わずにエラーを返す
+struct Bounded<'a> {
+    backend: &'a dyn WindowBackend,
+    deadline: Instant,
+}
+
+impl Bounded<'_> {
+    fn check(&self) -> anyhow::Result<()> {
+        if Instant::now() >= self.deadline {
+            bail!("Timed out");
+        }
+        Ok(())
+    }
+}
+
+impl WindowBackend for Bounded<'_> {
+    fn screens(&self) -> anyhow::Result<Vec<Screen>> {
+        self.check()?;
+        self.backend.screens()
+    }
+
+    fn frame(&self, window: WindowId) -> anyhow::Result<Frame> {
+        self.check()?;
+        self.backend.frame(window)
+    }
+
+    fn set_frame(&self, window: WindowId, frame: Frame) -> anyhow::Result<()> {
+        self.check()?;
+        self.backend.set_frame(window, frame)
+    }
+
+    fn windows(&self) -> anyhow::Result<Vec<WindowInfo>> {
+        self.check()?;
+        self.backend.windows()
+    }
+
+    fn focused(&self) -> anyhow::Result<WindowId> {
+        self.check()?;
+        self.backend.focused()
+    }
+
+    fn subscribe(&self, events: Sender<WindowEvent>) -> anyhow::Result<Subscription> {
+        self.backend.subscribe(events)
+    }
+
+    fn checks(&self) -> Vec<Box<dyn Check + '_>> {
+        self.backend.checks()
+    }
+}
+
+/// 待ち時間と、終わった仕事なら実行時間を `metrics` に加える
+fn record(metrics: &mut Metrics, waited: Duration, ran: Option<Duration>) {
+    metrics.total_wait += waited;
+    metrics.max_wait = metrics.max_wait.max(waited);
+    if let Some(ran) = ran {
+        metrics.total_run += ran;
+        metrics.max_run = metrics.max_run.max(ran);
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{command::Command, mock_backend::MockBackend, preferences::Preferences};
+
+    fn backend() -> (MockBackend, WindowId) {
+        let backend = MockBackend::one_screen();
+        let window = backend.add_window(Default::default(), Frame::new(0.0, 25.0, 400.0, 300.0));
+        (backend, window)
+    }
+
+    fn executor(backend: &MockBackend, timeout: u64, max_age: u64) -> Executor<u32> {
+        Executor::new(
+            Arc::new(backend.clone()),
+            ExecutorOptions {
+                timeout: Duration::from_millis(timeout),
+                max_age: Duration::from_millis(max_age),
+            },
+        )
+    }
+
+    /// `window` を `10 * 回数` だけ右へ動かす
+    fn move_right(window: WindowId) -> Task {
+        let command: Command = "move right 10".parse().unwrap();
+        Box::new(move |backend, count| {
+            let outcome =
+                command.execute_times(backend, window, &Preferences::default(), None, count)?;
+            Ok(vec![outcome])
+        })
+    }
+
+    /// `gate` に送るか落とすまで、実行するスレッドを止める。止まったら `started` に届く
+    fn blocked() -> (Receiver<()>, Sender<()>, Task) {
+        let (gate, opened) = mpsc::channel::<()>();
+        let (start, started) = mpsc::channel();
+        let task: Task = Box::new(move |_, _| {
+            let _ = start.send(());
+            let _ = opened.recv();
+            Ok(Vec::new())
+        });
+        (started, gate, task)
+    }
+
+    #[test]
+    fn runs_jobs_in_order() {
+        let (backend, window) = backend();
+        let executor = executor(&backend, 1000, 1000);
+        let pending: Vec<Pending> = (0..3)
+            .map(|_| executor.submit(None, "move", 1, move_right(window)))
+            .collect();
+        let xs: Vec<f64> = pending
+            .into_iter()
+            .map(|pending| pending.wait().unwrap()[0].after.x)
+            .collect();
+        assert_eq!(xs, [10.0, 20.0, 30.0]);
+
+        let metrics = executor.metrics();
+        assert_eq!((metrics.submitted, metrics.completed), (3, 3));
+        assert_eq!(
+            (metrics.coalesced, metrics.dropped, metrics.failed),
+            (0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn coalesces_consecutive_jobs_with_the_same_key() {
+        let (backend, window) = backend();
+        let executor = executor(&backend, 1000, 1000);
+        let (started, gate, task) = blocked();
+        executor.submit(None, "block", 1, task);
+        started.recv().unwrap();
+
+        let submitted = Instant::now();
+        let first: Vec<Pending> = (0..3)
+            .map(|_| executor.submit(Some(1), "move", 1, move_right(window)))
+            .collect();
+        let other = executor.submit(Some(2), "move", 2, move_right(window));
+        let last = executor.submit(Some(1), "move", 1, move_right(window));
+        drop(gate);
+
+        // まとめた 3 回は 1 回のフレーム更新になり、同じ結果を受け取る
+        for pending in first {
+            let outcomes = pending.wait().unwrap();
+            assert_eq!(outcomes[0].before.x, 0.0);
+            assert_eq!(outcomes[0].after.x, 30.0);
+        }
+        assert_eq!(other.wait().unwrap()[0].after.x, 50.0);
+        assert_eq!(last.wait().unwrap()[0].after.x, 60.0);
+
+        let metrics = executor.metrics();
+        assert_eq!((metrics.submitted, metrics.coalesced), (6, 2));
+        assert_eq!(metrics.completed, 4);
+        assert!(Duration::ZERO < metrics.max_wait && metrics.max_wait <= submitted.elapsed());
+        assert!(metrics.mean_wait() <= metrics.max_wait);
+    }
+
+    #[test]
+    fn drops_stale_jobs() {
+        let (backend, window) = backend();
+        let executor = executor(&backend, 1000, 100);
+        let (sender, waits) = mpsc::channel();
+        backend.notify_waits(sender);
+        backend.set_delay(Duration::from_millis(150));
+
+        // 遅いアプリの読み書きを待つ間に、後の仕事が古くなる
+        let slow = executor.submit(None, "slow", 1, move_right(window));
+        waits.recv().unwrap();
+        let stale = executor.submit(Some(1), "move", 1, move_right(window));
+        slow.wait().unwrap();
+        backend.set_delay(Duration::ZERO);
+
+        assert!(stale.wait().is_err());
+        assert_eq!(backend.frame(window).unwrap().x, 10.0);
+        let fresh = executor.submit(Some(1), "move", 1, move_right(window));
+        assert_eq!(fresh.wait().unwrap()[0].after.x, 20.0);
+
+        let metrics = executor.metrics();
+        assert_eq!((metrics.dropped, metrics.completed), (1, 2));
+    }
+
+    #[test]
+    fn times_out_without_running_jobs_concurrently() {
+        let (backend, window) = backend();
+        let executor = executor(&backend, 100, 1000);
+        let delay = Duration::from_millis(150);
+        backend.set_delay(delay);
+
+        let started = Instant::now();
+        let slow = executor.submit(None, "move", 1, move_right(window));
+        let (ran, ran_at) = mpsc::channel();
+        let next = executor.submit(
+            None,
+            "next",
+            1,
+            Box::new(move |_, _| {
+                let _ = ran.send(Instant::now());
+                Ok(Vec::new())
+            }),
+        );
+
+        let err = slow.wait().unwrap_err();
+        assert!(err.to_string().contains("did not finish"), "{err}");
+        assert!(started.elapsed() < delay);
+
+        // 止まった仕事が `timeout` のうちに終われば、次の仕事はその後で始まる
+        next.wait().unwrap();
+        assert!(ran_at.recv().unwrap() - started >= delay);
+        // 期限を過ぎてからは書き込まない
+        backend.set_delay(Duration::ZERO);
+        assert_eq!(backend.frame(window).unwrap().x, 0.0);
+
+        let metrics = executor.metrics();
+        assert_eq!(
+            (metrics.timed_out, metrics.completed, metrics.failed),
+            (1, 1, 0)
+        );
+    }
+
+    #[test]
+    fn abandons_a_hung_job() {
+        let (backend, window) = backend();
+        let executor = executor(&backend, 50, 1000);
+        let (started, gate, task) = blocked();
+        let hung = executor.submit(None, "hang", 1, task);
+        started.recv().unwrap();
+        let next = executor.submit(None, "move", 1, move_right(window));
+
+        // 止まった仕事が終わらないまま、次の仕事が別のスレッドで動く
+        assert!(hung.wait().is_err());
+        assert_eq!(next.wait().unwrap()[0].after.x, 10.0);
+        drop(gate);
+
+        let metrics = executor.metrics();
+        assert_eq!((metrics.timed_out, metrics.completed), (1, 1));
+    }
+
+    #[test]
+    fn keeps_running_after_a_panic() {
+        let (backend, window) = backend();
+        let executor = executor(&backend, 1000, 1000);
+        let failed = executor.submit(None, "panic", 1, Box::new(|_, _| panic!("broken")));
+        let next = executor.submit(None, "move", 1, move_right(window));
+
+        assert!(failed.wait().unwrap_err().to_string().contains("panicked"));
+        assert_eq!(next.wait().unwrap()[0].after.x, 10.0);
+        let metrics = executor.metrics();
+        assert_eq!((metrics.failed, metrics.completed), (1, 1));
+    }
+}
@@ -1,9 +1,16 @@
+use std::{fmt, str::FromStr, time::Instant};
+
 use anyhow::{anyhow, bail};
 use core_graphics::display::{CGPoint, CGSize};
-
-use crate::window::{
-    __AXUIElement, get_cgpoint, get_cgsize, get_focused_window, get_kAXPositionAttribute,
-    get_kAXSizeAttribute, set_cgpoint, set_cgsize,
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    outcome::OperationOutcome,
+    step::Axis,
+    window::{
+        __AXUIElement, get_cgpoint, get_cgsize, get_focused_window, get_kAXPositionAttribute,
+        get_kAXSizeAttribute, set_cgpoint, set_cgsize,
+    },
 };
 
 type AXUIElementRef = *const __AXUIElement;
@@ -11,7 +18,7 @@ type AXUIElementRef = *const __AXUIElement;
 /// ウィンドウの最小サイズ（ピクセル）
 const MIN_WINDOW_SIZE: f64 = 1.0;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Frame {
     pub x: f64,
     pub y: f64,
@@ -40,12 +47,87 @@ impl Frame {
     pub fn size(&self) -> CGSize {
         CGSize::new(self.w, self.h)
     }
+
+    /// `other` と重なっている部分の面積
+    pub fn intersection_area(&self, other: &Frame) -> f64 {
+        let w = (self.x + self.w).min(other.x + other.w) - self.x.max(other.x);
+        let h = (self.y + self.h).min(other.y + other.h) - self.y.max(other.y);
+        w.max(0.0) * h.max(0.0)
+    }
+
+    /// `direction` の向きに `step` だけ移動したフレーム
+    pub fn moved(&self, direction: &Direction, step: f64) -> Frame {
+        match direction {
+            Direction::Right => Frame::new(self.x + step, self.y, self.w, self.h),
+            Direction::Left => Frame::new(self.x - step, self.y, self.w, self.h),
+            Direction::Up => Frame::new(self.x, self.y - step, self.w, self.h),
+            Direction::Down => Frame::new(self.x, self.y + step, self.w, self.h),
+        }
+    }
+
+    /// `edge` を `delta` だけ動かしたフレーム（`resize` と同じ向き）
+    pub fn resized(&self, edge: Edge, delta: f64) -> Frame {
+        match edge {
+            Edge::Right => Frame::new(
+                self.x,
+                self.y,
+                (self.w + delta).max(MIN_WINDOW_SIZE),
+                self.h,
+            ),
+            Edge::Left => Frame::new(
+                self.x + delta,
+                self.y,
+                (self.w - delta).max(MIN_WINDOW_SIZE),
+                self.h,
+            ),
+            Edge::Top => Frame::new(
+                self.x,
+                self.y - delta,
+                self.w,
+                (self.h + delta).max(MIN_WINDOW_SIZE),
+            ),
+            Edge::Bottom => Frame::new(
+                self.x,
+                self.y,
+                self.w,
+                (self.h + delta).max(MIN_WINDOW_SIZE),
+            ),
+        }
+    }
+
+    /// 各値の差が `tolerance` 以内なら同じフレームとみなす
+    pub fn approx_eq(&self, other: &Frame, tolerance: f64) -> bool {
+        (self.x - other.x).abs() <= tolerance
+            && (self.y - other.y).abs() <= tolerance
+            && (self.w - other.w).abs() <= tolerance
+            && (self.h - other.h).abs() <= tolerance
+    }
+}
+
+/// `x,y wxh` の形で書く
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{} {}x{}", self.x, self.y, self.w, self.h)
+    }
 }
 
 pub fn get_frame() -> anyhow::Result<Frame> {
     unsafe {
         let win = get_focused_window().ok_or_else(|| anyhow!("No focused window"))?;
+        frame_of(win)
+    }
+}
 
+pub fn set_frame(frame: Frame) -> anyhow::Result<()> {
+    unsafe {
+        let win = get_focused_window().ok_or_else(|| anyhow!("No focused window"))?;
+        set_frame_of(win, frame)
+    }
+}
+
+/// ウィンドウ `win` のフレーム
+pub(crate) unsafe fn frame_of(win: AXUIElementRef) -> anyhow::Result<Frame> {
+    unsafe {
         let position = get_cgpoint(win, get_kAXPositionAttribute())
             .ok_or_else(|| anyhow!("Failed to get window position"))?;
 
@@ -56,10 +138,8 @@ pub fn get_frame() -> anyhow::Result<Frame> {
     }
 }
 
-pub fn set_frame(frame: Frame) -> anyhow::Result<()> {
+pub(crate) unsafe fn set_frame_of(win: AXUIElementRef, frame: Frame) -> anyhow::Result<()> {
     unsafe {
-        let win = get_focused_window().ok_or_else(|| anyhow!("No focused window"))?;
-
         // サイズ→位置の順で設定することで、左/上辺固定っぽく見せる
         if !set_cgsize(win, get_kAXSizeAttribute(), frame.size()) {
             bail!("Failed to set window size");
@@ -74,7 +154,7 @@ pub fn set_frame(frame: Frame) -> anyhow::Result<()> {
 }
 
 /// ウィンドウのリサイズ対象となる辺
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Edge {
     /// 左辺
     Left,
@@ -86,80 +166,41 @@ pub enum Edge {
     Bottom,
 }
 
-pub fn resize(edge: Edge, delta: f64) -> anyhow::Result<()> {
-    unsafe {
-        let win = get_focused_window().ok_or_else(|| anyhow!("No focused window"))?;
-
-        let current_size = get_cgsize(win, get_kAXSizeAttribute())
-            .ok_or_else(|| anyhow!("Failed to get window size"))?;
-
-        let new_size = match edge {
-            Edge::Right => resize_right(current_size, delta)?,
-            Edge::Left => resize_left(win, current_size, delta)?,
-            Edge::Top => resize_top(win, current_size, delta)?,
-            Edge::Bottom => resize_bottom(current_size, delta)?,
-        };
-
-        if !set_cgsize(win, get_kAXSizeAttribute(), new_size) {
-            bail!("Failed to set window size");
+impl Edge {
+    pub fn axis(self) -> Axis {
+        match self {
+            Edge::Left | Edge::Right => Axis::Horizontal,
+            Edge::Top | Edge::Bottom => Axis::Vertical,
         }
-
-        println!(
-            "Resized window to ({}, {})",
-            new_size.width, new_size.height
-        );
-        Ok(())
     }
 }
 
-fn resize_right(current_size: CGSize, delta: f64) -> anyhow::Result<CGSize> {
-    Ok(CGSize::new(
-        (current_size.width + delta).max(MIN_WINDOW_SIZE),
-        current_size.height,
-    ))
-}
-
-fn resize_left(win: AXUIElementRef, current_size: CGSize, delta: f64) -> anyhow::Result<CGSize> {
-    unsafe {
-        let current_pos = get_cgpoint(win, get_kAXPositionAttribute())
-            .ok_or_else(|| anyhow!("Failed to get window position"))?;
+impl FromStr for Edge {
+    type Err = String;
 
-        let new_pos = CGPoint::new(current_pos.x + delta, current_pos.y);
-        if !set_cgpoint(win, get_kAXPositionAttribute(), new_pos) {
-            bail!("Failed to set window position");
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "l" | "left" => Ok(Edge::Left),
+            "r" | "right" => Ok(Edge::Right),
+            "t" | "top" => Ok(Edge::Top),
+            "b" | "bottom" => Ok(Edge::Bottom),
+            _ => Err(format!("invalid edge: {s}")),
         }
-
-        Ok(CGSize::new(
-            (current_size.width - delta).max(MIN_WINDOW_SIZE),
-            current_size.height,
-        ))
     }
 }
 
-fn resize_top(win: AXUIElementRef, current_size: CGSize, delta: f64) -> anyhow::Result<CGSize> {
-    unsafe {
-        let current_pos = get_cgpoint(win, get_kAXPositionAttribute())
-            .ok_or_else(|| anyhow!("Failed to get window position"))?;
-
-        let new_pos = CGPoint::new(current_pos.x, current_pos.y - delta);
-        if !set_cgpoint(win, get_kAXPositionAttribute(), new_pos) {
-            bail!("Failed to set window position");
-        }
-
-        Ok(CGSize::new(
-            current_size.width.max(MIN_WINDOW_SIZE),
-            (current_size.height + delta).max(MIN_WINDOW_SIZE),
-        ))
+impl fmt::Display for Edge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Edge::Left => "left",
+            Edge::Right => "right",
+            Edge::Top => "top",
+            Edge::Bottom => "bottom",
+        })
     }
 }
 
-fn resize_bottom(current_size: CGSize, delta: f64) -> anyhow::Result<CGSize> {
-    Ok(CGSize::new(
-        current_size.width,
-        (current_size.height + delta).max(MIN_WINDOW_SIZE),
-    ))
-}
-
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Direction {
     Right,
     Left,
@@ -167,68 +208,55 @@ pub enum Direction {
     Down,
 }
 
-pub fn move_window(direction: &Direction, step: f64) -> anyhow::Result<()> {
-    unsafe {
-        let window = get_focused_window().ok_or_else(|| anyhow!("No focused window"))?;
-        if let Some(pos) = get_cgpoint(window, get_kAXPositionAttribute()) {
-            let new_p = match direction {
-                Direction::Right => CGPoint::new(pos.x + step, pos.y),
-                Direction::Left => CGPoint::new(pos.x - step, pos.y),
-                Direction::Up => CGPoint::new(pos.x, pos.y - step),
-                Direction::Down => CGPoint::new(pos.x, pos.y + step),
-            };
-            let _ = set_cgpoint(window, get_kAXPositionAttribute(), new_p);
-            println!("Moved window to ({}, {}).", new_p.x, new_p.y);
+impl Direction {
+    pub fn axis(&self) -> Axis {
+        match self {
+            Direction::Right | Direction::Left => Axis::Horizontal,
+            Direction::Up | Direction::Down => Axis::Vertical,
         }
     }
-
-    Ok(())
 }
 
-/// NSWindow-style setFrame function using Accessibility API
-/// This allows setting both position and size in one call, similar to NSWindow.setFrame
-pub fn set_window_frame(origin: CGPoint, size: CGSize) -> anyhow::Result<()> {
-    unsafe {
-        let window = get_focused_window().ok_or_else(|| anyhow!("No focused window"))?;
+impl FromStr for Direction {
+    type Err = String;
 
-        // Set size first, then position (similar to NSWindow behavior)
-        if !set_cgsize(window, get_kAXSizeAttribute(), size) {
-            bail!("Failed to set window size");
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "r" | "right" => Ok(Direction::Right),
+            "l" | "left" => Ok(Direction::Left),
+            "u" | "up" => Ok(Direction::Up),
+            "d" | "down" => Ok(Direction::Down),
+            _ => Err(format!("invalid dir: {s}")),
         }
-
-        if !set_cgpoint(window, get_kAXPositionAttribute(), origin) {
-            bail!("Failed to set window position");
-        }
-
-        println!(
-            "Set window frame to origin:({}, {}) size:({}, {})",
-            origin.x, origin.y, size.width, size.height
-        );
     }
+}
 
-    Ok(())
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Direction::Right => "right",
+            Direction::Left => "left",
+            Direction::Up => "up",
+            Direction::Down => "down",
+        })
+    }
 }
 
-/// Move window using NSWindow-style approach with Accessibility API
-pub fn move_window_nswindow_style(direction: &Direction, step: f64) -> anyhow::Result<()> {
+/// NSWindow-style setFrame function using Accessibility API
+/// This allows setting both position and size in one call, similar to NSWindow.setFrame
+///
+/// 移動やリサイズは制約やアニメーションを扱う `Operation::execute` を使う
+pub fn set_window_frame(origin: CGPoint, size: CGSize) -> anyhow::Result<OperationOutcome> {
+    let started = Instant::now();
+    let frame = Frame::from_position_and_size(origin, size);
     unsafe {
         let window = get_focused_window().ok_or_else(|| anyhow!("No focused window"))?;
-
-        // Get current position and size
-        let current_pos = get_cgpoint(window, get_kAXPositionAttribute())
-            .ok_or_else(|| anyhow!("Failed to get window position"))?;
-        let current_size = get_cgsize(window, get_kAXSizeAttribute())
-            .ok_or_else(|| anyhow!("Failed to get window size"))?;
-
-        // Calculate new position based on direction
-        let new_pos = match direction {
-            Direction::Right => CGPoint::new(current_pos.x + step, current_pos.y),
-            Direction::Left => CGPoint::new(current_pos.x - step, current_pos.y),
-            Direction::Up => CGPoint::new(current_pos.x, current_pos.y - step),
-            Direction::Down => CGPoint::new(current_pos.x, current_pos.y + step),
-        };
-
-        // Use the setFrame-style function
-        set_window_frame(new_pos, current_size)
+        let before = frame_of(window)?;
+        // 制約はかけないので、要求したフレームをそのまま書き込む
+        set_frame_of(window, frame)?;
+        let after = frame_of(window)?;
+        Ok(OperationOutcome::new(
+            None, before, frame, frame, after, started,
+        ))
     }
 }
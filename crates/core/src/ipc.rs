@@ -0,0 +1,592 @@
+use std::{
+    env, fs,
+    io::{BufRead, BufReader, Write},
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, mpsc::Sender},
+    thread,
+};
+
+use anyhow::{Context, anyhow, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    frame::Frame,
+    operation::Operation,
+    outcome::OperationOutcome,
+    preferences::{Binding, Preferences},
+    screen::Screen,
+    window::{Subscription, WindowBackend, WindowEvent, WindowId, WindowInfo},
+};
+
+/// プロトコルの版。要求と応答の `version` に書く
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// ソケットの場所を上書きする環境変数
+pub const SOCKET_ENV: &str = "RESIZE_KEY_SOCKET";
+
+/// アプリが待ち受けるソケットの場所
+///
+/// `RESIZE_KEY_SOCKET` があればそれを、なければユーザーごとの一時ディレクトリを使う
+pub fn socket_path() -> PathBuf {
+    env::var_os(SOCKET_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| env::temp_dir().join("resize_key.sock"))
+}
+
+/// 1 行 1 つの JSON で送る要求
+///
+/// `{"version": 1, "method": "get-frame"}` のように書く
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Request {
+    pub version: u32,
+    #[serde(flatten)]
+    pub method: Method,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "kebab-case")]
+pub enum Method {
+    /// 操作を設定の step やバインディングの設定で実行する。`operation` は `apply_preset:left_half` など
+    RunOperation {
+        operation: Operation,
+    },
+    /// ウィンドウのフレーム。`window` を省略するとフォーカスされているウィンドウ
+    GetFrame {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        window: Option<WindowId>,
+    },
+    /// フレームを変え、変えた後のフレームを返す
+    SetFrame {
+        window: WindowId,
+        frame: Frame,
+    },
+    ListWindows,
+    /// 接続されているディスプレイ。先頭がメインディスプレイ
+    ListScreens,
+    /// 設定ファイルを読み込み直す
+    ReloadConfig,
+    /// 現在のバインディング
+    ListBindings,
+}
+
+/// 要求 1 つに対する応答。`result` か `error` のどちらかを持つ
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Response {
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Reply>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Reply {
+    /// 実行した操作の結果。スクリプトならコマンドの順に並ぶ
+    Outcomes {
+        outcomes: Vec<OperationOutcome>,
+    },
+    Frame {
+        window: WindowId,
+        frame: Frame,
+    },
+    Windows {
+        windows: Vec<WindowInfo>,
+    },
+    Screens {
+        screens: Vec<Screen>,
+    },
+    /// 読み込み直した。設定の問題があれば `warnings` に入る
+    Reloaded {
+        warnings: Vec<String>,
+    },
+    Bindings {
+        bindings: Vec<Binding>,
+    },
+}
+
+impl Response {
+    fn new(result: anyhow::Result<Reply>) -> Self {
+        match result {
+            Ok(reply) => Self {
+                version: PROTOCOL_VERSION,
+                result: Some(reply),
+                error: None,
+            },
+            Err(err) => Self {
+                version: PROTOCOL_VERSION,
+                result: None,
+                error: Some(format!("{err:#}")),
+            },
+        }
+    }
+}
+
+/// サーバーが要求を処理するために使うアプリ側の機能
+pub trait Host: Send + Sync {
+    /// 現在の設定
+    fn preferences(&self) -> Arc<Preferences>;
+    fn run(&self, operation: Operation) -> anyhow::Result<Vec<OperationOutcome>>;
+    /// 設定を読み込み直し、設定の問題を返す
+    fn reload(&self) -> anyhow::Result<Vec<String>>;
+}
+
+/// Unix ドメインソケットで要求を受けるサーバー
+pub struct Server {
+    listener: UnixListener,
+    backend: Arc<dyn WindowBackend>,
+    host: Arc<dyn Host>,
+}
+
+impl Server {
+    /// `path` で待ち受ける
+    ///
+    /// 前回のソケットが残っていれば消す。ほかのプロセスが待ち受けているか、
+    /// ソケットではないファイルがあればエラー
+    pub fn bind(
+        path: &Path,
+        backend: Arc<dyn WindowBackend>,
+        host: Arc<dyn Host>,
+    ) -> anyhow::Result<Self> {
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                bail!("{} exists and is not a socket", path.display());
+            }
+            if UnixStream::connect(path).is_ok() {
+                bail!("{} is already in use", path.display());
+            }
+            fs::remove_file(path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("Failed to listen on {}", path.display()))?;
+        Ok(Self {
+            listener,
+            backend,
+            host,
+        })
+    }
+
+    /// 接続を受け続ける。接続ごとにスレッドを作る
+    pub fn serve(self) {
+        for stream in self.listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    log::warn!("Failed to accept a connection: {err}");
+                    continue;
+                }
+            };
+            let backend = Arc::clone(&self.backend);
+            let host = Arc::clone(&self.host);
+            thread::spawn(move || {
+                if let Err(err) = serve_connection(stream, backend.as_ref(), host.as_ref()) {
+                    log::warn!("IPC connection closed: {err}");
+                }
+            });
+        }
+    }
+}
+
+/// 接続が閉じられるまで、1 行ずつ要求を読んで応答を書く
+fn serve_connection(
+    stream: UnixStream,
+    backend: &dyn WindowBackend,
+    host: &dyn Host,
+) -> std::io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = respond(backend, host, &line);
+        match &response.error {
+            Some(error) => log::warn!("IPC request failed: {line}: {error}"),
+            None => log::debug!("IPC request: {line}"),
+        }
+        let mut encoded = serde_json::to_string(&response).map_err(std::io::Error::other)?;
+        encoded.push('\n');
+        writer.write_all(encoded.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// 1 行の要求を処理する
+pub fn respond(backend: &dyn WindowBackend, host: &dyn Host, line: &str) -> Response {
+    Response::new(
+        serde_json::from_str::<Request>(line)
+            .context("Invalid request")
+            .and_then(|request| {
+                if request.version != PROTOCOL_VERSION {
+                    bail!(
+                        "Unsupported protocol version {} (expected {PROTOCOL_VERSION})",
+                        request.version
+                    );
+                }
+                handle(backend, host, request.method)
+            }),
+    )
+}
+
+fn handle(backend: &dyn WindowBackend, host: &dyn Host, method: Method) -> anyhow::Result<Reply> {
+    match method {
+        Method::RunOperation { operation } => Ok(Reply::Outcomes {
+            outcomes: host.run(operation)?,
+        }),
+        Method::GetFrame { window } => {
+            let window = match window {
+                Some(window) => window,
+                None => backend.focused()?,
+            };
+            Ok(Reply::Frame {
+                window,
+                frame: backend.frame(window)?,
+            })
+        }
+        Method::SetFrame { window, frame } => {
+            backend.set_frame(window, frame)?;
+            Ok(Reply::Frame {
+                window,
+                frame: backend.frame(window)?,
+            })
+        }
+        Method::ListWindows => Ok(Reply::Windows {
+            windows: backend.windows()?,
+        }),
+        Method::ListScreens => Ok(Reply::Screens {
+            screens: backend.screens()?,
+        }),
+        Method::ReloadConfig => Ok(Reply::Reloaded {
+            warnings: host.reload()?,
+        }),
+        Method::ListBindings => Ok(Reply::Bindings {
+            bindings: host.preferences().bindings.clone(),
+        }),
+    }
+}
+
+/// アプリのサーバーにつなぐクライアント
+pub struct Client {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl Client {
+    /// アプリが起動していなければエラー
+    pub fn connect(path: &Path) -> std::io::Result<Self> {
+        let writer = UnixStream::connect(path)?;
+        let reader = BufReader::new(writer.try_clone()?);
+        Ok(Self { reader, writer })
+    }
+
+    /// 要求を送り、応答を待つ。サーバーが返したエラーは `Err` にする
+    pub fn request(&mut self, method: Method) -> anyhow::Result<Reply> {
+        let request = Request {
+            version: PROTOCOL_VERSION,
+            method,
+        };
+        let mut encoded = serde_json::to_string(&request)?;
+        encoded.push('\n');
+        self.writer.write_all(encoded.as_bytes())?;
+
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            bail!("ResizeKey closed the connection");
+        }
+        let response: Response = serde_json::from_str(&line).context("Invalid response")?;
+        if response.version != PROTOCOL_VERSION {
+            bail!(
+                "ResizeKey speaks protocol version {} (expected {PROTOCOL_VERSION})",
+                response.version
+            );
+        }
+        match (response.result, response.error) {
+            (_, Some(error)) => Err(anyhow!(error)),
+            (Some(reply), None) => Ok(reply),
+            (None, None) => bail!("Empty response"),
+        }
+    }
+}
+
+/// アプリのサーバーを通してウィンドウを操作するバックエンド
+///
+/// Accessibility の許可はアプリのものを使う。監視はできない
+pub struct RemoteBackend(Mutex<Client>);
+
+impl RemoteBackend {
+    pub fn new(client: Client) -> Self {
+        Self(Mutex::new(client))
+    }
+
+    fn request(&self, method: Method) -> anyhow::Result<Reply> {
+        self.0
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .request(method)
+    }
+}
+
+fn unexpected(reply: Reply) -> anyhow::Error {
+    anyhow!("Unexpected response from ResizeKey: {reply:?}")
+}
+
+impl WindowBackend for RemoteBackend {
+    fn screens(&self) -> anyhow::Result<Vec<Screen>> {
+        match self.request(Method::ListScreens)? {
+            Reply::Screens { screens } => Ok(screens),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    fn frame(&self, window: WindowId) -> anyhow::Result<Frame> {
+        match self.request(Method::GetFrame {
+            window: Some(window),
+        })? {
+            Reply::Frame { frame, .. } => Ok(frame),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    fn set_frame(&self, window: WindowId, frame: Frame) -> anyhow::Result<()> {
+        match self.request(Method::SetFrame { window, frame })? {
+            Reply::Frame { .. } => Ok(()),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    fn windows(&self) -> anyhow::Result<Vec<WindowInfo>> {
+        match self.request(Method::ListWindows)? {
+            Reply::Windows { windows } => Ok(windows),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    fn focused(&self) -> anyhow::Result<WindowId> {
+        match self.request(Method::GetFrame { window: None })? {
+            Reply::Frame { window, .. } => Ok(window),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    fn subscribe(&self, _events: Sender<WindowEvent>) -> anyhow::Result<Subscription> {
+        bail!("Window events are not available through ResizeKey")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+    use crate::mock_backend::MockBackend;
+
+    struct StubHost;
+
+    impl Host for StubHost {
+        fn preferences(&self) -> Arc<Preferences> {
+            Arc::new(Preferences::default())
+        }
+
+        fn run(&self, operation: Operation) -> anyhow::Result<Vec<OperationOutcome>> {
+            match operation {
+                Operation::CyclePresets => bail!("No presets"),
+                _ => Ok(Vec::new()),
+            }
+        }
+
+        fn reload(&self) -> anyhow::Result<Vec<String>> {
+            Ok(vec!["bindings[0]: unknown key".to_string()])
+        }
+    }
+
+    /// テストごとのソケットの場所。前回の実行で残ったものは消す
+    fn socket(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("rk-{}-{name}.sock", process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    /// `path` で `backend` を使うサーバーを動かす
+    fn serve(path: &Path, backend: &MockBackend) {
+        let server = Server::bind(path, Arc::new(backend.clone()), Arc::new(StubHost)).unwrap();
+        thread::spawn(move || server.serve());
+    }
+
+    fn backend() -> (MockBackend, WindowId) {
+        let backend = MockBackend::one_screen();
+        let window = backend.add_window(Default::default(), Frame::new(0.0, 25.0, 800.0, 600.0));
+        (backend, window)
+    }
+
+    /// 1 行をそのまま送り、応答を読む
+    fn send_raw(path: &Path, line: &str) -> Response {
+        let mut stream = UnixStream::connect(path).unwrap();
+        stream.write_all(format!("{line}\n").as_bytes()).unwrap();
+        let mut response = String::new();
+        BufReader::new(stream).read_line(&mut response).unwrap();
+        serde_json::from_str(&response).unwrap()
+    }
+
+    #[test]
+    fn round_trips_requests() {
+        let path = socket("round-trip");
+        let (backend, window) = backend();
+        serve(&path, &backend);
+        let mut client = Client::connect(&path).unwrap();
+
+        let Reply::Frame {
+            window: focused,
+            frame,
+        } = client.request(Method::GetFrame { window: None }).unwrap()
+        else {
+            panic!("expected a frame");
+        };
+        assert_eq!(
+            (focused, frame),
+            (window, Frame::new(0.0, 25.0, 800.0, 600.0))
+        );
+
+        let moved = Frame::new(100.0, 100.0, 400.0, 300.0);
+        let Reply::Frame { frame, .. } = client
+            .request(Method::SetFrame {
+                window,
+                frame: moved,
+            })
+            .unwrap()
+        else {
+            panic!("expected a frame");
+        };
+        assert_eq!(frame, moved);
+        assert_eq!(backend.frame(window).unwrap(), moved);
+
+        assert!(matches!(
+            client.request(Method::ListWindows).unwrap(),
+            Reply::Windows { windows } if windows.len() == 1
+        ));
+        assert!(matches!(
+            client.request(Method::ListScreens).unwrap(),
+            Reply::Screens { screens } if screens.len() == 1
+        ));
+        assert!(matches!(
+            client
+                .request(Method::RunOperation {
+                    operation: Operation::MoveLeft
+                })
+                .unwrap(),
+            Reply::Outcomes { outcomes } if outcomes.is_empty()
+        ));
+        assert!(matches!(
+            client.request(Method::ReloadConfig).unwrap(),
+            Reply::Reloaded { warnings } if warnings.len() == 1
+        ));
+        assert!(matches!(
+            client.request(Method::ListBindings).unwrap(),
+            Reply::Bindings { bindings } if !bindings.is_empty()
+        ));
+    }
+
+    #[test]
+    fn returns_errors_to_the_client() {
+        let path = socket("errors");
+        let (backend, _) = backend();
+        serve(&path, &backend);
+        let mut client = Client::connect(&path).unwrap();
+
+        let err = client
+            .request(Method::GetFrame { window: Some(99) })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "No window 99");
+        let err = client
+            .request(Method::RunOperation {
+                operation: Operation::CyclePresets,
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "No presets");
+
+        // エラーの後も同じ接続を使える
+        assert!(client.request(Method::ListScreens).is_ok());
+    }
+
+    #[test]
+    fn rejects_other_versions_and_unknown_methods() {
+        let path = socket("invalid");
+        let (backend, _) = backend();
+        serve(&path, &backend);
+
+        let cases = [
+            (
+                r#"{"version": 2, "method": "list-screens"}"#,
+                "Unsupported protocol version 2",
+            ),
+            (
+                r#"{"version": 1, "method": "close-window"}"#,
+                "Invalid request",
+            ),
+            (r#"{"version": 1}"#, "Invalid request"),
+            ("not json", "Invalid request"),
+        ];
+        for (line, message) in cases {
+            let response = send_raw(&path, line);
+            assert_eq!(response.version, PROTOCOL_VERSION);
+            assert!(response.result.is_none(), "{line}");
+            let error = response.error.unwrap();
+            assert!(error.starts_with(message), "{line}: {error}");
+        }
+    }
+
+    #[test]
+    fn client_rejects_other_versions() {
+        let path = socket("client-version");
+        let listener = UnixListener::bind(&path).unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut line = String::new();
+            BufReader::new(stream.try_clone().unwrap())
+                .read_line(&mut line)
+                .unwrap();
+            stream
+                .write_all(
+                    b"{\"version\": 2, \"result\": {\"type\": \"screens\", \"screens\": []}}\n",
+                )
+                .unwrap();
+        });
+
+        let err = Client::connect(&path)
+            .unwrap()
+            .request(Method::ListScreens)
+            .unwrap_err();
+        assert!(err.to_string().contains("protocol version 2"), "{err}");
+    }
+
+    #[test]
+    fn binds_only_over_stale_sockets() {
+        let (backend, _) = backend();
+
+        // 待ち受けていないソケットは消して使う
+        let stale = socket("stale");
+        drop(UnixListener::bind(&stale).unwrap());
+        serve(&stale, &backend);
+        assert!(Client::connect(&stale).is_ok());
+
+        // 待ち受けているソケットは使わない
+        let err = Server::bind(&stale, Arc::new(backend.clone()), Arc::new(StubHost))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("already in use"), "{err}");
+
+        // ソケットではないファイルは消さない
+        let file = socket("file");
+        fs::write(&file, "keep").unwrap();
+        let err = Server::bind(&file, Arc::new(backend), Arc::new(StubHost))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("not a socket"), "{err}");
+        assert_eq!(fs::read_to_string(&file).unwrap(), "keep");
+        fs::remove_file(&file).unwrap();
+    }
+}
@@ -0,0 +1,414 @@
+use serde::{Deserialize, Deserializer};
+
+use crate::{
+    constraint::Constraint,
+    logging::LogLevel,
+    mode::{ModalKey, ModalSettings},
+    preferences::{Binding, Preferences},
+    preset::Preset,
+    rule::Rule,
+    screen::ScreenSelector,
+    step::Step,
+};
+
+/// 既定値の上に重ねる設定ファイル 1 枚分
+///
+/// 書かれていない項目は下の層の値がそのまま使われる
+#[derive(Debug, Default, Deserialize)]
+pub struct PreferencesLayer {
+    #[serde(default)]
+    pub move_step: Option<Step>,
+    #[serde(default)]
+    pub resize_step: Option<Step>,
+    #[serde(default)]
+    pub constraint: Option<Constraint>,
+    #[serde(default)]
+    pub animate: Option<bool>,
+    /// 同じ名前のプリセットは置き換え、それ以外は追加する
+    #[serde(default)]
+    pub presets: Option<Vec<Preset>>,
+    #[serde(default)]
+    pub bindings: Option<Vec<BindingOverride>>,
+    #[serde(default)]
+    pub sequence_timeout: Option<f64>,
+    #[serde(default)]
+    pub modal: Option<ModalLayer>,
+    /// 下の層のルールより先に調べる
+    #[serde(default)]
+    pub rules: Option<Vec<Rule>>,
+    #[serde(default)]
+    pub log_level: Option<LogLevel>,
+}
+
+/// リサイズモードの設定に対する変更
+///
+/// `keys` は同じキーのものを置き換え、それ以外は追加する。
+/// `"command": null`（TOML では `command = ""`）ならそのキーを外す
+#[derive(Debug, Default, Deserialize)]
+pub struct ModalLayer {
+    #[serde(default, deserialize_with = "present")]
+    pub leader: Option<Option<String>>,
+    #[serde(default)]
+    pub timeout: Option<f64>,
+    #[serde(default)]
+    pub keys: Option<Vec<ModalKeyOverride>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModalKeyOverride {
+    pub key: String,
+    #[serde(default, deserialize_with = "present")]
+    pub command: Option<Option<String>>,
+}
+
+/// 下の層のバインディングに対する変更
+///
+/// - 同じ操作（またはコマンド）のバインディングがあれば、書かれた項目だけを上書きする
+/// - `"extra": true` なら既存のものを残したままキーを追加する
+/// - `"key": null`（TOML では `key = ""`）ならその操作の割り当てを外す
+#[derive(Debug, Deserialize)]
+pub struct BindingOverride {
+    #[serde(default)]
+    pub operation: Option<String>,
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default, deserialize_with = "present")]
+    pub key: Option<Option<String>>,
+    #[serde(default)]
+    pub extra: bool,
+    #[serde(default)]
+    pub step: Option<Step>,
+    #[serde(default)]
+    pub constraint: Option<Constraint>,
+    #[serde(default)]
+    pub animate: Option<bool>,
+    #[serde(default)]
+    pub screen: Option<ScreenSelector>,
+}
+
+/// `null` と項目の省略を区別する
+fn present<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Option<String>>, D::Error> {
+    Option::<String>::deserialize(deserializer).map(Some)
+}
+
+impl BindingOverride {
+    fn unbinds(&self) -> bool {
+        matches!(&self.key, Some(None)) || matches!(&self.key, Some(Some(key)) if key.is_empty())
+    }
+
+    /// 同じ操作（またはコマンド）を割り当てたバインディングかどうか
+    fn targets(&self, binding: &Binding) -> bool {
+        self.operation == binding.operation && self.command == binding.command
+    }
+
+    /// エラーメッセージに使う名前
+    fn label(&self) -> &str {
+        self.operation
+            .as_deref()
+            .or(self.command.as_deref())
+            .unwrap_or_default()
+    }
+
+    fn merge_into(self, binding: &mut Binding) {
+        if let Some(Some(key)) = self.key {
+            binding.key = key;
+        }
+        binding.step = self.step.or(binding.step);
+        binding.constraint = self.constraint.or(binding.constraint);
+        binding.animate = self.animate.or(binding.animate);
+        binding.screen = self.screen.or(binding.screen);
+    }
+
+    fn into_binding(self) -> Result<Binding, String> {
+        if self.operation.is_none() && self.command.is_none() {
+            return Err("bindings: each entry needs an `operation` or a `command`".to_string());
+        }
+        let Some(Some(key)) = self.key else {
+            return Err(format!(
+                "bindings: `{}` has no binding to override; add a `key`",
+                self.label()
+            ));
+        };
+        Ok(Binding {
+            operation: self.operation,
+            command: self.command,
+            key,
+            step: self.step,
+            constraint: self.constraint,
+            animate: self.animate,
+            screen: self.screen,
+        })
+    }
+}
+
+impl PreferencesLayer {
+    /// `preferences` にこの層を重ねる
+    pub fn apply_to(self, preferences: &mut Preferences) -> Result<(), String> {
+        if let Some(step) = self.move_step {
+            preferences.move_step = step;
+        }
+        if let Some(step) = self.resize_step {
+            preferences.resize_step = step;
+        }
+        if let Some(constraint) = self.constraint {
+            preferences.constraint = constraint;
+        }
+        if let Some(animate) = self.animate {
+            preferences.animate = animate;
+        }
+        if let Some(presets) = self.presets {
+            for preset in presets {
+                match preferences
+                    .presets
+                    .iter_mut()
+                    .find(|existing| existing.name == preset.name)
+                {
+                    Some(existing) => *existing = preset,
+                    None => preferences.presets.push(preset),
+                }
+            }
+        }
+        if let Some(bindings) = self.bindings {
+            merge_bindings(&mut preferences.bindings, bindings)?;
+        }
+        if let Some(timeout) = self.sequence_timeout {
+            preferences.sequence_timeout = positive_seconds("sequence_timeout", timeout)?;
+        }
+        if let Some(modal) = self.modal {
+            modal.apply_to(&mut preferences.modal)?;
+        }
+        if let Some(mut rules) = self.rules {
+            rules.append(&mut preferences.rules);
+            preferences.rules = rules;
+        }
+        if let Some(level) = self.log_level {
+            preferences.log_level = level;
+        }
+        Ok(())
+    }
+}
+
+impl ModalLayer {
+    fn apply_to(self, modal: &mut ModalSettings) -> Result<(), String> {
+        if let Some(leader) = self.leader {
+            // TOML には null がないので空文字列でも無効にできるようにする
+            modal.leader = leader.filter(|key| !key.is_empty());
+        }
+        if let Some(timeout) = self.timeout {
+            modal.timeout = positive_seconds("modal.timeout", timeout)?;
+        }
+        for entry in self.keys.into_iter().flatten() {
+            let existing = modal.keys.iter().position(|key| key.key == entry.key);
+            match (
+                existing,
+                entry
+                    .command
+                    .flatten()
+                    .filter(|command| !command.is_empty()),
+            ) {
+                (Some(index), Some(command)) => modal.keys[index].command = command,
+                (Some(index), None) => {
+                    modal.keys.remove(index);
+                }
+                (None, Some(command)) => modal.keys.push(ModalKey {
+                    key: entry.key,
+                    command,
+                }),
+                (None, None) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// バインディングを操作ごとに突き合わせて重ねる
+fn merge_bindings(
+    bindings: &mut Vec<Binding>,
+    overrides: Vec<BindingOverride>,
+) -> Result<(), String> {
+    // この層で追加したものは上書き・解除の対象にしない
+    let base_len = bindings.len();
+    let mut overridden = vec![false; base_len];
+    let mut unbound: Vec<BindingOverride> = Vec::new();
+
+    for entry in overrides {
+        if entry.unbinds() {
+            unbound.push(entry);
+            continue;
+        }
+
+        let target = if entry.extra {
+            None
+        } else {
+            (0..base_len).find(|&i| !overridden[i] && entry.targets(&bindings[i]))
+        };
+        match target {
+            Some(index) => {
+                overridden[index] = true;
+                entry.merge_into(&mut bindings[index]);
+            }
+            None => bindings.push(entry.into_binding()?),
+        }
+    }
+
+    let mut index = 0;
+    bindings.retain(|binding| {
+        let keep = index >= base_len || !unbound.iter().any(|entry| entry.targets(binding));
+        index += 1;
+        keep
+    });
+    Ok(())
+}
+
+fn positive_seconds(name: &str, seconds: f64) -> Result<f64, String> {
+    if !seconds.is_finite() || seconds <= 0.0 {
+        return Err(format!(
+            "{name} must be a positive number of seconds, got {seconds}"
+        ));
+    }
+    Ok(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::from_jsonc;
+
+    /// `base` のバインディングに `layer` を重ね、`(operation か command, key)` の一覧を返す
+    fn merged(base: &str, layer: &str) -> Result<Vec<(String, String)>, String> {
+        let mut preferences = Preferences {
+            bindings: from_jsonc(base).unwrap(),
+            ..Preferences::default()
+        };
+        let layer: PreferencesLayer = from_jsonc(layer).unwrap();
+        layer.apply_to(&mut preferences)?;
+        Ok(preferences
+            .bindings
+            .into_iter()
+            .map(|binding| {
+                let target = binding.operation.or(binding.command).unwrap_or_default();
+                (target, binding.key)
+            })
+            .collect())
+    }
+
+    fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+        expected
+            .iter()
+            .map(|(target, key)| (target.to_string(), key.to_string()))
+            .collect()
+    }
+
+    const BASE: &str = r#"[
+        {"operation": "move_left", "key": "ctrl+h"},
+        {"operation": "move_right", "key": "ctrl+l"},
+        {"command": "place left_half", "key": "ctrl+1"}
+    ]"#;
+
+    #[test]
+    fn merges_bindings() {
+        let cases: &[(&str, &[(&str, &str)])] = &[
+            // 書かれていなければそのまま
+            (
+                "{}",
+                &[
+                    ("move_left", "ctrl+h"),
+                    ("move_right", "ctrl+l"),
+                    ("place left_half", "ctrl+1"),
+                ],
+            ),
+            // 同じ操作のキーを置き換える
+            (
+                r#"{"bindings": [{"operation": "move_left", "key": "alt+h"}]}"#,
+                &[
+                    ("move_left", "alt+h"),
+                    ("move_right", "ctrl+l"),
+                    ("place left_half", "ctrl+1"),
+                ],
+            ),
+            // `key: null` で外す
+            (
+                r#"{"bindings": [{"operation": "move_right", "key": null}]}"#,
+                &[("move_left", "ctrl+h"), ("place left_half", "ctrl+1")],
+            ),
+            // TOML と同じく空文字列でも外せる
+            (
+                r#"{"bindings": [{"command": "place left_half", "key": ""}]}"#,
+                &[("move_left", "ctrl+h"), ("move_right", "ctrl+l")],
+            ),
+            // `extra` なら残したまま追加する
+            (
+                r#"{"bindings": [{"operation": "move_left", "key": "alt+h", "extra": true}]}"#,
+                &[
+                    ("move_left", "ctrl+h"),
+                    ("move_right", "ctrl+l"),
+                    ("place left_half", "ctrl+1"),
+                    ("move_left", "alt+h"),
+                ],
+            ),
+            // 下の層にない操作は追加する
+            (
+                r#"{"bindings": [{"operation": "move_up", "key": "ctrl+k"}]}"#,
+                &[
+                    ("move_left", "ctrl+h"),
+                    ("move_right", "ctrl+l"),
+                    ("place left_half", "ctrl+1"),
+                    ("move_up", "ctrl+k"),
+                ],
+            ),
+            // この層で追加したものは同じ層の `null` で外さない
+            (
+                r#"{"bindings": [
+                    {"operation": "move_left", "key": "alt+h", "extra": true},
+                    {"operation": "move_left", "key": null}
+                ]}"#,
+                &[
+                    ("move_right", "ctrl+l"),
+                    ("place left_half", "ctrl+1"),
+                    ("move_left", "alt+h"),
+                ],
+            ),
+        ];
+        for (layer, expected) in cases {
+            assert_eq!(merged(BASE, layer), Ok(pairs(expected)), "{layer}");
+        }
+    }
+
+    #[test]
+    fn overrides_only_the_written_fields() {
+        let mut preferences = Preferences {
+            bindings: from_jsonc(r#"[{"operation": "move_left", "key": "ctrl+h", "step": 10}]"#)
+                .unwrap(),
+            ..Preferences::default()
+        };
+        let layer: PreferencesLayer =
+            from_jsonc(r#"{"bindings": [{"operation": "move_left", "animate": true}]}"#).unwrap();
+        layer.apply_to(&mut preferences).unwrap();
+
+        let binding = &preferences.bindings[0];
+        assert_eq!(binding.key, "ctrl+h");
+        assert_eq!(binding.step, Some(Step::Points(10.0)));
+        assert_eq!(binding.animate, Some(true));
+    }
+
+    #[test]
+    fn rejects_incomplete_bindings() {
+        let cases = [
+            r#"{"bindings": [{"key": "ctrl+k"}]}"#,
+            r#"{"bindings": [{"operation": "move_up", "step": 10}]}"#,
+        ];
+        for layer in cases {
+            assert!(merged(BASE, layer).is_err(), "{layer}");
+        }
+    }
+
+    #[test]
+    fn rejects_non_positive_timeouts() {
+        for layer in [
+            r#"{"sequence_timeout": 0}"#,
+            r#"{"modal": {"timeout": -1}}"#,
+        ] {
+            assert!(merged(BASE, layer).is_err(), "{layer}");
+        }
+    }
+}
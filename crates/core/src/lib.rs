@@ -1,2 +1,23 @@
+pub mod ax_backend;
+pub mod command;
+pub mod config;
+pub mod constraint;
+pub mod doctor;
+pub mod executor;
 pub mod frame;
+pub mod ipc;
+pub mod layer;
+pub mod logging;
+pub mod mock_backend;
+pub mod mode;
+pub mod operation;
+pub mod outcome;
+pub mod placement;
+pub mod preferences;
+pub mod preset;
+pub mod rule;
+pub mod screen;
+pub mod sequence;
+pub mod step;
+pub mod validation;
 pub mod window;
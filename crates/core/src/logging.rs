@@ -0,0 +1,314 @@
+use std::{
+    backtrace::Backtrace,
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    panic,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::{Deserialize, Serialize};
+
+/// ログファイルの名前
+pub const LOG_FILE_NAME: &str = "resize_key.log";
+/// ログファイルがこの大きさを超えたら切り替える
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+/// 切り替えた古いログファイルを残す数（`resize_key.log.1` から）
+const KEPT_FILES: usize = 3;
+
+/// 設定の `log_level`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    pub fn filter(self) -> LevelFilter {
+        match self {
+            LogLevel::Off => LevelFilter::Off,
+            LogLevel::Error => LevelFilter::Error,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Trace => LevelFilter::Trace,
+        }
+    }
+}
+
+/// ログの出力先
+#[derive(Debug, Clone)]
+pub enum Output {
+    /// 大きくなったら切り替えるファイル
+    File(PathBuf),
+    Stderr,
+}
+
+/// ログファイルを置くディレクトリ（`~/Library/Logs/ResizeKey`）
+pub fn log_dir() -> PathBuf {
+    env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir)
+        .join("Library/Logs/ResizeKey")
+}
+
+/// アプリが書くログファイル
+pub fn log_path() -> PathBuf {
+    log_dir().join(LOG_FILE_NAME)
+}
+
+/// `output` に書くロガーを登録する。プロセスで 1 回だけ呼べる
+pub fn init(output: Output, level: LogLevel) -> anyhow::Result<()> {
+    let sink = match output {
+        Output::File(path) => Sink::File(RotatingFile::open(path)?),
+        Output::Stderr => Sink::Stderr,
+    };
+    log::set_boxed_logger(Box::new(Logger {
+        sink: Mutex::new(sink),
+    }))
+    .context("A logger is already registered")?;
+    set_level(level);
+    Ok(())
+}
+
+/// 出力するログの水準を変える。設定を読み込み直したときに呼ぶ
+pub fn set_level(level: LogLevel) {
+    log::set_max_level(level.filter());
+}
+
+/// パニックの内容とバックトレースをログに残す
+///
+/// 元のフック（標準エラー出力への表示）もそのまま呼ぶ
+pub fn install_panic_hook() {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        log::error!(target: "panic", "{info}\n{}", Backtrace::force_capture());
+        log::logger().flush();
+        previous(info);
+    }));
+}
+
+struct Logger {
+    sink: Mutex<Sink>,
+}
+
+enum Sink {
+    File(RotatingFile),
+    Stderr,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let mut sink = self.sink.lock().unwrap_or_else(|err| err.into_inner());
+        match &mut *sink {
+            Sink::File(file) => {
+                let line = format!(
+                    "{} {:<5} {}: {}\n",
+                    timestamp(SystemTime::now()),
+                    record.level(),
+                    record.target(),
+                    record.args()
+                );
+                // ログを書けなくても本来の処理は続ける
+                if let Err(err) = file.write(line.as_bytes()) {
+                    eprintln!("Failed to write the log: {err}");
+                    eprint!("{line}");
+                }
+            }
+            Sink::Stderr => eprintln!(
+                "{}: {}",
+                record.level().as_str().to_lowercase(),
+                record.args()
+            ),
+        }
+    }
+
+    fn flush(&self) {
+        if let Sink::File(file) = &mut *self.sink.lock().unwrap_or_else(|err| err.into_inner()) {
+            let _ = file.file.flush();
+        }
+    }
+}
+
+/// `MAX_FILE_BYTES` を超えたら `<name>.1`、`<name>.2`… にずらして新しく書き始めるファイル
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let file = append(&path)?;
+        let written = file.metadata().map_or(0, |metadata| metadata.len());
+        Ok(Self {
+            path,
+            file,
+            written,
+        })
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.written > 0 && self.written + bytes.len() as u64 > MAX_FILE_BYTES {
+            self.rotate()?;
+        }
+        self.file.write_all(bytes)?;
+        self.written += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for index in (1..KEPT_FILES).rev() {
+            let from = numbered(&self.path, index);
+            if from.exists() {
+                fs::rename(&from, numbered(&self.path, index + 1))?;
+            }
+        }
+        fs::rename(&self.path, numbered(&self.path, 1))?;
+        self.file = append(&self.path).map_err(io::Error::other)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+fn append(path: &Path) -> anyhow::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))
+}
+
+/// `resize_key.log` に対する `resize_key.log.<index>`
+fn numbered(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{index}"));
+    path.with_file_name(name)
+}
+
+/// `2025-01-31T09:41:05.123Z` の形の UTC の時刻
+fn timestamp(time: SystemTime) -> String {
+    let elapsed = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = elapsed.as_secs();
+    let (days, rest) = (seconds / 86_400, seconds % 86_400);
+
+    // 1970-01-01 からの日数を暦の日付にする（Howard Hinnant の civil_from_days）
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        rest / 3600,
+        rest % 3600 / 60,
+        rest % 60,
+        elapsed.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{process, time::Duration};
+
+    use super::*;
+
+    /// テストごとの空のディレクトリ。前回の実行で残ったものは消す
+    fn directory(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("rk-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn numbers_rotated_files() {
+        let path = Path::new("/tmp/logs/resize_key.log");
+        assert_eq!(numbered(path, 1), Path::new("/tmp/logs/resize_key.log.1"));
+        assert_eq!(numbered(path, 3), Path::new("/tmp/logs/resize_key.log.3"));
+    }
+
+    #[test]
+    fn rotates_and_keeps_the_newest_files() {
+        let dir = directory("rotate");
+        let path = dir.join(LOG_FILE_NAME);
+        let mut file = RotatingFile::open(path.clone()).unwrap();
+        for text in ["a", "b", "c", "d", "e"] {
+            if text != "a" {
+                file.rotate().unwrap();
+            }
+            file.write(text.as_bytes()).unwrap();
+        }
+
+        let read = |index| match index {
+            0 => fs::read_to_string(&path).ok(),
+            index => fs::read_to_string(numbered(&path, index)).ok(),
+        };
+        let kept: Vec<Option<String>> = (0..=KEPT_FILES + 1).map(read).collect();
+        let expected = ["e", "d", "c", "b"].map(|text| Some(text.to_string()));
+        assert_eq!(kept[..=KEPT_FILES], expected);
+        assert_eq!(kept[KEPT_FILES + 1], None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rotates_when_the_file_gets_too_large() {
+        let dir = directory("size");
+        let path = dir.join(LOG_FILE_NAME);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, vec![b'x'; MAX_FILE_BYTES as usize]).unwrap();
+
+        // 開いたときの大きさから数える
+        let mut file = RotatingFile::open(path.clone()).unwrap();
+        file.write(b"y").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "y");
+        assert_eq!(
+            fs::metadata(numbered(&path, 1)).unwrap().len(),
+            MAX_FILE_BYTES
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn formats_timestamps() {
+        let cases = [
+            (0, 0, "1970-01-01T00:00:00.000Z"),
+            // 2000 年は 400 で割り切れるのでうるう年
+            (946_684_799, 999, "1999-12-31T23:59:59.999Z"),
+            (946_684_800, 0, "2000-01-01T00:00:00.000Z"),
+            (951_782_400, 0, "2000-02-29T00:00:00.000Z"),
+            (1_709_210_096, 789, "2024-02-29T12:34:56.789Z"),
+            // 2100 年はうるう年ではない
+            (4_107_542_400, 0, "2100-03-01T00:00:00.000Z"),
+        ];
+        for (seconds, millis, expected) in cases {
+            let time = UNIX_EPOCH + Duration::from_secs(seconds) + Duration::from_millis(millis);
+            assert_eq!(timestamp(time), expected, "{seconds}");
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard, mpsc::Sender},
+    thread,
+    time::Duration,
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    doctor::{Check, Diagnosis},
+    frame::Frame,
+    screen::Screen,
+    window::{AppIdentity, Subscription, WindowBackend, WindowEvent, WindowId, WindowInfo},
+};
+
+/// テスト用のバックエンド
+///
+/// ウィンドウとディスプレイをメモリ上に持ち、`inject` で監視のイベントを起こす。
+/// `set_frame` で位置や大きさが変われば `WindowMoved` / `WindowResized` を送る。
+/// `set_delay` で応答の遅いアプリを真似られる
+#[derive(Clone, Default)]
+pub struct MockBackend {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    screens: Vec<Screen>,
+    windows: HashMap<WindowId, (AppIdentity, Frame)>,
+    next_window: WindowId,
+    focused: Option<WindowId>,
+    subscribers: Vec<(usize, Sender<WindowEvent>)>,
+    next_subscriber: usize,
+    /// フレームを読み書きするたびに待つ時間
+    delay: Duration,
+    /// 待ち始めたことを知らせる相手
+    waits: Option<Sender<()>>,
+}
+
+impl MockBackend {
+    /// `screens` の先頭をメインディスプレイとする
+    pub fn new(screens: Vec<Screen>) -> Self {
+        let backend = Self::default();
+        backend.state().screens = screens;
+        backend
+    }
+
+    /// 1440x900 のディスプレイが 1 つのバックエンド。上の 25pt はメニューバー
+    pub fn one_screen() -> Self {
+        Self::new(vec![Screen {
+            id: 1,
+            frame: Frame::new(0.0, 0.0, 1440.0, 900.0),
+            visible_frame: Frame::new(0.0, 25.0, 1440.0, 875.0),
+        }])
+    }
+
+    /// `app` のウィンドウを加えてフォーカスし、その番号を返す。イベントは送らない
+    pub fn add_window(&self, app: AppIdentity, frame: Frame) -> WindowId {
+        let mut state = self.state();
+        state.next_window += 1;
+        let window = state.next_window;
+        state.windows.insert(window, (app, frame));
+        state.focused = Some(window);
+        window
+    }
+
+    /// `window` をフォーカスする。イベントは送らない
+    pub fn focus(&self, window: WindowId) {
+        self.state().focused = Some(window);
+    }
+
+    /// これ以降、フレームを読み書きするたびに `delay` だけ待つ
+    pub fn set_delay(&self, delay: Duration) {
+        self.state().delay = delay;
+    }
+
+    /// これ以降、フレームの読み書きが待ち始めるたびに `waits` に送る
+    pub fn notify_waits(&self, waits: Sender<()>) {
+        self.state().waits = Some(waits);
+    }
+
+    /// 購読しているすべての相手に `event` を送る
+    pub fn inject(&self, event: WindowEvent) {
+        self.state().send(event);
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// 状態のロックを持たずに待つ。待つ間も他のスレッドは読み書きできる
+    fn wait(&self) {
+        let (delay, waits) = {
+            let state = self.state();
+            (state.delay, state.waits.clone())
+        };
+        if let Some(waits) = waits {
+            let _ = waits.send(());
+        }
+        if !delay.is_zero() {
+            thread::sleep(delay);
+        }
+    }
+}
+
+impl State {
+    fn send(&mut self, event: WindowEvent) {
+        self.subscribers
+            .retain(|(_, events)| events.send(event.clone()).is_ok());
+    }
+}
+
+impl WindowBackend for MockBackend {
+    fn screens(&self) -> anyhow::Result<Vec<Screen>> {
+        Ok(self.state().screens.clone())
+    }
+
+    fn frame(&self, window: WindowId) -> anyhow::Result<Frame> {
+        self.wait();
+        self.state()
+            .windows
+            .get(&window)
+            .map(|(_, frame)| *frame)
+            .ok_or_else(|| anyhow!("No window {window}"))
+    }
+
+    fn set_frame(&self, window: WindowId, frame: Frame) -> anyhow::Result<()> {
+        self.wait();
+        let mut state = self.state();
+        let (app, current) = state
+            .windows
+            .get_mut(&window)
+            .ok_or_else(|| anyhow!("No window {window}"))?;
+        let previous = std::mem::replace(current, frame);
+        let app = app.clone();
+
+        if (previous.x, previous.y) != (frame.x, frame.y) {
+            state.send(WindowEvent::WindowMoved {
+                window,
+                app: app.clone(),
+                frame,
+            });
+        }
+        if (previous.w, previous.h) != (frame.w, frame.h) {
+            state.send(WindowEvent::WindowResized { window, app, frame });
+        }
+        Ok(())
+    }
+
+    fn windows(&self) -> anyhow::Result<Vec<WindowInfo>> {
+        let state = self.state();
+        let mut windows: Vec<WindowInfo> = state
+            .windows
+            .iter()
+            .map(|(window, (app, frame))| WindowInfo {
+                window: *window,
+                app: app.clone(),
+                frame: *frame,
+            })
+            .collect();
+        windows.sort_by_key(|info| info.window);
+        Ok(windows)
+    }
+
+    fn focused(&self) -> anyhow::Result<WindowId> {
+        let state = self.state();
+        state
+            .focused
+            .filter(|window| state.windows.contains_key(window))
+            .ok_or_else(|| anyhow!("No focused window"))
+    }
+
+    fn subscribe(&self, events: Sender<WindowEvent>) -> anyhow::Result<Subscription> {
+        let mut state = self.state();
+        state.next_subscriber += 1;
+        let id = state.next_subscriber;
+        state.subscribers.push((id, events));
+
+        let shared = Arc::clone(&self.state);
+        Ok(Subscription::new(move || {
+            let mut state = shared.lock().unwrap_or_else(|err| err.into_inner());
+            state.subscribers.retain(|(other, _)| *other != id);
+        }))
+    }
+
+    fn checks(&self) -> Vec<Box<dyn Check + '_>> {
+        vec![Box::new(MockSetupCheck(self))]
+    }
+}
+
+/// ディスプレイとウィンドウが用意されているか
+struct MockSetupCheck<'a>(&'a MockBackend);
+
+impl Check for MockSetupCheck<'_> {
+    fn name(&self) -> &str {
+        "Mock backend"
+    }
+
+    fn run(&self) -> Diagnosis {
+        let state = self.0.state();
+        if state.screens.is_empty() {
+            return Diagnosis::fail("No screens").hint("Pass screens to `MockBackend::new`");
+        }
+        Diagnosis::pass(format!(
+            "{} screen(s), {} window(s)",
+            state.screens.len(),
+            state.windows.len()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, time::Instant};
+
+    use super::*;
+
+    fn safari() -> AppIdentity {
+        AppIdentity {
+            name: Some("Safari".to_string()),
+            ..AppIdentity::default()
+        }
+    }
+
+    #[test]
+    fn keeps_windows_and_focus() {
+        let backend = MockBackend::one_screen();
+        assert!(backend.focused().is_err());
+
+        let first = backend.add_window(safari(), Frame::new(0.0, 0.0, 100.0, 100.0));
+        let second = backend.add_window(safari(), Frame::new(50.0, 50.0, 200.0, 200.0));
+        assert_ne!(first, second);
+        assert_eq!(backend.focused().unwrap(), second);
+
+        backend.focus(first);
+        assert_eq!(backend.focused().unwrap(), first);
+        assert_eq!(
+            backend.frame(first).unwrap(),
+            Frame::new(0.0, 0.0, 100.0, 100.0)
+        );
+        assert_eq!(
+            backend
+                .windows()
+                .unwrap()
+                .iter()
+                .map(|info| info.window)
+                .collect::<Vec<_>>(),
+            vec![first, second]
+        );
+        assert_eq!(backend.screens().unwrap()[0].id, 1);
+        assert!(backend.frame(99).is_err());
+        assert!(
+            backend
+                .set_frame(99, Frame::new(0.0, 0.0, 1.0, 1.0))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn sends_events_for_changes() {
+        let backend = MockBackend::one_screen();
+        let window = backend.add_window(safari(), Frame::new(0.0, 0.0, 100.0, 100.0));
+        let (sender, events) = mpsc::channel();
+        let subscription = backend.subscribe(sender).unwrap();
+
+        let moved = Frame::new(10.0, 0.0, 100.0, 100.0);
+        backend.set_frame(window, moved).unwrap();
+        let both = Frame::new(20.0, 20.0, 50.0, 50.0);
+        backend.set_frame(window, both).unwrap();
+        // 変わらなければ知らせない
+        backend.set_frame(window, both).unwrap();
+        backend.inject(WindowEvent::AppLaunched { app: safari() });
+
+        let received: Vec<WindowEvent> = events.try_iter().collect();
+        assert_eq!(
+            received,
+            vec![
+                WindowEvent::WindowMoved {
+                    window,
+                    app: safari(),
+                    frame: moved
+                },
+                WindowEvent::WindowMoved {
+                    window,
+                    app: safari(),
+                    frame: both
+                },
+                WindowEvent::WindowResized {
+                    window,
+                    app: safari(),
+                    frame: both
+                },
+                WindowEvent::AppLaunched { app: safari() },
+            ]
+        );
+
+        drop(subscription);
+        backend.inject(WindowEvent::AppLaunched { app: safari() });
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn checks_its_setup() {
+        let empty = MockBackend::default();
+        let checks = empty.checks();
+        assert_eq!(checks[0].run().status, crate::doctor::Status::Fail);
+
+        let backend = MockBackend::one_screen();
+        backend.add_window(safari(), Frame::new(0.0, 0.0, 100.0, 100.0));
+        let diagnosis = backend.checks()[0].run();
+        assert_eq!(diagnosis.status, crate::doctor::Status::Pass);
+        assert_eq!(diagnosis.detail, "1 screen(s), 1 window(s)");
+    }
+
+    #[test]
+    fn injects_delays() {
+        let backend = MockBackend::one_screen();
+        let window = backend.add_window(safari(), Frame::new(0.0, 0.0, 100.0, 100.0));
+        let delay = Duration::from_millis(50);
+        backend.set_delay(delay);
+
+        let started = Instant::now();
+        backend.frame(window).unwrap();
+        backend
+            .set_frame(window, Frame::new(1.0, 0.0, 100.0, 100.0))
+            .unwrap();
+        assert!(started.elapsed() >= delay * 2);
+
+        // 待つ間もロックは持たない
+        let (sender, waits) = mpsc::channel();
+        backend.notify_waits(sender);
+        let other = backend.clone();
+        let reader = thread::spawn(move || other.frame(window));
+        waits.recv().unwrap();
+        backend.windows().unwrap();
+        assert!(!reader.is_finished());
+        reader.join().unwrap().unwrap();
+    }
+}
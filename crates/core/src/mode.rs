@@ -0,0 +1,350 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{command::Script, frame::Frame};
+
+/// モード中に `Escape` で元に戻して抜けるキー
+pub const CANCEL_KEY: &str = "escape";
+/// モード中に `Return` で確定して抜けるキー
+pub const COMMIT_KEY: &str = "enter";
+/// モード中に回数を入力するキー。添字がその数字になる
+pub const DIGIT_KEYS: [&str; 10] = ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"];
+/// 回数の上限
+pub(crate) const MAX_COUNT: u32 = 999;
+
+/// リサイズモードの設定
+///
+/// `leader` を押すとモードに入り、`keys` のキーだけで操作できる
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModalSettings {
+    /// モードに入るキー。`null` ならモードを使わない
+    #[serde(default)]
+    pub leader: Option<String>,
+    /// 最後の操作からモードを自動で抜けるまでの秒数
+    #[serde(default = "default_timeout")]
+    pub timeout: f64,
+    /// モード中のキーとコマンド
+    #[serde(default = "default_keys")]
+    pub keys: Vec<ModalKey>,
+}
+
+impl Default for ModalSettings {
+    fn default() -> Self {
+        Self {
+            leader: None,
+            timeout: default_timeout(),
+            keys: default_keys(),
+        }
+    }
+}
+
+impl ModalSettings {
+    pub fn timeout(&self) -> Duration {
+        Duration::try_from_secs_f64(self.timeout).unwrap_or_default()
+    }
+}
+
+/// モード中のキーと、押したときに実行するコマンド
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModalKey {
+    pub key: String,
+    pub command: String,
+}
+
+fn default_timeout() -> f64 {
+    3.0
+}
+
+/// h/j/k/l で移動、shift+h/j/k/l で右下の辺を動かしてリサイズ、n / shift+n でディスプレイを移り、
+/// `.` で直前の操作を繰り返す
+pub fn default_keys() -> Vec<ModalKey> {
+    let keys = [
+        ("h", "move left"),
+        ("j", "move down"),
+        ("k", "move up"),
+        ("l", "move right"),
+        ("shift+h", "resize_right_to_left"),
+        ("shift+j", "resize_bottom_to_bottom"),
+        ("shift+k", "resize_bottom_to_top"),
+        ("shift+l", "resize_right_to_right"),
+        ("n", "screen next"),
+        ("shift+n", "screen previous"),
+        (".", "repeat_last"),
+    ];
+
+    keys.into_iter()
+        .map(|(key, command)| ModalKey {
+            key: key.to_string(),
+            command: command.to_string(),
+        })
+        .collect()
+}
+
+/// モード中の入力
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input {
+    /// リーダーキー。`frame` はその時点のウィンドウのフレーム
+    Leader(Frame),
+    /// `keys` のキー
+    Key(Script),
+    /// 次の操作の回数に加える数字（`5l` の `5`）
+    Digit(u8),
+    /// `Escape`
+    Cancel,
+    /// `Return`
+    Commit,
+    /// 時間の経過を確認する
+    Tick,
+}
+
+/// 入力に応じて呼び出し側が行うこと
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+    /// モードに入った。一時的なキーを登録する
+    Enter,
+    /// コマンドを実行する。`count` は直前に入力された回数
+    Run { script: Script, count: Option<u32> },
+    /// モードを抜けた。一時的なキーを外し、`restore` があればそのフレームに戻す
+    Exit { restore: Option<Frame> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    Active {
+        /// モードに入ったときのフレーム
+        origin: Frame,
+        /// この時刻まで入力がなければ確定して抜ける
+        deadline: Instant,
+        /// 入力中の回数
+        count: Option<u32>,
+    },
+}
+
+/// リサイズモードの状態遷移
+///
+/// キーの登録やウィンドウの操作は行わず、行うべきことを `Effect` として返す
+#[derive(Debug, Clone)]
+pub struct ResizeMode {
+    timeout: Duration,
+    state: State,
+}
+
+impl ResizeMode {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            state: State::Idle,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        matches!(self.state, State::Active { .. })
+    }
+
+    /// モードを自動で抜ける時刻
+    pub fn deadline(&self) -> Option<Instant> {
+        match self.state {
+            State::Idle => None,
+            State::Active { deadline, .. } => Some(deadline),
+        }
+    }
+
+    /// `now` 時点の `input` を処理する
+    pub fn handle(&mut self, input: Input, now: Instant) -> Option<Effect> {
+        let State::Active {
+            origin,
+            deadline,
+            count,
+        } = self.state
+        else {
+            return match input {
+                Input::Leader(origin) => {
+                    self.state = State::Active {
+                        origin,
+                        deadline: now + self.timeout,
+                        count: None,
+                    };
+                    Some(Effect::Enter)
+                }
+                _ => None,
+            };
+        };
+
+        match input {
+            Input::Key(script) => {
+                self.state = State::Active {
+                    origin,
+                    deadline: now + self.timeout,
+                    count: None,
+                };
+                Some(Effect::Run {
+                    script,
+                    count: count.filter(|&count| count > 0),
+                })
+            }
+            Input::Digit(digit) => {
+                let count = count.unwrap_or(0) * 10 + u32::from(digit);
+                self.state = State::Active {
+                    origin,
+                    deadline: now + self.timeout,
+                    count: Some(count.min(MAX_COUNT)),
+                };
+                None
+            }
+            Input::Cancel => self.exit(Some(origin)),
+            // リーダーキーをもう一度押したときも確定して抜ける
+            Input::Leader(_) | Input::Commit => self.exit(None),
+            Input::Tick if now >= deadline => self.exit(None),
+            Input::Tick => None,
+        }
+    }
+
+    fn exit(&mut self, restore: Option<Frame>) -> Option<Effect> {
+        self.state = State::Idle;
+        Some(Effect::Exit { restore })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(3);
+
+    fn origin() -> Frame {
+        Frame::new(10.0, 20.0, 300.0, 200.0)
+    }
+
+    fn key(source: &str) -> Input {
+        Input::Key(source.parse().unwrap())
+    }
+
+    fn run(source: &str, count: Option<u32>) -> Option<Effect> {
+        Some(Effect::Run {
+            script: source.parse().unwrap(),
+            count,
+        })
+    }
+
+    /// モードに入った状態と、その時刻
+    fn entered() -> (ResizeMode, Instant) {
+        let mut mode = ResizeMode::new(TIMEOUT);
+        let now = Instant::now();
+        assert_eq!(
+            mode.handle(Input::Leader(origin()), now),
+            Some(Effect::Enter)
+        );
+        (mode, now)
+    }
+
+    #[test]
+    fn ignores_input_while_idle() {
+        let mut mode = ResizeMode::new(TIMEOUT);
+        let now = Instant::now();
+        for input in [
+            key("move left"),
+            Input::Digit(3),
+            Input::Cancel,
+            Input::Commit,
+            Input::Tick,
+        ] {
+            assert_eq!(mode.handle(input, now), None);
+        }
+        assert!(!mode.is_active());
+        assert_eq!(mode.deadline(), None);
+    }
+
+    #[test]
+    fn runs_keys_until_committed() {
+        let (mut mode, now) = entered();
+        assert!(mode.is_active());
+        assert_eq!(mode.deadline(), Some(now + TIMEOUT));
+
+        assert_eq!(mode.handle(key("move left"), now), run("move left", None));
+        assert_eq!(mode.handle(key("move up"), now), run("move up", None));
+        assert_eq!(
+            mode.handle(Input::Commit, now),
+            Some(Effect::Exit { restore: None })
+        );
+        assert!(!mode.is_active());
+    }
+
+    #[test]
+    fn exits() {
+        let cases = [
+            (Input::Cancel, Some(origin())),
+            (Input::Commit, None),
+            (Input::Leader(Frame::new(0.0, 0.0, 1.0, 1.0)), None),
+        ];
+        for (input, restore) in cases {
+            let (mut mode, now) = entered();
+            mode.handle(key("move left"), now);
+            assert_eq!(
+                mode.handle(input.clone(), now),
+                Some(Effect::Exit { restore }),
+                "{input:?}"
+            );
+            assert!(!mode.is_active());
+        }
+    }
+
+    #[test]
+    fn collects_counts() {
+        // (入力した数字, 実行に渡る回数)
+        let cases: &[(&[u8], Option<u32>)] = &[
+            (&[], None),
+            (&[5], Some(5)),
+            (&[1, 2], Some(12)),
+            (&[0], None),
+            (&[0, 3], Some(3)),
+            (&[9, 9, 9, 9], Some(MAX_COUNT)),
+        ];
+        for (digits, count) in cases {
+            let (mut mode, now) = entered();
+            for digit in *digits {
+                assert_eq!(mode.handle(Input::Digit(*digit), now), None);
+            }
+            assert_eq!(
+                mode.handle(key("move right"), now),
+                run("move right", *count),
+                "{digits:?}"
+            );
+            // 回数は 1 回の実行だけに使う
+            assert_eq!(mode.handle(key("move right"), now), run("move right", None));
+        }
+    }
+
+    #[test]
+    fn times_out_after_the_last_input() {
+        let (mut mode, start) = entered();
+        let later = start + Duration::from_secs(2);
+        assert_eq!(mode.handle(Input::Tick, later), None);
+
+        // 入力のたびに期限が延びる
+        mode.handle(key("move left"), later);
+        assert_eq!(mode.deadline(), Some(later + TIMEOUT));
+        assert_eq!(mode.handle(Input::Tick, start + TIMEOUT), None);
+        assert!(mode.is_active());
+
+        mode.handle(Input::Digit(2), later + Duration::from_secs(1));
+        assert_eq!(
+            mode.deadline(),
+            Some(later + Duration::from_secs(1) + TIMEOUT)
+        );
+
+        assert_eq!(
+            mode.handle(Input::Tick, later + Duration::from_secs(1) + TIMEOUT),
+            Some(Effect::Exit { restore: None })
+        );
+        assert!(!mode.is_active());
+    }
+
+    #[test]
+    fn default_keys_parse() {
+        for key in default_keys() {
+            assert!(key.command.parse::<Script>().is_ok(), "{}", key.command);
+        }
+    }
+}
@@ -0,0 +1,581 @@
+use std::{
+    fmt, mem,
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    constraint::Constraint,
+    frame::{Direction, Edge, Frame},
+    outcome::OperationOutcome,
+    preferences::suggest,
+    preset::{self, Preset},
+    screen::{Screen, ScreenSelector, select_among},
+    step::Step,
+    window::{WindowBackend, WindowId},
+};
+
+/// アニメーションのコマ数
+const ANIMATION_FRAMES: u32 = 8;
+/// アニメーションの 1 コマの間隔
+const ANIMATION_INTERVAL: Duration = Duration::from_millis(16);
+
+/// ウィンドウに対する操作
+///
+/// 設定ファイルでは `REGISTRY` の `id`（引数を取るものは `id:<引数>`）で書く
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    ResizeLeftToLeft,
+    ResizeLeftToRight,
+    ResizeTopToTop,
+    ResizeTopToBottom,
+    ResizeRightToLeft,
+    ResizeRightToRight,
+    ResizeBottomToTop,
+    ResizeBottomToBottom,
+    /// `apply_preset:<name>`
+    ApplyPreset(String),
+    CyclePresets,
+    /// `move_to_screen:<screen>`
+    MoveToScreen(ScreenSelector),
+    /// 最後に実行した操作を同じパラメータでもう一度実行する
+    ///
+    /// 実行の履歴はアプリが持つので、`Operation::execute` では実行できない
+    RepeatLast,
+}
+
+/// 操作の分類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    Move,
+    Resize,
+    Preset,
+    General,
+}
+
+impl Category {
+    /// メニューなどに表示する名前
+    pub fn title(self) -> &'static str {
+        match self {
+            Category::Move => "Move",
+            Category::Resize => "Resize",
+            Category::Preset => "Presets",
+            Category::General => "General",
+        }
+    }
+}
+
+/// 操作の説明
+#[derive(Debug, Serialize)]
+pub struct OperationInfo {
+    pub id: &'static str,
+    pub description: &'static str,
+    pub category: Category,
+    /// `id:<引数>` の形で受け取る引数の名前
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parameter: Option<&'static str>,
+    /// 同梱のデフォルト設定で割り当てるキー
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_key: Option<&'static str>,
+    /// 操作の雛形。引数を取る操作は `Operation::with_parameter` で引数を埋める
+    #[serde(skip)]
+    pub operation: Operation,
+}
+
+/// すべての操作の一覧
+///
+/// 操作を追加するときは、ここに 1 行加えて `Operation::execute` に処理を書く
+pub const REGISTRY: &[OperationInfo] = &[
+    OperationInfo {
+        id: "move_left",
+        description: "Move the window left",
+        category: Category::Move,
+        parameter: None,
+        default_key: Some("command+option+h"),
+        operation: Operation::MoveLeft,
+    },
+    OperationInfo {
+        id: "move_right",
+        description: "Move the window right",
+        category: Category::Move,
+        parameter: None,
+        default_key: Some("command+option+l"),
+        operation: Operation::MoveRight,
+    },
+    OperationInfo {
+        id: "move_up",
+        description: "Move the window up",
+        category: Category::Move,
+        parameter: None,
+        default_key: Some("command+option+k"),
+        operation: Operation::MoveUp,
+    },
+    OperationInfo {
+        id: "move_down",
+        description: "Move the window down",
+        category: Category::Move,
+        parameter: None,
+        default_key: Some("command+option+j"),
+        operation: Operation::MoveDown,
+    },
+    OperationInfo {
+        id: "resize_left_to_left",
+        description: "Move the left edge left",
+        category: Category::Resize,
+        parameter: None,
+        default_key: Some("option+shift+h"),
+        operation: Operation::ResizeLeftToLeft,
+    },
+    OperationInfo {
+        id: "resize_left_to_right",
+        description: "Move the left edge right",
+        category: Category::Resize,
+        parameter: None,
+        default_key: Some("option+control+l"),
+        operation: Operation::ResizeLeftToRight,
+    },
+    OperationInfo {
+        id: "resize_top_to_top",
+        description: "Move the top edge up",
+        category: Category::Resize,
+        parameter: None,
+        default_key: Some("option+shift+k"),
+        operation: Operation::ResizeTopToTop,
+    },
+    OperationInfo {
+        id: "resize_top_to_bottom",
+        description: "Move the top edge down",
+        category: Category::Resize,
+        parameter: None,
+        default_key: Some("option+control+j"),
+        operation: Operation::ResizeTopToBottom,
+    },
+    OperationInfo {
+        id: "resize_right_to_left",
+        description: "Move the right edge left",
+        category: Category::Resize,
+        parameter: None,
+        default_key: Some("option+control+h"),
+        operation: Operation::ResizeRightToLeft,
+    },
+    OperationInfo {
+        id: "resize_right_to_right",
+        description: "Move the right edge right",
+        category: Category::Resize,
+        parameter: None,
+        default_key: Some("option+shift+l"),
+        operation: Operation::ResizeRightToRight,
+    },
+    OperationInfo {
+        id: "resize_bottom_to_top",
+        description: "Move the bottom edge up",
+        category: Category::Resize,
+        parameter: None,
+        default_key: Some("option+control+k"),
+        operation: Operation::ResizeBottomToTop,
+    },
+    OperationInfo {
+        id: "resize_bottom_to_bottom",
+        description: "Move the bottom edge down",
+        category: Category::Resize,
+        parameter: None,
+        default_key: Some("option+shift+j"),
+        operation: Operation::ResizeBottomToBottom,
+    },
+    OperationInfo {
+        id: "apply_preset",
+        description: "Apply a preset",
+        category: Category::Preset,
+        parameter: Some("preset name"),
+        default_key: None,
+        operation: Operation::ApplyPreset(String::new()),
+    },
+    OperationInfo {
+        id: "cycle_presets",
+        description: "Apply the next preset",
+        category: Category::Preset,
+        parameter: None,
+        default_key: None,
+        operation: Operation::CyclePresets,
+    },
+    OperationInfo {
+        id: "move_to_screen",
+        description: "Move the window to another display",
+        category: Category::Move,
+        parameter: Some("screen"),
+        default_key: None,
+        operation: Operation::MoveToScreen(ScreenSelector::Next),
+    },
+    OperationInfo {
+        id: "repeat_last",
+        description: "Repeat the last operation",
+        category: Category::General,
+        parameter: None,
+        default_key: None,
+        operation: Operation::RepeatLast,
+    },
+];
+
+impl Operation {
+    /// `REGISTRY` にあるこの操作の説明
+    pub fn info(&self) -> &'static OperationInfo {
+        REGISTRY
+            .iter()
+            .find(|info| mem::discriminant(&info.operation) == mem::discriminant(self))
+            .expect("every operation is listed in REGISTRY")
+    }
+
+    /// 操作の引数
+    pub fn parameter(&self) -> Option<String> {
+        match self {
+            Operation::ApplyPreset(name) => Some(name.clone()),
+            Operation::MoveToScreen(selector) => Some(selector.to_string()),
+            _ => None,
+        }
+    }
+
+    /// 引数を `parameter` に置き換えた操作
+    fn with_parameter(&self, parameter: &str) -> Result<Operation, String> {
+        match self {
+            Operation::ApplyPreset(_) => Ok(Operation::ApplyPreset(parameter.to_string())),
+            Operation::MoveToScreen(_) => parameter.parse().map(Operation::MoveToScreen),
+            _ => Ok(self.clone()),
+        }
+    }
+
+    /// `backend` を通して `window` に操作を実行する
+    ///
+    /// `presets` は `apply_preset` / `cycle_presets` で使うプリセットの一覧
+    pub fn execute(
+        &self,
+        backend: &dyn WindowBackend,
+        window: WindowId,
+        presets: &[Preset],
+        options: &OperationOptions,
+    ) -> anyhow::Result<OperationOutcome> {
+        let target = Target { backend, window };
+        match self {
+            Operation::MoveLeft => move_window(target, &Direction::Left, options),
+            Operation::MoveRight => move_window(target, &Direction::Right, options),
+            Operation::MoveUp => move_window(target, &Direction::Up, options),
+            Operation::MoveDown => move_window(target, &Direction::Down, options),
+            Operation::ResizeLeftToLeft => resize(target, Edge::Left, -1.0, options),
+            Operation::ResizeLeftToRight => resize(target, Edge::Left, 1.0, options),
+            Operation::ResizeRightToLeft => resize(target, Edge::Right, -1.0, options),
+            Operation::ResizeRightToRight => resize(target, Edge::Right, 1.0, options),
+            Operation::ResizeTopToTop => resize(target, Edge::Top, 1.0, options),
+            Operation::ResizeTopToBottom => resize(target, Edge::Top, -1.0, options),
+            Operation::ResizeBottomToBottom => resize(target, Edge::Bottom, 1.0, options),
+            Operation::ResizeBottomToTop => resize(target, Edge::Bottom, -1.0, options),
+            Operation::ApplyPreset(name) => {
+                apply_preset(target, preset::find(presets, name)?, options)
+            }
+            Operation::CyclePresets => cycle_presets(target, presets, options),
+            Operation::MoveToScreen(selector) => move_to_screen(target, *selector, options),
+            Operation::RepeatLast => Err(anyhow!("`repeat_last` can only be run from the app")),
+        }
+    }
+}
+
+/// 操作名が解釈できなかったときのエラー
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseOperationError {
+    Unknown {
+        name: String,
+        /// 綴りが近い操作名
+        suggestion: Option<&'static str>,
+    },
+    /// 引数を取る操作に引数がない
+    MissingParameter {
+        id: &'static str,
+        parameter: &'static str,
+    },
+    /// 引数を取らない操作に引数がある
+    UnexpectedParameter { id: &'static str },
+    /// 引数が解釈できない
+    InvalidParameter { id: &'static str, message: String },
+}
+
+impl fmt::Display for ParseOperationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseOperationError::Unknown { name, suggestion } => {
+                write!(f, "unknown operation `{name}`")?;
+                if let Some(suggestion) = suggestion {
+                    write!(f, " (did you mean `{suggestion}`?)")?;
+                }
+                Ok(())
+            }
+            ParseOperationError::MissingParameter { id, parameter } => {
+                write!(f, "`{id}` needs a {parameter}: `{id}:<{parameter}>`")
+            }
+            ParseOperationError::UnexpectedParameter { id } => {
+                write!(f, "`{id}` does not take a parameter")
+            }
+            ParseOperationError::InvalidParameter { id, message } => write!(f, "`{id}`: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseOperationError {}
+
+impl FromStr for Operation {
+    type Err = ParseOperationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (id, parameter) = match s.split_once(':') {
+            Some((id, parameter)) => (id, Some(parameter)),
+            None => (s, None),
+        };
+        let Some(info) = REGISTRY.iter().find(|info| info.id == id) else {
+            return Err(ParseOperationError::Unknown {
+                name: s.to_string(),
+                suggestion: suggest(id, REGISTRY.iter().map(|info| info.id)),
+            });
+        };
+
+        match (info.parameter, parameter) {
+            (Some(_), Some(parameter)) if !parameter.is_empty() => info
+                .operation
+                .with_parameter(parameter)
+                .map_err(|message| ParseOperationError::InvalidParameter {
+                    id: info.id,
+                    message,
+                }),
+            (Some(name), _) => Err(ParseOperationError::MissingParameter {
+                id: info.id,
+                parameter: name,
+            }),
+            (None, Some(_)) => Err(ParseOperationError::UnexpectedParameter { id: info.id }),
+            (None, None) => Ok(info.operation.clone()),
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.info().id)?;
+        if let Some(parameter) = self.parameter() {
+            write!(f, ":{parameter}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for Operation {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Operation {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// 1 回の操作に適用するパラメータ
+///
+/// グローバル設定をバインディングごとの設定で上書きしたもの
+#[derive(Debug, Clone, Copy)]
+pub struct OperationOptions {
+    pub step: Step,
+    pub constraint: Constraint,
+    pub animate: bool,
+    /// 相対指定の基準や制約に使うディスプレイ
+    pub screen: ScreenSelector,
+}
+
+/// 操作するウィンドウと、その読み書きに使うバックエンド
+#[derive(Clone, Copy)]
+pub struct Target<'a> {
+    pub backend: &'a dyn WindowBackend,
+    pub window: WindowId,
+}
+
+impl Target<'_> {
+    fn frame(self) -> anyhow::Result<Frame> {
+        self.backend.frame(self.window)
+    }
+
+    fn set_frame(self, frame: Frame) -> anyhow::Result<()> {
+        self.backend.set_frame(self.window, frame)
+    }
+
+    /// `frame` を基準に `selector` のディスプレイを選ぶ。先頭のディスプレイをメインとする
+    fn screen(self, selector: ScreenSelector, frame: &Frame) -> anyhow::Result<Screen> {
+        let screens = self.backend.screens()?;
+        let main = screens.first().map_or(0, |screen| screen.id);
+        select_among(&screens, main, selector, frame)
+    }
+}
+
+pub fn move_window(
+    target: Target<'_>,
+    direction: &Direction,
+    options: &OperationOptions,
+) -> anyhow::Result<OperationOutcome> {
+    let started = Instant::now();
+    let current = target.frame()?;
+    let screen = target.screen(options.screen, &current)?;
+    let step = options
+        .step
+        .resolve(direction.axis(), &screen.visible_frame, &current);
+
+    commit(
+        target,
+        current,
+        current.moved(direction, step),
+        &screen,
+        options,
+        started,
+    )
+}
+
+/// `sign` はリサイズ量の符号（辺を動かす向き）
+pub fn resize(
+    target: Target<'_>,
+    edge: Edge,
+    sign: f64,
+    options: &OperationOptions,
+) -> anyhow::Result<OperationOutcome> {
+    let started = Instant::now();
+    let current = target.frame()?;
+    let screen = target.screen(options.screen, &current)?;
+    let delta = options
+        .step
+        .resolve(edge.axis(), &screen.visible_frame, &current);
+
+    commit(
+        target,
+        current,
+        current.resized(edge, sign * delta),
+        &screen,
+        options,
+        started,
+    )
+}
+
+/// `options.screen` が指すディスプレイ上にプリセットを適用する
+pub fn apply_preset(
+    target: Target<'_>,
+    preset: &Preset,
+    options: &OperationOptions,
+) -> anyhow::Result<OperationOutcome> {
+    let started = Instant::now();
+    let current = target.frame()?;
+    let from = target.screen(ScreenSelector::Current, &current)?;
+    let to = target.screen(options.screen, &current)?;
+
+    let frame = preset.resolve(&current, &from.visible_frame, &to.visible_frame);
+    commit(target, current, frame, &to, options, started)
+}
+
+/// 現在のフレームに一致するプリセットの次のプリセットを適用する
+///
+/// どのプリセットにも一致しない場合は先頭のプリセットを適用する
+pub fn cycle_presets(
+    target: Target<'_>,
+    presets: &[Preset],
+    options: &OperationOptions,
+) -> anyhow::Result<OperationOutcome> {
+    let started = Instant::now();
+    let current = target.frame()?;
+    let from = target.screen(ScreenSelector::Current, &current)?;
+    let to = target.screen(options.screen, &current)?;
+
+    let index = preset::next_index(presets, &current, &from.visible_frame)
+        .ok_or_else(|| anyhow!("No presets configured"))?;
+    let frame = presets[index].resolve(&current, &from.visible_frame, &to.visible_frame);
+    commit(target, current, frame, &to, options, started)
+}
+
+/// ウィンドウを `selector` のディスプレイに移す
+///
+/// 可視領域内での相対的な位置を保ち、収まらない大きさは可視領域に合わせて縮める
+pub fn move_to_screen(
+    target: Target<'_>,
+    selector: ScreenSelector,
+    options: &OperationOptions,
+) -> anyhow::Result<OperationOutcome> {
+    let started = Instant::now();
+    let current = target.frame()?;
+    let from = target
+        .screen(ScreenSelector::Current, &current)?
+        .visible_frame;
+    let to = target.screen(selector, &current)?;
+    let visible = to.visible_frame;
+
+    let frame = Frame::new(
+        visible.x + (current.x - from.x) * visible.w / from.w,
+        visible.y + (current.y - from.y) * visible.h / from.h,
+        current.w.min(visible.w),
+        current.h.min(visible.h),
+    );
+    commit(
+        target,
+        current,
+        Constraint::Screen.apply(frame, &visible),
+        &to,
+        options,
+        started,
+    )
+}
+
+/// `frame` に制約を適用して設定し、読み直したフレームと合わせて結果を返す
+fn commit(
+    target: Target<'_>,
+    current: Frame,
+    frame: Frame,
+    screen: &Screen,
+    options: &OperationOptions,
+    started: Instant,
+) -> anyhow::Result<OperationOutcome> {
+    let applied = options.constraint.apply(frame, &screen.visible_frame);
+
+    if options.animate {
+        animate(target, current, applied)?;
+    } else {
+        target.set_frame(applied)?;
+    }
+
+    let after = target.frame()?;
+    Ok(OperationOutcome::new(
+        Some(target.window),
+        current,
+        frame,
+        applied,
+        after,
+        started,
+    ))
+}
+
+fn animate(target: Target<'_>, from: Frame, to: Frame) -> anyhow::Result<()> {
+    for i in 1..=ANIMATION_FRAMES {
+        // ease-out: 終わりに向かって減速させる
+        let t = 1.0 - (1.0 - f64::from(i) / f64::from(ANIMATION_FRAMES)).powi(3);
+        target.set_frame(Frame::new(
+            from.x + (to.x - from.x) * t,
+            from.y + (to.y - from.y) * t,
+            from.w + (to.w - from.w) * t,
+            from.h + (to.h - from.h) * t,
+        ))?;
+
+        if i < ANIMATION_FRAMES {
+            thread::sleep(ANIMATION_INTERVAL);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{frame::Frame, window::WindowId};
+
+/// 制約で変わったとみなす差（ポイント）
+const CONSTRAINED_TOLERANCE: f64 = 0.5;
+/// アプリに制限されたとみなす差（ポイント）。AX の丸めは許す
+const CLAMPED_TOLERANCE: f64 = 1.0;
+
+/// 1 回の操作の結果
+///
+/// 出力やログは呼び出し側で行う
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OperationOutcome {
+    /// バックエンドを通して操作したときのウィンドウの番号。フォーカスされているウィンドウを直接操作したときは `None`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window: Option<WindowId>,
+    pub before: Frame,
+    /// 操作が求めたフレーム（制約を適用する前）
+    pub requested: Frame,
+    /// 設定した後に読み直したフレーム
+    pub after: Frame,
+    /// 制約で `requested` を変えて設定した
+    pub constrained: bool,
+    /// アプリが位置や大きさを制限し、設定したフレームにならなかった
+    pub clamped: bool,
+    #[serde(rename = "elapsed_ms", with = "millis")]
+    pub elapsed: Duration,
+}
+
+impl OperationOutcome {
+    /// `applied` は制約を適用して実際に設定したフレーム、`started` は操作を始めた時刻
+    pub fn new(
+        window: Option<WindowId>,
+        before: Frame,
+        requested: Frame,
+        applied: Frame,
+        after: Frame,
+        started: Instant,
+    ) -> Self {
+        Self {
+            window,
+            before,
+            requested,
+            after,
+            constrained: !requested.approx_eq(&applied, CONSTRAINED_TOLERANCE),
+            clamped: !applied.approx_eq(&after, CLAMPED_TOLERANCE),
+            elapsed: started.elapsed(),
+        }
+    }
+}
+
+/// `10,10 400x300 -> 0,25 500x775 (constrained)` の形で書く
+impl fmt::Display for OperationOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> {}", self.before, self.after)?;
+        if self.constrained {
+            f.write_str(" (constrained)")?;
+        }
+        if self.clamped {
+            f.write_str(" (limited by the app)")?;
+        }
+        Ok(())
+    }
+}
+
+/// `Duration` をミリ秒の数値で書く
+mod millis {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(duration.as_secs_f64() * 1000.0)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let millis = f64::deserialize(deserializer)?;
+        Duration::try_from_secs_f64(millis / 1000.0).map_err(serde::de::Error::custom)
+    }
+}
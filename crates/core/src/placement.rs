@@ -0,0 +1,61 @@
+use std::time::Instant;
+
+use crate::{
+    constraint::Constraint,
+    outcome::OperationOutcome,
+    preferences::Preferences,
+    preset::{self, Preset},
+    screen::{ScreenSelector, select_among},
+    window::{WindowBackend, WindowEvent, WindowId},
+};
+
+/// `event` が新しいウィンドウなら、当てはまるルールの `place` に従って配置する
+///
+/// 配置したときはその結果を返す。ルールで無効にされているアプリは配置しない
+pub fn place_new_window(
+    backend: &dyn WindowBackend,
+    preferences: &Preferences,
+    event: &WindowEvent,
+) -> anyhow::Result<Option<OperationOutcome>> {
+    let WindowEvent::WindowCreated { window, app, .. } = event else {
+        return Ok(None);
+    };
+    let Some(rule) = preferences
+        .rule_for(app)
+        .map(|index| &preferences.rules[index])
+    else {
+        return Ok(None);
+    };
+    let Some(rule_place) = rule.place.as_ref().filter(|_| !rule.disabled) else {
+        return Ok(None);
+    };
+
+    let preset = preset::find(&preferences.presets, &rule_place.frame)?;
+    let constraint = rule.constraint.unwrap_or(preferences.constraint);
+    place(backend, *window, preset, rule_place.screen, constraint).map(Some)
+}
+
+/// `window` に `screen` のディスプレイ上で `preset` を適用する
+pub fn place(
+    backend: &dyn WindowBackend,
+    window: WindowId,
+    preset: &Preset,
+    screen: ScreenSelector,
+    constraint: Constraint,
+) -> anyhow::Result<OperationOutcome> {
+    let started = Instant::now();
+    let current = backend.frame(window)?;
+    let screens = backend.screens()?;
+    let main = screens.first().map_or(0, |screen| screen.id);
+    let from = select_among(&screens, main, ScreenSelector::Current, &current)?;
+    let to = select_among(&screens, main, screen, &current)?;
+
+    let target = preset.resolve(&current, &from.visible_frame, &to.visible_frame);
+    let applied = constraint.apply(target, &to.visible_frame);
+    backend.set_frame(window, applied)?;
+
+    let after = backend.frame(window)?;
+    let outcome = OperationOutcome::new(Some(window), current, target, applied, after, started);
+    log::debug!("Placed window {window} with {}: {outcome}", preset.name);
+    Ok(outcome)
+}
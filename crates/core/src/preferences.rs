@@ -0,0 +1,202 @@
+use std::{borrow::Cow, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{self, ConfigError, LoadOptions},
+    constraint::Constraint,
+    logging::LogLevel,
+    mode::ModalSettings,
+    operation::{Category, Operation, OperationOptions, REGISTRY},
+    preset::Preset,
+    rule::Rule,
+    screen::ScreenSelector,
+    sequence,
+    step::Step,
+    window::AppIdentity,
+};
+
+const DEFAULT_JSONC: &str = include_str!("data/default.jsonc");
+
+/// `candidates` の中から `input` に綴りが近いものを返す
+pub fn suggest<'a>(input: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    // 名前の長さの 1/3 程度までの打ち間違いを候補にする
+    let threshold = (input.chars().count() / 3).max(2);
+
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(input, candidate), candidate))
+        .filter(|(distance, _)| *distance <= threshold)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// レーベンシュタイン距離
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// キーと操作の対応
+///
+/// `operation` か `command` のどちらかを書く。
+/// `step` などを省略した場合はグローバル設定が使われる
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Binding {
+    /// `REGISTRY` の操作名
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operation: Option<String>,
+    /// コマンド言語で書いた処理（`"move left 50; resize right +10%"` など）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    pub key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub step: Option<Step>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub constraint: Option<Constraint>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub animate: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub screen: Option<ScreenSelector>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Preferences {
+    pub move_step: Step,
+    pub resize_step: Step,
+    #[serde(default)]
+    pub constraint: Constraint,
+    #[serde(default)]
+    pub animate: bool,
+    #[serde(default)]
+    pub presets: Vec<Preset>,
+    /// 省略時は `REGISTRY` の `default_key` を割り当てる
+    #[serde(default = "default_bindings")]
+    pub bindings: Vec<Binding>,
+    /// `"ctrl+option+w, h"` のようなキーの並びで、続きのキーを待つ秒数
+    #[serde(default = "default_sequence_timeout")]
+    pub sequence_timeout: f64,
+    /// リサイズモード
+    #[serde(default)]
+    pub modal: ModalSettings,
+    /// アプリごとのルール。最初に当てはまったものを使う
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    /// ログに書く水準
+    #[serde(default)]
+    pub log_level: LogLevel,
+}
+
+impl Preferences {
+    /// デフォルト設定にユーザー設定とプロファイルを重ねて読み込む
+    ///
+    /// 探し方は `config::resolve` を参照。ユーザー設定ファイルがない場合はデフォルト設定を返す
+    pub fn load(options: &LoadOptions) -> Result<Self, ConfigError> {
+        config::load(options)
+    }
+
+    pub fn sequence_timeout(&self) -> Duration {
+        Duration::try_from_secs_f64(self.sequence_timeout).unwrap_or_default()
+    }
+
+    /// `app` に当てはまる最初のルールの位置
+    pub fn rule_for(&self, app: &AppIdentity) -> Option<usize> {
+        self.rules.iter().position(|rule| rule.matcher.matches(app))
+    }
+
+    /// `app` に当てはまるルールを反映した設定
+    ///
+    /// ルールで無効にされていれば `None`。`app` が分からなければそのままの設定を返す
+    pub fn for_app(&self, app: Option<&AppIdentity>) -> Option<Cow<'_, Self>> {
+        let Some(index) = app.and_then(|app| self.rule_for(app)) else {
+            return Some(Cow::Borrowed(self));
+        };
+        let rule = &self.rules[index];
+        if rule.disabled {
+            return None;
+        }
+
+        let mut preferences = self.clone();
+        preferences.move_step = rule.move_step.unwrap_or(self.move_step);
+        preferences.resize_step = rule.resize_step.unwrap_or(self.resize_step);
+        preferences.constraint = rule.constraint.unwrap_or(self.constraint);
+        if let Some(bindings) = &rule.bindings {
+            preferences.bindings = bindings.clone();
+        }
+        Some(Cow::Owned(preferences))
+    }
+
+    /// `operation` に割り当てられている最初のバインディング
+    pub fn binding_for(&self, operation: &Operation) -> Option<&Binding> {
+        self.bindings.iter().find(|binding| {
+            binding
+                .operation
+                .as_deref()
+                .and_then(|name| name.parse::<Operation>().ok())
+                .is_some_and(|parsed| &parsed == operation)
+        })
+    }
+
+    /// `operation` を実行するときのパラメータ
+    ///
+    /// `binding` が指定されていれば、その設定でグローバル設定を上書きする
+    pub fn options_for(
+        &self,
+        operation: &Operation,
+        binding: Option<&Binding>,
+    ) -> OperationOptions {
+        let step = match operation.info().category {
+            Category::Resize => self.resize_step,
+            _ => self.move_step,
+        };
+
+        OperationOptions {
+            step: binding.and_then(|b| b.step).unwrap_or(step),
+            constraint: binding
+                .and_then(|b| b.constraint)
+                .unwrap_or(self.constraint),
+            animate: binding.and_then(|b| b.animate).unwrap_or(self.animate),
+            screen: binding.and_then(|b| b.screen).unwrap_or_default(),
+        }
+    }
+}
+
+fn default_sequence_timeout() -> f64 {
+    sequence::DEFAULT_TIMEOUT
+}
+
+/// `REGISTRY` の `default_key` から作るバインディング
+pub fn default_bindings() -> Vec<Binding> {
+    REGISTRY
+        .iter()
+        .filter_map(|info| {
+            Some(Binding {
+                operation: Some(info.id.to_string()),
+                command: None,
+                key: info.default_key?.to_string(),
+                step: None,
+                constraint: None,
+                animate: None,
+                screen: None,
+            })
+        })
+        .collect()
+}
+
+impl Default for Preferences {
+    /// 同梱の `default.jsonc` の設定
+    fn default() -> Self {
+        config::from_jsonc(DEFAULT_JSONC).expect("Cannot parse default.jsonc")
+    }
+}
@@ -0,0 +1,138 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::frame::Frame;
+
+/// 現在のフレームとプリセットが一致しているとみなす誤差（ポイント）
+const MATCH_TOLERANCE: f64 = 1.0;
+
+/// プリセットの位置・サイズの指定
+///
+/// JSON では数値（ポイント）か `"50%"` のような文字列（画面の可視領域に対する割合）で書く
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+    /// ポイント単位の絶対値
+    Points(f64),
+    /// 画面の可視領域に対する割合（0〜100）
+    Percent(f64),
+}
+
+impl Length {
+    /// `total`（画面の可視領域の幅または高さ）に対する実際の長さを求める
+    pub fn resolve(self, total: f64) -> f64 {
+        match self {
+            Length::Points(points) => points,
+            Length::Percent(percent) => total * percent / 100.0,
+        }
+    }
+}
+
+impl FromStr for Length {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(percent) = s.strip_suffix('%') {
+            return percent
+                .trim()
+                .parse()
+                .map(Length::Percent)
+                .map_err(|_| format!("invalid percentage: {s}"));
+        }
+        s.strip_suffix("pt")
+            .unwrap_or(s)
+            .trim()
+            .parse()
+            .map(Length::Points)
+            .map_err(|_| format!("invalid length: {s}"))
+    }
+}
+
+impl fmt::Display for Length {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Length::Points(points) => write!(f, "{points}"),
+            Length::Percent(percent) => write!(f, "{percent}%"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Length {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(f64),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Number(points) => Ok(Length::Points(points)),
+            Raw::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+impl Serialize for Length {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Length::Points(points) => serializer.serialize_f64(*points),
+            Length::Percent(_) => serializer.collect_str(self),
+        }
+    }
+}
+
+/// 名前付きのウィンドウサイズ・フレーム
+///
+/// `x` / `y` は画面の可視領域の左上からのオフセットで、省略すると可視領域内での現在の位置を保つ
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x: Option<Length>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub y: Option<Length>,
+    pub width: Length,
+    pub height: Length,
+}
+
+impl Preset {
+    /// `from` の可視領域にある `current` のウィンドウを、`to` の可視領域上で
+    /// プリセットに合わせた後のフレームを求める
+    pub fn resolve(&self, current: &Frame, from: &Frame, to: &Frame) -> Frame {
+        Frame::new(
+            to.x + self.x.map_or(current.x - from.x, |x| x.resolve(to.w)),
+            to.y + self.y.map_or(current.y - from.y, |y| y.resolve(to.h)),
+            self.width.resolve(to.w),
+            self.height.resolve(to.h),
+        )
+    }
+}
+
+pub fn find<'a>(presets: &'a [Preset], name: &str) -> anyhow::Result<&'a Preset> {
+    presets
+        .iter()
+        .find(|preset| preset.name == name)
+        .ok_or_else(|| anyhow!("Unknown preset: {name}"))
+}
+
+/// 可視領域 `visible` 上で現在のフレームに一致するプリセットの次の添字を返す
+///
+/// どのプリセットにも一致しない場合は先頭を返す。プリセットが空なら `None`
+pub fn next_index(presets: &[Preset], current: &Frame, visible: &Frame) -> Option<usize> {
+    if presets.is_empty() {
+        return None;
+    }
+
+    let index = presets
+        .iter()
+        .position(|preset| {
+            preset
+                .resolve(current, visible, visible)
+                .approx_eq(current, MATCH_TOLERANCE)
+        })
+        .map_or(0, |index| (index + 1) % presets.len());
+    Some(index)
+}
@@ -0,0 +1,193 @@
+use std::fmt;
+
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    constraint::Constraint, preferences::Binding, screen::ScreenSelector, step::Step,
+    window::AppIdentity,
+};
+
+/// 最前面のアプリごとに設定を切り替えるルール
+///
+/// 書かれていない項目はグローバル設定がそのまま使われる
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rule {
+    #[serde(rename = "match")]
+    pub matcher: AppMatcher,
+    /// `true` ならこのアプリでは何も実行しない（ゲーム・仮想マシン・リモートデスクトップなど）
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub move_step: Option<Step>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resize_step: Option<Step>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub constraint: Option<Constraint>,
+    /// グローバルの `bindings` の代わりに使うバインディング
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bindings: Option<Vec<Binding>>,
+    /// 新しく開いたウィンドウを自動で配置する
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub place: Option<Place>,
+}
+
+/// 新しく開いたウィンドウの配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Place {
+    /// 合わせるプリセットの名前
+    pub frame: String,
+    /// 配置するディスプレイ。省略するとウィンドウが開いたディスプレイ
+    #[serde(default)]
+    pub screen: ScreenSelector,
+}
+
+/// ルールを適用するアプリの条件
+///
+/// 書かれた項目がすべて当てはまるときに適用する
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AppMatcher {
+    /// バンドル ID（大文字・小文字は区別しない）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bundle_id: Option<String>,
+    /// アプリ名（大文字・小文字は区別しない）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// ウィンドウのタイトルに対する正規表現
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<TitlePattern>,
+}
+
+/// ウィンドウのタイトルに対する正規表現
+///
+/// 設定を読み込んだときに 1 回だけコンパイルする。誤りは `validation::validate_rules` で報告する
+#[derive(Debug, Clone)]
+pub struct TitlePattern {
+    source: String,
+    regex: Result<Regex, String>,
+}
+
+impl TitlePattern {
+    pub fn new(source: impl Into<String>) -> Self {
+        let source = source.into();
+        let regex = Regex::new(&source).map_err(|err| {
+            // 構文エラーは複数行になるので、最後の行の説明だけを使う
+            let err = err.to_string();
+            let reason = err.lines().last().unwrap_or_default();
+            reason.trim_start_matches("error: ").to_string()
+        });
+        Self { source, regex }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// 正規表現として解釈できなかった理由
+    pub fn error(&self) -> Option<&str> {
+        self.regex.as_ref().err().map(String::as_str)
+    }
+
+    /// 誤りのある正規表現は何にも当てはまらない
+    pub fn is_match(&self, title: &str) -> bool {
+        self.regex.as_ref().is_ok_and(|regex| regex.is_match(title))
+    }
+}
+
+impl fmt::Display for TitlePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Serialize for TitlePattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for TitlePattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(TitlePattern::new)
+    }
+}
+
+impl AppMatcher {
+    /// 条件が 1 つも書かれていない
+    pub fn is_empty(&self) -> bool {
+        self.bundle_id.is_none() && self.name.is_none() && self.title.is_none()
+    }
+
+    pub fn matches(&self, app: &AppIdentity) -> bool {
+        fn same(expected: &Option<String>, actual: &Option<String>) -> bool {
+            match (expected, actual) {
+                (None, _) => true,
+                (Some(expected), Some(actual)) => expected.eq_ignore_ascii_case(actual),
+                (Some(_), None) => false,
+            }
+        }
+
+        let title = match (&self.title, &app.title) {
+            (None, _) => true,
+            (Some(pattern), Some(title)) => pattern.is_match(title),
+            (Some(_), None) => false,
+        };
+
+        !self.is_empty()
+            && same(&self.bundle_id, &app.bundle_id)
+            && same(&self.name, &app.name)
+            && title
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(bundle_id: &str, name: &str, title: Option<&str>) -> AppIdentity {
+        AppIdentity {
+            bundle_id: Some(bundle_id.to_string()),
+            name: Some(name.to_string()),
+            title: title.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn matches_every_written_condition() {
+        let safari = app("com.apple.Safari", "Safari", Some("Inbox – Mail"));
+        let cases = [
+            (r#"{}"#, false),
+            (r#"{"bundle_id": "COM.APPLE.SAFARI"}"#, true),
+            (r#"{"name": "safari"}"#, true),
+            (
+                r#"{"name": "Safari", "bundle_id": "com.apple.Terminal"}"#,
+                false,
+            ),
+            (r#"{"title": "^Inbox"}"#, true),
+            (r#"{"title": "^Mail"}"#, false),
+            (r#"{"name": "Safari", "title": "Mail$"}"#, true),
+            // 誤りのある正規表現は何にも当てはまらない
+            (r#"{"title": "(Inbox"}"#, false),
+        ];
+        for (matcher, expected) in cases {
+            let matcher: AppMatcher = serde_json::from_str(matcher).unwrap();
+            assert_eq!(matcher.matches(&safari), expected, "{matcher:?}");
+        }
+
+        let untitled = app("com.apple.Safari", "Safari", None);
+        let matcher: AppMatcher = serde_json::from_str(r#"{"title": ".*"}"#).unwrap();
+        assert!(!matcher.matches(&untitled));
+    }
+
+    #[test]
+    fn compiles_title_patterns_once() {
+        let pattern = TitlePattern::new("^日本語");
+        assert_eq!(pattern.error(), None);
+        assert!(pattern.is_match("日本語のタイトル"));
+        assert_eq!(serde_json::to_string(&pattern).unwrap(), r#""^日本語""#);
+
+        let invalid = TitlePattern::new("(Inbox");
+        assert_eq!(invalid.error(), Some("unclosed group"));
+        assert_eq!(invalid.to_string(), "(Inbox");
+    }
+}
@@ -0,0 +1,179 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::anyhow;
+use core_graphics::display::CGDisplay;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::frame::Frame;
+
+unsafe extern "C" {
+    // HIToolbox (Carbon) が返すメニューバーの高さ
+    fn GetMBarHeight() -> i16;
+}
+
+/// 接続されているディスプレイ 1 枚分の情報
+///
+/// 座標は AX API と同じグローバル座標（メインディスプレイ左上が原点、y は下向き）
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Screen {
+    pub id: u32,
+    /// ディスプレイ全体の領域
+    pub frame: Frame,
+    /// メニューバーを除いた領域（Dock は考慮しない）
+    pub visible_frame: Frame,
+}
+
+pub fn screens() -> anyhow::Result<Vec<Screen>> {
+    let ids = CGDisplay::active_displays()
+        .map_err(|err| anyhow!("Failed to list displays (CGError {err})"))?;
+    let menu_bar_height = f64::from(unsafe { GetMBarHeight() });
+
+    Ok(ids
+        .into_iter()
+        .map(|id| {
+            let bounds = CGDisplay::new(id).bounds();
+            let frame = Frame::new(
+                bounds.origin.x,
+                bounds.origin.y,
+                bounds.size.width,
+                bounds.size.height,
+            );
+            let visible_frame = Frame::new(
+                frame.x,
+                frame.y + menu_bar_height,
+                frame.w,
+                frame.h - menu_bar_height,
+            );
+            Screen {
+                id,
+                frame,
+                visible_frame,
+            }
+        })
+        .collect())
+}
+
+/// `frame` と最も広く重なっているディスプレイを返す
+///
+/// どのディスプレイとも重ならない場合はメインディスプレイを返す
+pub fn screen_for(frame: &Frame) -> anyhow::Result<Screen> {
+    current_among(&screens()?, CGDisplay::main().id, frame)
+        .ok_or_else(|| anyhow!("No display found"))
+}
+
+/// `screens` の中で `frame` と最も広く重なっているディスプレイ。`main` はメインディスプレイの ID
+fn current_among(screens: &[Screen], main: u32, frame: &Frame) -> Option<Screen> {
+    screens
+        .iter()
+        .copied()
+        .filter(|screen| screen.frame.intersection_area(frame) > 0.0)
+        .max_by(|a, b| {
+            a.frame
+                .intersection_area(frame)
+                .total_cmp(&b.frame.intersection_area(frame))
+        })
+        .or_else(|| screens.iter().copied().find(|screen| screen.id == main))
+        .or_else(|| screens.first().copied())
+}
+
+/// 操作の基準にするディスプレイの指定
+///
+/// 設定ファイルでは `"current"` / `"main"` / `"next"` / `"previous"` か、1 始まりの番号で書く
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScreenSelector {
+    /// ウィンドウがあるディスプレイ
+    #[default]
+    Current,
+    Main,
+    Next,
+    Previous,
+    /// `screens()` の並び順で 1 始まり
+    Index(usize),
+}
+
+impl FromStr for ScreenSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "current" => Ok(ScreenSelector::Current),
+            "main" => Ok(ScreenSelector::Main),
+            "next" => Ok(ScreenSelector::Next),
+            "prev" | "previous" => Ok(ScreenSelector::Previous),
+            other => match other.parse() {
+                Ok(index) if index > 0 => Ok(ScreenSelector::Index(index)),
+                _ => Err(format!("invalid screen: {s}")),
+            },
+        }
+    }
+}
+
+impl fmt::Display for ScreenSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScreenSelector::Current => f.write_str("current"),
+            ScreenSelector::Main => f.write_str("main"),
+            ScreenSelector::Next => f.write_str("next"),
+            ScreenSelector::Previous => f.write_str("previous"),
+            ScreenSelector::Index(index) => write!(f, "{index}"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ScreenSelector {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Index(usize),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Index(0) => Err(serde::de::Error::custom("screen index starts at 1")),
+            Raw::Index(index) => Ok(ScreenSelector::Index(index)),
+            Raw::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+impl Serialize for ScreenSelector {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ScreenSelector::Index(index) => serializer.serialize_u64(*index as u64),
+            _ => serializer.collect_str(self),
+        }
+    }
+}
+
+/// `window` の位置を基準に、`selector` が指すディスプレイを返す
+pub fn select(selector: ScreenSelector, window: &Frame) -> anyhow::Result<Screen> {
+    select_among(&screens()?, CGDisplay::main().id, selector, window)
+}
+
+/// `select` と同じ規則で `screens` の中から選ぶ。`main` はメインディスプレイの ID
+pub fn select_among(
+    screens: &[Screen],
+    main: u32,
+    selector: ScreenSelector,
+    window: &Frame,
+) -> anyhow::Result<Screen> {
+    let current =
+        current_among(screens, main, window).ok_or_else(|| anyhow!("No display found"))?;
+    let position = screens
+        .iter()
+        .position(|screen| screen.id == current.id)
+        .unwrap_or(0);
+
+    let screen = match selector {
+        ScreenSelector::Current => Some(current),
+        ScreenSelector::Main => screens.iter().copied().find(|screen| screen.id == main),
+        ScreenSelector::Next => screens.get((position + 1) % screens.len()).copied(),
+        ScreenSelector::Previous => screens
+            .get((position + screens.len() - 1) % screens.len())
+            .copied(),
+        ScreenSelector::Index(index) => screens.get(index - 1).copied(),
+    };
+
+    screen.ok_or_else(|| anyhow!("No display for screen selector: {selector}"))
+}
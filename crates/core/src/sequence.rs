@@ -0,0 +1,373 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use global_hotkey::hotkey::HotKey;
+
+use crate::{
+    mode::{DIGIT_KEYS, MAX_COUNT},
+    validation::{KeyError, parse_key},
+};
+
+/// 並びの続きを待つ時間の既定値（秒）
+pub const DEFAULT_TIMEOUT: f64 = 1.0;
+
+/// `"ctrl+option+w, h"` のように `,` で区切ったキーの並び
+///
+/// 先頭のキーを押してから、続くキーを順に押す。1 つだけなら通常のホットキーと同じ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeySequence(pub Vec<HotKey>);
+
+impl KeySequence {
+    /// キー文字列を解釈する。エラーの位置は `key` 全体に対する位置
+    pub fn parse(key: &str) -> Result<Self, KeyError> {
+        split(key)
+            .into_iter()
+            .map(|(offset, part)| {
+                parse_key(part).map_err(|error| {
+                    KeyError::new(offset + error.position, error.message).located(key)
+                })
+            })
+            .collect::<Result<_, _>>()
+            .map(KeySequence)
+    }
+
+    /// 最初に押すキー
+    pub fn first(&self) -> HotKey {
+        self.0[0]
+    }
+
+    /// `other` がこの並びで始まるかどうか（同じ並びも含む）
+    pub fn is_prefix_of(&self, other: &KeySequence) -> bool {
+        other.0.starts_with(&self.0)
+    }
+}
+
+impl fmt::Display for KeySequence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, key) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{key}")?;
+        }
+        Ok(())
+    }
+}
+
+/// `,` で区切り、前後の空白を除いた各部分とその開始位置を返す
+///
+/// `command+,` のように `+` の直後や先頭にある `,` はキー名として扱う
+fn split(key: &str) -> Vec<(usize, &str)> {
+    let trimmed = |start: usize, end: usize| {
+        let part = &key[start..end];
+        let offset = part.len() - part.trim_start().len();
+        (start + offset, part.trim())
+    };
+    let mut parts = Vec::new();
+    let mut start = 0;
+
+    for (i, c) in key.char_indices() {
+        let part = key[start..i].trim();
+        if c == ',' && !part.is_empty() && !part.ends_with('+') {
+            parts.push(trimmed(start, i));
+            start = i + 1;
+        }
+    }
+    parts.push(trimmed(start, key.len()));
+    parts
+}
+
+/// キーの並びの入力を進めた結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// 続きを待っている。`listen` のキーを一時的に登録する
+    Pending { listen: Vec<HotKey> },
+    /// `index` 番目の並びが揃った。`count` は途中で入力された回数
+    Matched { index: usize, count: Option<u32> },
+    /// 待つのをやめた。一時的に登録したキーを外す
+    Reset,
+}
+
+#[derive(Debug, Clone)]
+struct Pending {
+    typed: Vec<HotKey>,
+    count: Option<u32>,
+    deadline: Instant,
+}
+
+/// ホットキーのイベントからキーの並びを見つける
+///
+/// キーの登録や実行は行わず、行うべきことを `Outcome` として返す。
+/// 並びの途中で数字キーを押すと、揃ったときの回数になる（`ctrl+w, 5, l`）
+#[derive(Debug, Clone)]
+pub struct SequenceMatcher {
+    sequences: Vec<KeySequence>,
+    digits: Vec<HotKey>,
+    timeout: Duration,
+    pending: Option<Pending>,
+}
+
+impl SequenceMatcher {
+    pub fn new(sequences: Vec<KeySequence>, timeout: Duration) -> Self {
+        let digits = DIGIT_KEYS
+            .iter()
+            .map(|key| parse_key(key).expect("Cannot parse a digit key"))
+            .collect();
+        Self {
+            sequences,
+            digits,
+            timeout,
+            pending: None,
+        }
+    }
+
+    /// 続きを待つのをやめる時刻
+    pub fn deadline(&self) -> Option<Instant> {
+        self.pending.as_ref().map(|pending| pending.deadline)
+    }
+
+    /// `now` に押された `key` を処理する
+    pub fn press(&mut self, key: HotKey, now: Instant) -> Option<Outcome> {
+        let Some(pending) = self.pending.take() else {
+            return self.advance(vec![key], None, now);
+        };
+
+        let mut typed = pending.typed.clone();
+        typed.push(key);
+        if let Some(outcome) = self.advance(typed, pending.count, now) {
+            return Some(outcome);
+        }
+
+        if let Some(digit) = self.digits.iter().position(|digit| *digit == key) {
+            let count = pending.count.unwrap_or(0) * 10 + digit as u32;
+            self.pending = Some(Pending {
+                count: Some(count.min(MAX_COUNT)),
+                deadline: now + self.timeout,
+                ..pending
+            });
+            return None;
+        }
+
+        // 並びにないキーなら、そのキーから新しく始める
+        Some(self.advance(vec![key], None, now).unwrap_or(Outcome::Reset))
+    }
+
+    /// 時間切れなら待つのをやめる
+    pub fn tick(&mut self, now: Instant) -> Option<Outcome> {
+        match &self.pending {
+            Some(pending) if now >= pending.deadline => {
+                self.pending = None;
+                Some(Outcome::Reset)
+            }
+            _ => None,
+        }
+    }
+
+    /// `typed` まで押された状態に進める。どの並びにも当てはまらなければ `None`
+    fn advance(&mut self, typed: Vec<HotKey>, count: Option<u32>, now: Instant) -> Option<Outcome> {
+        if let Some(index) = self
+            .sequences
+            .iter()
+            .position(|sequence| sequence.0 == typed)
+        {
+            self.pending = None;
+            return Some(Outcome::Matched {
+                index,
+                count: count.filter(|&count| count > 0),
+            });
+        }
+
+        let mut listen: Vec<HotKey> = Vec::new();
+        for sequence in &self.sequences {
+            if sequence.0.len() > typed.len()
+                && sequence.0.starts_with(&typed)
+                && !listen.contains(&sequence.0[typed.len()])
+            {
+                listen.push(sequence.0[typed.len()]);
+            }
+        }
+        if listen.is_empty() {
+            return None;
+        }
+        for digit in &self.digits {
+            if !listen.contains(digit) {
+                listen.push(*digit);
+            }
+        }
+
+        self.pending = Some(Pending {
+            typed,
+            count,
+            deadline: now + self.timeout,
+        });
+        Some(Outcome::Pending { listen })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    fn key(text: &str) -> HotKey {
+        parse_key(text).unwrap()
+    }
+
+    fn matcher() -> SequenceMatcher {
+        let sequences = ["ctrl+w, h", "ctrl+w, l", "ctrl+w, g, g", "ctrl+e, h"]
+            .into_iter()
+            .map(|text| KeySequence::parse(text).unwrap())
+            .collect();
+        SequenceMatcher::new(sequences, TIMEOUT)
+    }
+
+    /// 押したキーを順に処理し、最後の結果を返す
+    fn press_all(matcher: &mut SequenceMatcher, keys: &[&str], now: Instant) -> Option<Outcome> {
+        keys.iter()
+            .map(|text| matcher.press(key(text), now))
+            .last()
+            .flatten()
+    }
+
+    #[test]
+    fn parses_sequences() {
+        let cases: &[(&str, &[&str])] = &[
+            ("ctrl+w", &["ctrl+w"]),
+            ("ctrl+w, h", &["ctrl+w", "h"]),
+            ("  ctrl+w ,h  ", &["ctrl+w", "h"]),
+            ("command+,, h", &["command+,", "h"]),
+            (",", &[","]),
+        ];
+        for (text, expected) in cases {
+            let expected: Vec<HotKey> = expected.iter().map(|text| key(text)).collect();
+            assert_eq!(
+                KeySequence::parse(text),
+                Ok(KeySequence(expected)),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn reports_errors_in_the_whole_string() {
+        let error = KeySequence::parse("ctrl+w, nokey").unwrap_err();
+        assert_eq!(error.position, 8);
+        assert_eq!(error.column, 9);
+        assert!(KeySequence::parse("ctrl+w,").is_err());
+    }
+
+    #[test]
+    fn prefixes() {
+        let short = KeySequence::parse("ctrl+w").unwrap();
+        let long = KeySequence::parse("ctrl+w, h").unwrap();
+        assert!(short.is_prefix_of(&long));
+        assert!(long.is_prefix_of(&long));
+        assert!(!long.is_prefix_of(&short));
+    }
+
+    #[test]
+    fn matches_sequences() {
+        let cases: &[(&[&str], usize)] = &[
+            (&["ctrl+w", "h"], 0),
+            (&["ctrl+w", "l"], 1),
+            (&["ctrl+w", "g", "g"], 2),
+            (&["ctrl+e", "h"], 3),
+        ];
+        for (keys, index) in cases {
+            let mut matcher = matcher();
+            let outcome = press_all(&mut matcher, keys, Instant::now());
+            assert_eq!(
+                outcome,
+                Some(Outcome::Matched {
+                    index: *index,
+                    count: None
+                }),
+                "{keys:?}"
+            );
+            assert_eq!(matcher.deadline(), None);
+        }
+    }
+
+    #[test]
+    fn listens_for_the_next_keys_and_digits() {
+        let mut matcher = matcher();
+        let now = Instant::now();
+        let Some(Outcome::Pending { listen }) = matcher.press(key("ctrl+w"), now) else {
+            panic!("expected to wait for the next key");
+        };
+        let mut expected = vec![key("h"), key("l"), key("g")];
+        expected.extend(DIGIT_KEYS.iter().map(|digit| key(digit)));
+        assert_eq!(listen, expected);
+        assert_eq!(matcher.deadline(), Some(now + TIMEOUT));
+    }
+
+    #[test]
+    fn collects_counts() {
+        let cases: &[(&[&str], Option<u32>)] = &[
+            (&["ctrl+w", "5", "h"], Some(5)),
+            (&["ctrl+w", "1", "2", "h"], Some(12)),
+            (&["ctrl+w", "0", "h"], None),
+            (&["ctrl+w", "9", "9", "9", "9", "h"], Some(MAX_COUNT)),
+            (&["ctrl+w", "g", "3", "g"], Some(3)),
+        ];
+        for (keys, count) in cases {
+            let mut matcher = matcher();
+            let outcome = press_all(&mut matcher, keys, Instant::now());
+            let Some(Outcome::Matched { count: matched, .. }) = outcome else {
+                panic!("{keys:?}: {outcome:?}");
+            };
+            assert_eq!(matched, *count, "{keys:?}");
+        }
+    }
+
+    #[test]
+    fn resets_on_other_keys() {
+        let mut matcher = matcher();
+        let now = Instant::now();
+        assert_eq!(
+            press_all(&mut matcher, &["ctrl+w", "x"], now),
+            Some(Outcome::Reset)
+        );
+        assert_eq!(matcher.deadline(), None);
+
+        // 別の並びの最初のキーならそこから始め直す
+        assert!(matches!(
+            press_all(&mut matcher, &["ctrl+w", "ctrl+e"], now),
+            Some(Outcome::Pending { .. })
+        ));
+        assert_eq!(
+            matcher.press(key("h"), now),
+            Some(Outcome::Matched {
+                index: 3,
+                count: None
+            })
+        );
+
+        // 並びの外のキーだけなら何もしない
+        assert_eq!(matcher.press(key("x"), now), None);
+    }
+
+    #[test]
+    fn times_out() {
+        let mut matcher = matcher();
+        let start = Instant::now();
+        matcher.press(key("ctrl+w"), start);
+        assert_eq!(matcher.tick(start + TIMEOUT / 2), None);
+
+        // 数字を押すと待つ時間が延びる
+        let later = start + TIMEOUT / 2;
+        assert_eq!(matcher.press(key("2"), later), None);
+        assert_eq!(matcher.deadline(), Some(later + TIMEOUT));
+        assert_eq!(matcher.tick(start + TIMEOUT), None);
+
+        assert_eq!(matcher.tick(later + TIMEOUT), Some(Outcome::Reset));
+        assert_eq!(matcher.deadline(), None);
+        assert_eq!(matcher.tick(later + TIMEOUT * 2), None);
+
+        // 時間切れの後は最初から
+        assert_eq!(matcher.press(key("h"), later + TIMEOUT), None);
+    }
+}
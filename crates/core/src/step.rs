@@ -0,0 +1,251 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::frame::Frame;
+
+/// 操作の方向軸
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    Horizontal,
+    Vertical,
+}
+
+impl Axis {
+    fn length_of(self, frame: &Frame) -> f64 {
+        match self {
+            Axis::Horizontal => frame.w,
+            Axis::Vertical => frame.h,
+        }
+    }
+}
+
+/// 移動量・リサイズ量の指定
+///
+/// 設定ファイルでは数値か、次のような文字列で書く
+/// - `"50pt"`: ポイント
+/// - `"5%"`: 画面の可視領域の幅（高さ）に対する割合
+/// - `"10%w"`: ウィンドウ自身の幅（高さ）に対する割合
+/// - `"1/12"`: 画面の可視領域の幅（高さ）に対する分数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    Points(f64),
+    ScreenPercent(f64),
+    WindowPercent(f64),
+    ScreenFraction { numerator: f64, denominator: f64 },
+}
+
+impl Step {
+    /// 操作軸に沿って、画面の可視領域とウィンドウのフレームからポイント単位の量を求める
+    pub fn resolve(self, axis: Axis, visible: &Frame, window: &Frame) -> f64 {
+        match self {
+            Step::Points(points) => points,
+            Step::ScreenPercent(percent) => axis.length_of(visible) * percent / 100.0,
+            Step::WindowPercent(percent) => axis.length_of(window) * percent / 100.0,
+            Step::ScreenFraction {
+                numerator,
+                denominator,
+            } => axis.length_of(visible) * numerator / denominator,
+        }
+    }
+}
+
+impl Step {
+    /// 量が負（逆向き）かどうか
+    pub fn is_negative(self) -> bool {
+        match self {
+            Step::Points(value) | Step::ScreenPercent(value) | Step::WindowPercent(value) => {
+                value < 0.0
+            }
+            Step::ScreenFraction {
+                numerator,
+                denominator,
+            } => (numerator < 0.0) != (denominator < 0.0),
+        }
+    }
+
+    /// `count` 倍した量
+    pub fn times(self, count: u32) -> Step {
+        let count = f64::from(count);
+        match self {
+            Step::Points(value) => Step::Points(value * count),
+            Step::ScreenPercent(value) => Step::ScreenPercent(value * count),
+            Step::WindowPercent(value) => Step::WindowPercent(value * count),
+            Step::ScreenFraction {
+                numerator,
+                denominator,
+            } => Step::ScreenFraction {
+                numerator: numerator * count,
+                denominator,
+            },
+        }
+    }
+
+    /// 符号を取り除いた量
+    pub fn abs(self) -> Step {
+        match self {
+            Step::Points(value) => Step::Points(value.abs()),
+            Step::ScreenPercent(value) => Step::ScreenPercent(value.abs()),
+            Step::WindowPercent(value) => Step::WindowPercent(value.abs()),
+            Step::ScreenFraction {
+                numerator,
+                denominator,
+            } => Step::ScreenFraction {
+                numerator: numerator.abs(),
+                denominator: denominator.abs(),
+            },
+        }
+    }
+}
+
+impl FromStr for Step {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let number = |value: &str| -> Result<f64, String> {
+            value
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid step: {s}"))
+        };
+
+        if let Some(percent) = s.strip_suffix("%w") {
+            Ok(Step::WindowPercent(number(percent)?))
+        } else if let Some(percent) = s.strip_suffix('%') {
+            Ok(Step::ScreenPercent(number(percent)?))
+        } else if let Some((numerator, denominator)) = s.split_once('/') {
+            let denominator = number(denominator)?;
+            if denominator == 0.0 {
+                return Err(format!("invalid step: {s} (division by zero)"));
+            }
+            Ok(Step::ScreenFraction {
+                numerator: number(numerator)?,
+                denominator,
+            })
+        } else {
+            Ok(Step::Points(number(s.strip_suffix("pt").unwrap_or(s))?))
+        }
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Points(points) => write!(f, "{points}pt"),
+            Step::ScreenPercent(percent) => write!(f, "{percent}%"),
+            Step::WindowPercent(percent) => write!(f, "{percent}%w"),
+            Step::ScreenFraction {
+                numerator,
+                denominator,
+            } => write!(f, "{numerator}/{denominator}"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Step {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(f64),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Number(points) => Ok(Step::Points(points)),
+            Raw::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+impl Serialize for Step {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Step::Points(points) => serializer.serialize_f64(*points),
+            _ => serializer.collect_str(self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_each_unit() {
+        let cases = [
+            ("50", Step::Points(50.0)),
+            ("50pt", Step::Points(50.0)),
+            (" -20pt ", Step::Points(-20.0)),
+            ("5%", Step::ScreenPercent(5.0)),
+            ("-2.5%", Step::ScreenPercent(-2.5)),
+            ("10%w", Step::WindowPercent(10.0)),
+            ("-10%w", Step::WindowPercent(-10.0)),
+            (
+                "1/12",
+                Step::ScreenFraction {
+                    numerator: 1.0,
+                    denominator: 12.0,
+                },
+            ),
+            (
+                "-1/3",
+                Step::ScreenFraction {
+                    numerator: -1.0,
+                    denominator: 3.0,
+                },
+            ),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(input.parse::<Step>(), Ok(expected), "{input}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_steps() {
+        for input in ["", "abc", "5%%", "%w", "1/0", "1/", "/2", "10px"] {
+            assert!(input.parse::<Step>().is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for input in ["50pt", "5%", "10%w", "1/12"] {
+            let step: Step = input.parse().unwrap();
+            assert_eq!(step.to_string(), input);
+            assert_eq!(step.to_string().parse::<Step>(), Ok(step));
+        }
+    }
+
+    #[test]
+    fn resolves_against_screen_and_window() {
+        let visible = Frame::new(0.0, 25.0, 1200.0, 800.0);
+        let window = Frame::new(100.0, 100.0, 400.0, 300.0);
+        let cases = [
+            ("50", Axis::Horizontal, 50.0),
+            ("5%", Axis::Horizontal, 60.0),
+            ("5%", Axis::Vertical, 40.0),
+            ("10%w", Axis::Horizontal, 40.0),
+            ("10%w", Axis::Vertical, 30.0),
+            ("1/12", Axis::Horizontal, 100.0),
+            ("1/4", Axis::Vertical, 200.0),
+        ];
+        for (input, axis, expected) in cases {
+            let step: Step = input.parse().unwrap();
+            assert_eq!(step.resolve(axis, &visible, &window), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn times_and_sign() {
+        let fraction: Step = "-1/12".parse().unwrap();
+        assert!(fraction.is_negative());
+        assert!(!fraction.abs().is_negative());
+        assert_eq!(fraction.times(3).to_string(), "-3/12");
+        assert_eq!(
+            "5%".parse::<Step>().unwrap().times(2),
+            Step::ScreenPercent(10.0)
+        );
+    }
+}
@@ -0,0 +1,736 @@
+use std::{collections::HashMap, fmt};
+
+use global_hotkey::hotkey::HotKey;
+
+use crate::{
+    command::{self, Script},
+    mode::{CANCEL_KEY, COMMIT_KEY, DIGIT_KEYS, ModalKey},
+    operation::{Operation, ParseOperationError},
+    preferences::{Binding, Preferences, suggest},
+    sequence::KeySequence,
+};
+
+/// キー文字列の修飾キーとして解釈される名前（大文字）
+const MODIFIER_NAMES: &[&str] = &[
+    "OPTION",
+    "ALT",
+    "CONTROL",
+    "CTRL",
+    "COMMAND",
+    "CMD",
+    "SUPER",
+    "SHIFT",
+    "COMMANDORCONTROL",
+    "COMMANDORCTRL",
+    "CMDORCTRL",
+    "CMDORCONTROL",
+];
+
+/// 検証を通過し、登録できるバインディング
+pub struct ValidBinding<'a> {
+    /// `Preferences::bindings`（ルールのものならそのルールの `bindings`）内の位置
+    pub index: usize,
+    pub binding: &'a Binding,
+    /// `operation` は 1 コマンドのスクリプトとして扱う
+    pub script: Script,
+    pub keys: KeySequence,
+}
+
+/// 検証を通過したリサイズモードの設定
+pub struct ValidModal {
+    pub leader: HotKey,
+    /// モード中のキーと実行するスクリプト
+    pub keys: Vec<(HotKey, Script)>,
+    /// `Escape`
+    pub cancel: HotKey,
+    /// `Return`
+    pub commit: HotKey,
+    /// 回数を入力する数字キー。添字がその数字になる
+    pub digits: Vec<HotKey>,
+}
+
+/// キー文字列が解釈できなかったときのエラー
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyError {
+    /// 問題のある位置（バイト単位）
+    pub position: usize,
+    /// 問題のある位置の 1 始まりの列番号（文字単位）
+    pub column: usize,
+    pub message: String,
+}
+
+impl KeyError {
+    pub(crate) fn new(position: usize, message: String) -> Self {
+        Self {
+            position,
+            column: 0,
+            message,
+        }
+    }
+
+    /// 解析した `source` から列番号を求める
+    pub(crate) fn located(mut self, source: &str) -> Self {
+        self.column = source[..self.position.min(source.len())].chars().count() + 1;
+        self
+    }
+}
+
+/// バインディングの問題
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    /// `operation` と `command` のどちらも書かれていない
+    MissingAction { index: usize },
+    /// `operation` と `command` の両方が書かれている
+    ConflictingAction { index: usize },
+    InvalidCommand {
+        index: usize,
+        command: String,
+        error: command::ParseError,
+    },
+    UnknownOperation {
+        index: usize,
+        error: ParseOperationError,
+    },
+    UnknownPreset {
+        index: usize,
+        name: String,
+        suggestion: Option<String>,
+    },
+    InvalidKey {
+        index: usize,
+        key: String,
+        error: KeyError,
+    },
+    /// 同じキーが `first` 番目のバインディングで既に使われている
+    DuplicateKey {
+        index: usize,
+        key: String,
+        first: usize,
+    },
+    /// キーの並びの一方が、`other` 番目のバインディングのキーの並びの始まりになっている
+    AmbiguousKey {
+        index: usize,
+        key: String,
+        other: usize,
+    },
+    /// OS への登録に失敗した（他のアプリが使っている場合など）
+    RegisterFailed {
+        index: usize,
+        key: String,
+        message: String,
+    },
+    /// リサイズモードの設定の問題。`key` は `leader` か `keys` のキー
+    Modal { key: String, message: String },
+    /// `index` 番目のルールの問題
+    InvalidRule { index: usize, message: String },
+    /// `rule` 番目のルールの `bindings` の問題
+    RuleBinding { rule: usize, issue: Box<Issue> },
+}
+
+impl Issue {
+    /// `rule` 番目のルールの `bindings` の問題にする
+    pub fn in_rule(self, rule: usize) -> Issue {
+        Issue::RuleBinding {
+            rule,
+            issue: Box::new(self),
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::MissingAction { index } => {
+                write!(f, "bindings[{index}]: needs an `operation` or a `command`")
+            }
+            Issue::ConflictingAction { index } => write!(
+                f,
+                "bindings[{index}]: use either `operation` or `command`, not both"
+            ),
+            Issue::InvalidCommand {
+                index,
+                command,
+                error,
+            } => write!(
+                f,
+                "bindings[{index}]: invalid command \"{command}\" at column {}: {}",
+                error.column, error.message
+            ),
+            Issue::UnknownOperation { index, error } => write!(f, "bindings[{index}]: {error}"),
+            Issue::UnknownPreset {
+                index,
+                name,
+                suggestion,
+            } => {
+                write!(f, "bindings[{index}]: unknown preset `{name}`")?;
+                if let Some(suggestion) = suggestion {
+                    write!(f, " (did you mean `{suggestion}`?)")?;
+                }
+                Ok(())
+            }
+            Issue::InvalidKey { index, key, error } => write!(
+                f,
+                "bindings[{index}]: invalid key \"{key}\" at column {}: {}",
+                error.column, error.message
+            ),
+            Issue::DuplicateKey { index, key, first } => write!(
+                f,
+                "bindings[{index}]: key \"{key}\" is already bound by bindings[{first}]"
+            ),
+            Issue::AmbiguousKey { index, key, other } => write!(
+                f,
+                "bindings[{index}]: key \"{key}\" overlaps the key sequence of bindings[{other}]; one starts with the other"
+            ),
+            Issue::RegisterFailed {
+                index,
+                key,
+                message,
+            } => write!(
+                f,
+                "bindings[{index}]: failed to register \"{key}\" (it may be used by another app): {message}"
+            ),
+            Issue::Modal { key, message } => write!(f, "modal \"{key}\": {message}"),
+            Issue::InvalidRule { index, message } => write!(f, "rules[{index}]: {message}"),
+            Issue::RuleBinding { rule, issue } => write!(f, "rules[{rule}].{issue}"),
+        }
+    }
+}
+
+/// 設定の検証結果
+#[derive(Debug, Clone, Default)]
+pub struct ValidationReport {
+    pub issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    pub fn push(&mut self, issue: Issue) {
+        self.issues.push(issue);
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for issue in &self.issues {
+            writeln!(f, "{issue}")?;
+        }
+        Ok(())
+    }
+}
+
+/// キー文字列を `HotKey` に変換する
+pub fn parse_key(key: &str) -> Result<HotKey, KeyError> {
+    key.parse().map_err(|_| locate_key_error(key))
+}
+
+/// `global_hotkey` と同じ規則で字句を追い、最初に問題のある字句の位置を求める
+fn locate_key_error(key: &str) -> KeyError {
+    let mut offset = 0;
+    let mut has_main_key = false;
+
+    for raw in key.split('+') {
+        let token = raw.trim();
+        let position = offset + (raw.len() - raw.trim_start().len());
+        offset += raw.len() + 1;
+
+        let message = if token.is_empty() {
+            "empty key name".to_string()
+        } else if has_main_key {
+            format!("unexpected `{token}` after the main key")
+        } else if MODIFIER_NAMES.contains(&token.to_ascii_uppercase().as_str()) {
+            continue;
+        } else if token.parse::<HotKey>().is_err() {
+            format!("unrecognized key `{token}`")
+        } else {
+            has_main_key = true;
+            continue;
+        };
+
+        return KeyError::new(position, message).located(key);
+    }
+
+    KeyError::new(key.len(), "missing main key".to_string()).located(key)
+}
+
+/// バインディングを検証し、登録できるものと問題の一覧に分ける
+pub fn validate(preferences: &Preferences) -> (Vec<ValidBinding<'_>>, ValidationReport) {
+    validate_bindings(&preferences.bindings, preferences)
+}
+
+/// ルールを検証する
+///
+/// `bindings` を持つルールごとに、その位置と登録できるバインディングを返す。
+/// ルールのバインディングの問題は `Issue::RuleBinding` として報告する
+pub fn validate_rules(
+    preferences: &Preferences,
+) -> (Vec<(usize, Vec<ValidBinding<'_>>)>, ValidationReport) {
+    let mut sets = Vec::new();
+    let mut report = ValidationReport::default();
+
+    for (index, rule) in preferences.rules.iter().enumerate() {
+        if rule.matcher.is_empty() {
+            report.push(Issue::InvalidRule {
+                index,
+                message: "`match` needs a `bundle_id`, a `name` or a `title`".to_string(),
+            });
+            continue;
+        }
+        if let Some(title) = &rule.matcher.title
+            && let Some(reason) = title.error()
+        {
+            report.push(Issue::InvalidRule {
+                index,
+                message: format!("invalid title pattern \"{title}\": {reason}"),
+            });
+            continue;
+        }
+
+        if let Some(place) = &rule.place
+            && !preferences
+                .presets
+                .iter()
+                .any(|preset| preset.name == place.frame)
+        {
+            let names = preferences
+                .presets
+                .iter()
+                .map(|preset| preset.name.as_str());
+            let mut message = format!("unknown preset `{}` in `place`", place.frame);
+            if let Some(suggestion) = suggest(&place.frame, names) {
+                message.push_str(&format!(" (did you mean `{suggestion}`?)"));
+            }
+            report.push(Issue::InvalidRule { index, message });
+        }
+
+        if let Some(bindings) = &rule.bindings {
+            let (valid, rule_report) = validate_bindings(bindings, preferences);
+            report.issues.extend(
+                rule_report
+                    .issues
+                    .into_iter()
+                    .map(|issue| issue.in_rule(index)),
+            );
+            sets.push((index, valid));
+        }
+    }
+
+    (sets, report)
+}
+
+fn validate_bindings<'a>(
+    bindings: &'a [Binding],
+    preferences: &Preferences,
+) -> (Vec<ValidBinding<'a>>, ValidationReport) {
+    let mut valid: Vec<ValidBinding<'a>> = Vec::new();
+    let mut report = ValidationReport::default();
+
+    for (index, binding) in bindings.iter().enumerate() {
+        let script = match (&binding.operation, &binding.command) {
+            (Some(operation), None) => match operation.parse::<Operation>() {
+                Ok(operation) => Script::from(operation),
+                Err(error) => {
+                    report.push(Issue::UnknownOperation { index, error });
+                    continue;
+                }
+            },
+            (None, Some(command)) => match command.parse::<Script>() {
+                Ok(script) => script,
+                Err(error) => {
+                    report.push(Issue::InvalidCommand {
+                        index,
+                        command: command.clone(),
+                        error,
+                    });
+                    continue;
+                }
+            },
+            (None, None) => {
+                report.push(Issue::MissingAction { index });
+                continue;
+            }
+            (Some(_), Some(_)) => {
+                report.push(Issue::ConflictingAction { index });
+                continue;
+            }
+        };
+
+        if let Some(name) = unknown_preset(&script, preferences) {
+            let names = preferences
+                .presets
+                .iter()
+                .map(|preset| preset.name.as_str());
+            report.push(Issue::UnknownPreset {
+                index,
+                suggestion: suggest(&name, names).map(str::to_string),
+                name,
+            });
+            continue;
+        }
+
+        let keys = match KeySequence::parse(&binding.key) {
+            Ok(keys) => keys,
+            Err(error) => {
+                report.push(Issue::InvalidKey {
+                    index,
+                    key: binding.key.clone(),
+                    error,
+                });
+                continue;
+            }
+        };
+
+        if let Some(other) = valid
+            .iter()
+            .find(|other| other.keys.is_prefix_of(&keys) || keys.is_prefix_of(&other.keys))
+        {
+            let key = binding.key.clone();
+            report.push(if other.keys == keys {
+                Issue::DuplicateKey {
+                    index,
+                    key,
+                    first: other.index,
+                }
+            } else {
+                Issue::AmbiguousKey {
+                    index,
+                    key,
+                    other: other.index,
+                }
+            });
+            continue;
+        }
+
+        valid.push(ValidBinding {
+            index,
+            binding,
+            script,
+            keys,
+        });
+    }
+
+    (valid, report)
+}
+
+/// `script` が使うプリセットのうち、設定にない最初のもの
+fn unknown_preset(script: &Script, preferences: &Preferences) -> Option<String> {
+    script
+        .0
+        .iter()
+        .find_map(|command| match command.operation() {
+            (Operation::ApplyPreset(name), _)
+                if !preferences.presets.iter().any(|preset| preset.name == name) =>
+            {
+                Some(name)
+            }
+            _ => None,
+        })
+}
+
+/// リサイズモードの設定を検証する
+///
+/// `leader` が設定されていない、または使えない場合は `None` を返す。
+/// 使えないキーは問題として報告し、それ以外のキーだけを返す
+pub fn validate_modal(preferences: &Preferences) -> (Option<ValidModal>, ValidationReport) {
+    let mut report = ValidationReport::default();
+    let modal = &preferences.modal;
+    let Some(leader_key) = &modal.leader else {
+        return (None, report);
+    };
+
+    let leader = match parse_key(leader_key) {
+        Ok(hotkey) => hotkey,
+        Err(error) => {
+            report.push(Issue::Modal {
+                key: leader_key.clone(),
+                message: format!(
+                    "invalid leader key at column {}: {}",
+                    error.column, error.message
+                ),
+            });
+            return (None, report);
+        }
+    };
+    let uses_leader = |bindings: &[Binding]| {
+        bindings.iter().position(|binding| {
+            KeySequence::parse(&binding.key).is_ok_and(|keys| keys.first() == leader)
+        })
+    };
+    let bound_by = uses_leader(&preferences.bindings)
+        .map(|index| format!("bindings[{index}]"))
+        .or_else(|| {
+            preferences
+                .rules
+                .iter()
+                .enumerate()
+                .find_map(|(rule, entry)| {
+                    let index = uses_leader(entry.bindings.as_deref()?)?;
+                    Some(format!("rules[{rule}].bindings[{index}]"))
+                })
+        });
+    if let Some(bound_by) = bound_by {
+        report.push(Issue::Modal {
+            key: leader_key.clone(),
+            message: format!("leader key is already bound by {bound_by}"),
+        });
+        return (None, report);
+    }
+
+    let cancel = parse_key(CANCEL_KEY).expect("Cannot parse the cancel key");
+    let commit = parse_key(COMMIT_KEY).expect("Cannot parse the commit key");
+    let digits: Vec<HotKey> = DIGIT_KEYS
+        .iter()
+        .map(|key| parse_key(key).expect("Cannot parse a digit key"))
+        .collect();
+    let mut bound: HashMap<u32, &str> = HashMap::from([
+        (cancel.id(), CANCEL_KEY),
+        (commit.id(), COMMIT_KEY),
+        (leader.id(), leader_key.as_str()),
+    ]);
+    for (hotkey, key) in digits.iter().zip(DIGIT_KEYS) {
+        bound.insert(hotkey.id(), key);
+    }
+    let mut keys = Vec::new();
+
+    for entry in &modal.keys {
+        match modal_key(entry, preferences, &bound) {
+            Ok((hotkey, script)) => {
+                bound.insert(hotkey.id(), &entry.key);
+                keys.push((hotkey, script));
+            }
+            Err(message) => report.push(Issue::Modal {
+                key: entry.key.clone(),
+                message,
+            }),
+        }
+    }
+
+    let valid = ValidModal {
+        leader,
+        keys,
+        cancel,
+        commit,
+        digits,
+    };
+    (Some(valid), report)
+}
+
+fn modal_key(
+    entry: &ModalKey,
+    preferences: &Preferences,
+    bound: &HashMap<u32, &str>,
+) -> Result<(HotKey, Script), String> {
+    let hotkey = parse_key(&entry.key)
+        .map_err(|error| format!("invalid key at column {}: {}", error.column, error.message))?;
+    if let Some(other) = bound.get(&hotkey.id()) {
+        return Err(format!("key is already used by \"{other}\""));
+    }
+    let script = entry.command.parse::<Script>().map_err(|error| {
+        format!(
+            "invalid command \"{}\" at column {}: {}",
+            entry.command, error.column, error.message
+        )
+    })?;
+    if let Some(name) = unknown_preset(&script, preferences) {
+        return Err(format!("unknown preset `{name}`"));
+    }
+    Ok((hotkey, script))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::from_jsonc;
+
+    /// `bindings` と `rules`、`modal` を書き換えた既定の設定
+    fn preferences(bindings: &str, rules: &str, modal: &str) -> Preferences {
+        Preferences {
+            bindings: from_jsonc(bindings).unwrap(),
+            rules: from_jsonc(rules).unwrap(),
+            modal: from_jsonc(modal).unwrap(),
+            ..Preferences::default()
+        }
+    }
+
+    fn messages(report: &ValidationReport) -> Vec<String> {
+        report.issues.iter().map(Issue::to_string).collect()
+    }
+
+    #[test]
+    fn locates_key_errors() {
+        let cases = [
+            ("ctrl+nokey", 5, 6, "unrecognized key `nokey`"),
+            ("ctrl++h", 5, 6, "empty key name"),
+            ("ctrl+h+j", 7, 8, "unexpected `j` after the main key"),
+            ("ctrl+shift", 10, 11, "missing main key"),
+            // 全角スペースは 3 バイトだが 1 文字
+            ("ctrl+\u{3000}nokey", 8, 7, "unrecognized key `nokey`"),
+        ];
+
+        for (key, position, column, message) in cases {
+            let error = parse_key(key).unwrap_err();
+            assert_eq!(
+                (error.position, error.column, error.message.as_str()),
+                (position, column, message),
+                "{key}"
+            );
+        }
+    }
+
+    #[test]
+    fn reports_binding_issues() {
+        let cases: &[(&str, &[&str])] = &[
+            (r#"[{"operation": "move_left", "key": "ctrl+h"}]"#, &[]),
+            (
+                r#"[{"key": "ctrl+h"}]"#,
+                &["bindings[0]: needs an `operation` or a `command`"],
+            ),
+            (
+                r#"[{"operation": "move_left", "command": "move left", "key": "ctrl+h"}]"#,
+                &["bindings[0]: use either `operation` or `command`, not both"],
+            ),
+            (
+                r#"[{"operation": "mvoe_left", "key": "ctrl+h"}]"#,
+                &["bindings[0]: unknown operation `mvoe_left` (did you mean `move_left`?)"],
+            ),
+            (
+                r#"[{"command": "place lfet_half", "key": "ctrl+1"}]"#,
+                &["bindings[0]: unknown preset `lfet_half` (did you mean `left_half`?)"],
+            ),
+            (
+                r#"[{"command": "place nothing_like_it", "key": "ctrl+1"}]"#,
+                &["bindings[0]: unknown preset `nothing_like_it`"],
+            ),
+            (
+                r#"[{"operation": "move_left", "key": "ctrl+\u3000nokey"}]"#,
+                &[
+                    "bindings[0]: invalid key \"ctrl+\u{3000}nokey\" at column 7: unrecognized key `nokey`",
+                ],
+            ),
+            (
+                r#"[
+                    {"operation": "move_left", "key": "ctrl+h"},
+                    {"operation": "move_right", "key": "ctrl+h"}
+                ]"#,
+                &["bindings[1]: key \"ctrl+h\" is already bound by bindings[0]"],
+            ),
+            (
+                r#"[
+                    {"operation": "move_left", "key": "ctrl+w, h"},
+                    {"operation": "move_right", "key": "ctrl+w"}
+                ]"#,
+                &[
+                    "bindings[1]: key \"ctrl+w\" overlaps the key sequence of bindings[0]; one starts with the other",
+                ],
+            ),
+            // 問題のあるバインディングは、後の重複の判定に使わない
+            (
+                r#"[
+                    {"operation": "mvoe_left", "key": "ctrl+h"},
+                    {"operation": "move_right", "key": "ctrl+h"}
+                ]"#,
+                &["bindings[0]: unknown operation `mvoe_left` (did you mean `move_left`?)"],
+            ),
+        ];
+
+        for (bindings, expected) in cases {
+            let preferences = preferences(bindings, "[]", "{}");
+            let (valid, report) = validate(&preferences);
+            assert_eq!(messages(&report), *expected, "{bindings}");
+            assert_eq!(
+                valid.len() + report.issues.len(),
+                preferences.bindings.len(),
+                "{bindings}"
+            );
+        }
+    }
+
+    #[test]
+    fn reports_rule_issues() {
+        let cases: &[(&str, &[&str])] = &[
+            (
+                r#"[{"match": {"name": "Safari"}, "place": {"frame": "left_half"}}]"#,
+                &[],
+            ),
+            (
+                r#"[{"match": {}}]"#,
+                &["rules[0]: `match` needs a `bundle_id`, a `name` or a `title`"],
+            ),
+            (
+                r#"[{"match": {"name": "Safari"}, "place": {"frame": "lfet_half"}}]"#,
+                &["rules[0]: unknown preset `lfet_half` in `place` (did you mean `left_half`?)"],
+            ),
+            (
+                r#"[
+                    {"match": {"name": "Safari"}},
+                    {"match": {"name": "Terminal"}, "bindings": [{"key": "ctrl+h"}]}
+                ]"#,
+                &["rules[1].bindings[0]: needs an `operation` or a `command`"],
+            ),
+        ];
+
+        for (rules, expected) in cases {
+            let preferences = preferences("[]", rules, "{}");
+            let (_, report) = validate_rules(&preferences);
+            assert_eq!(messages(&report), *expected, "{rules}");
+        }
+    }
+
+    #[test]
+    fn checks_the_resize_mode() {
+        let bindings = r#"[{"operation": "move_left", "key": "ctrl+h"}]"#;
+        let rules = r#"[{"match": {"name": "Terminal"}, "bindings": [{"operation": "move_left", "key": "ctrl+m"}]}]"#;
+        let cases: &[(&str, bool, &[&str])] = &[
+            (r#"{}"#, false, &[]),
+            (r#"{"leader": "ctrl+r"}"#, true, &[]),
+            (
+                r#"{"leader": "ctrl+\u3000nokey"}"#,
+                false,
+                &[
+                    "modal \"ctrl+\u{3000}nokey\": invalid leader key at column 7: unrecognized key `nokey`",
+                ],
+            ),
+            (
+                r#"{"leader": "ctrl+h"}"#,
+                false,
+                &["modal \"ctrl+h\": leader key is already bound by bindings[0]"],
+            ),
+            (
+                r#"{"leader": "ctrl+m"}"#,
+                false,
+                &["modal \"ctrl+m\": leader key is already bound by rules[0].bindings[0]"],
+            ),
+            (
+                r#"{"leader": "ctrl+r", "keys": [
+                    {"key": "h", "command": "move left"},
+                    {"key": "escape", "command": "move right"},
+                    {"key": "1", "command": "move right"},
+                    {"key": "ctrl+r", "command": "move right"},
+                    {"key": "H", "command": "move right"},
+                    {"key": "nokey", "command": "move right"},
+                    {"key": "x", "command": "mvoe right"},
+                    {"key": "y", "command": "place lfet_half"}
+                ]}"#,
+                true,
+                &[
+                    "modal \"escape\": key is already used by \"escape\"",
+                    "modal \"1\": key is already used by \"1\"",
+                    "modal \"ctrl+r\": key is already used by \"ctrl+r\"",
+                    "modal \"H\": key is already used by \"h\"",
+                    "modal \"nokey\": invalid key at column 1: unrecognized key `nokey`",
+                    "modal \"x\": invalid command \"mvoe right\" at column 1: unknown command `mvoe` (did you mean `move`?)",
+                    "modal \"y\": unknown preset `lfet_half`",
+                ],
+            ),
+        ];
+
+        for (modal, enabled, expected) in cases {
+            let preferences = preferences(bindings, rules, modal);
+            let (valid, report) = validate_modal(&preferences);
+            assert_eq!(valid.is_some(), *enabled, "{modal}");
+            assert_eq!(messages(&report), *expected, "{modal}");
+        }
+    }
+}
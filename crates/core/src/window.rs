@@ -1,22 +1,31 @@
 #![allow(unsafe_op_in_unsafe_fn)]
 
-use std::{ffi::c_void, ptr::NonNull};
+use std::{
+    ffi::c_void, os::unix::ffi::OsStringExt, path::PathBuf, ptr::NonNull, sync::mpsc::Sender,
+};
 
 use core_foundation::{
-    base::{CFRelease, TCFType},
+    base::{CFRelease, CFType, TCFType},
     boolean::CFBoolean,
+    bundle::CFBundle,
     string::CFString,
+    url::CFURL,
 };
 use core_graphics::{
     display::CFDictionary,
     geometry::{CGPoint, CGSize},
 };
 
+use serde::{Deserialize, Serialize};
+
+use crate::{doctor::Check, frame::Frame, screen::Screen};
+
 unsafe extern "C" {
     fn AXIsProcessTrustedWithOptions(options: CFDictionaryRef) -> Boolean;
 
     pub fn AXUIElementCreateSystemWide() -> AXUIElementRef;
     pub fn AXUIElementCreateApplication(pid: i32) -> AXUIElementRef;
+    pub(crate) fn AXUIElementGetPid(element: AXUIElementRef, pid: *mut i32) -> AXError;
     pub fn AXUIElementCopyAttributeValue(
         element: AXUIElementRef,
         attribute: CFStringRef,
@@ -41,13 +50,28 @@ unsafe extern "C" {
     pub fn get_kAXFocusedWindowAttribute() -> CFStringRef;
     pub fn get_kAXPositionAttribute() -> CFStringRef;
     pub fn get_kAXSizeAttribute() -> CFStringRef;
+    pub fn get_kAXTitleAttribute() -> CFStringRef;
+    pub fn get_kAXWindowsAttribute() -> CFStringRef;
+    pub fn get_kAXWindowCreatedNotification() -> CFStringRef;
+    pub fn get_kAXUIElementDestroyedNotification() -> CFStringRef;
+    pub fn get_kAXWindowMovedNotification() -> CFStringRef;
+    pub fn get_kAXWindowResizedNotification() -> CFStringRef;
+    pub fn get_kAXFocusedWindowChangedNotification() -> CFStringRef;
+    pub fn get_kAXApplicationActivatedNotification() -> CFStringRef;
     fn get_kAXTrustedCheckOptionPrompt() -> CFStringRef;
 
     // Additional functions for getting frontmost app
     fn GetFrontProcess(psn: *mut ProcessSerialNumber) -> i32;
     fn GetProcessPID(psn: *const ProcessSerialNumber, pid: *mut i32) -> i32;
+    fn GetNextProcess(psn: *mut ProcessSerialNumber) -> i32;
+
+    // libproc
+    fn proc_pidpath(pid: i32, buffer: *mut c_void, size: u32) -> i32;
 }
 
+/// `proc_pidpath` に渡すバッファの大きさ（`PROC_PIDPATHINFO_MAXSIZE`）
+const PROC_PIDPATH_SIZE: usize = 4096;
+
 // ===== RAII Wrapper for AXValue =====
 struct OwnedAxValue(NonNull<c_void>);
 
@@ -75,11 +99,15 @@ impl Drop for OwnedAxValue {
 }
 
 // ===== macOS AX API FFI =====
-#[repr(C)]
+/// AX API の戻り値
+///
+/// OS は列挙していない値（`kAXErrorCannotComplete` など）も返すので、整数のまま受け取る
+#[repr(transparent)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub enum AXError {
-    Success = 0,
-    // 他の値は必要になれば追加
+pub struct AXError(pub i32);
+
+impl AXError {
+    pub const SUCCESS: AXError = AXError(0);
 }
 
 #[repr(C)]
@@ -111,10 +139,10 @@ pub unsafe fn cfstring_ref(s: CFStringRef) -> CFString {
     CFString::wrap_under_get_rule(s as *const _)
 }
 
-unsafe fn copy_attr(element: AXUIElementRef, key: CFStringRef) -> Option<CFTypeRef> {
+pub(crate) unsafe fn copy_attr(element: AXUIElementRef, key: CFStringRef) -> Option<CFTypeRef> {
     let mut out: CFTypeRef = std::ptr::null();
     let err = AXUIElementCopyAttributeValue(element, key, &mut out);
-    if err == AXError::Success && !out.is_null() {
+    if err == AXError::SUCCESS && !out.is_null() {
         Some(out)
     } else {
         None
@@ -142,23 +170,211 @@ unsafe fn get_frontmost_app_pid() -> Option<i32> {
     Some(pid)
 }
 
-pub unsafe fn get_focused_window() -> Option<AXUIElementRef> {
+/// 起動しているアプリのプロセス ID
+pub(crate) unsafe fn running_app_pids() -> Vec<i32> {
+    // kNoProcess から順にたどる
+    let mut psn = ProcessSerialNumber {
+        high_long_of_psn: 0,
+        low_long_of_psn: 0,
+    };
+    let mut pids = Vec::new();
+
+    while GetNextProcess(&mut psn) == 0 {
+        let mut pid: i32 = 0;
+        if GetProcessPID(&psn, &mut pid) == 0 {
+            pids.push(pid);
+        }
+    }
+
+    pids
+}
+
+unsafe fn get_focused_app() -> Option<AXUIElementRef> {
     // Try method 1: Get focused app from system-wide element
     let sys = AXUIElementCreateSystemWide();
 
-    let app = match copy_attr(sys, get_kAXFocusedApplicationAttribute()) {
-        Some(val) => val as AXUIElementRef,
+    match copy_attr(sys, get_kAXFocusedApplicationAttribute()) {
+        Some(val) => Some(val as AXUIElementRef),
         None => {
             // Try method 2: Get frontmost application by PID
             let pid = get_frontmost_app_pid()?;
-            AXUIElementCreateApplication(pid)
+            Some(AXUIElementCreateApplication(pid))
         }
-    };
+    }
+}
 
+pub unsafe fn get_focused_window() -> Option<AXUIElementRef> {
+    let app = get_focused_app()?;
     let win_val = copy_attr(app, get_kAXFocusedWindowAttribute())?;
     Some(win_val as AXUIElementRef)
 }
 
+/// 最前面のアプリ
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppIdentity {
+    /// `com.apple.Safari` などのバンドル ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bundle_id: Option<String>,
+    /// アプリ名
+    #[serde(rename = "app", default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// フォーカスされているウィンドウのタイトル
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+/// バックエンドがウィンドウに振る番号
+pub type WindowId = u64;
+
+/// 開いているウィンドウ 1 つ分。`app` の `title` はそのウィンドウのタイトル
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowInfo {
+    pub window: WindowId,
+    #[serde(flatten)]
+    pub app: AppIdentity,
+    pub frame: Frame,
+}
+
+/// 監視で受け取るウィンドウやアプリの変化
+///
+/// `app` の `title` は対象のウィンドウのタイトル。JSON では `"event"` に種類を書き、
+/// `app` の項目は同じ階層に並べる
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum WindowEvent {
+    /// アプリが起動した
+    AppLaunched {
+        #[serde(flatten)]
+        app: AppIdentity,
+    },
+    /// ウィンドウが作られた
+    WindowCreated {
+        window: WindowId,
+        #[serde(flatten)]
+        app: AppIdentity,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        frame: Option<Frame>,
+    },
+    /// ウィンドウが閉じられた
+    WindowDestroyed {
+        window: WindowId,
+        #[serde(flatten)]
+        app: AppIdentity,
+    },
+    /// フォーカスされているウィンドウが変わった
+    FocusChanged {
+        window: WindowId,
+        #[serde(flatten)]
+        app: AppIdentity,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        frame: Option<Frame>,
+    },
+    WindowMoved {
+        window: WindowId,
+        #[serde(flatten)]
+        app: AppIdentity,
+        frame: Frame,
+    },
+    WindowResized {
+        window: WindowId,
+        #[serde(flatten)]
+        app: AppIdentity,
+        frame: Frame,
+    },
+}
+
+/// `WindowBackend::subscribe` の監視。落とすと止まる
+pub struct Subscription(Option<Box<dyn FnOnce() + Send>>);
+
+impl Subscription {
+    pub fn new(stop: impl FnOnce() + Send + 'static) -> Self {
+        Self(Some(Box::new(stop)))
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(stop) = self.0.take() {
+            stop();
+        }
+    }
+}
+
+/// ウィンドウの操作と監視
+///
+/// macOS では `AxBackend`、テストでは `MockBackend` を使う
+pub trait WindowBackend: Send + Sync {
+    /// 接続されているディスプレイ。先頭がメインディスプレイ
+    fn screens(&self) -> anyhow::Result<Vec<Screen>>;
+    fn frame(&self, window: WindowId) -> anyhow::Result<Frame>;
+    fn set_frame(&self, window: WindowId, frame: Frame) -> anyhow::Result<()>;
+    /// 開いているウィンドウ
+    fn windows(&self) -> anyhow::Result<Vec<WindowInfo>>;
+    /// フォーカスされているウィンドウ
+    fn focused(&self) -> anyhow::Result<WindowId>;
+    /// 変化を `events` に送り始める
+    fn subscribe(&self, events: Sender<WindowEvent>) -> anyhow::Result<Subscription>;
+    /// `resize doctor` でこのバックエンドについて行う診断
+    ///
+    /// ウィンドウの読み書きの診断（`doctor::WindowAccessCheck`）は含めなくてよい
+    fn checks(&self) -> Vec<Box<dyn Check + '_>> {
+        Vec::new()
+    }
+}
+
+/// 最前面のアプリとそのウィンドウを調べる
+pub fn focused_app() -> Option<AppIdentity> {
+    unsafe {
+        let app = get_focused_app()?;
+
+        let mut pid = 0;
+        let bundle_id = if AXUIElementGetPid(app, &mut pid) == AXError::SUCCESS {
+            bundle_id_of(pid)
+        } else {
+            None
+        };
+        let name = copy_string(app, get_kAXTitleAttribute());
+        let title = copy_attr(app, get_kAXFocusedWindowAttribute()).and_then(|window| {
+            let title = copy_string(window as AXUIElementRef, get_kAXTitleAttribute());
+            CFRelease(window);
+            title
+        });
+        CFRelease(app as CFTypeRef);
+
+        Some(AppIdentity {
+            bundle_id,
+            name,
+            title,
+        })
+    }
+}
+
+pub(crate) unsafe fn copy_string(element: AXUIElementRef, key: CFStringRef) -> Option<String> {
+    let value = CFType::wrap_under_create_rule(copy_attr(element, key)?);
+    value.downcast::<CFString>().map(|s| s.to_string())
+}
+
+/// 実行ファイルを含む `.app` の `Info.plist` からバンドル ID を読む
+pub(crate) unsafe fn bundle_id_of(pid: i32) -> Option<String> {
+    let mut buffer = vec![0u8; PROC_PIDPATH_SIZE];
+    let len = proc_pidpath(pid, buffer.as_mut_ptr().cast(), buffer.len() as u32);
+    if len <= 0 {
+        return None;
+    }
+    buffer.truncate(len as usize);
+
+    let executable = PathBuf::from(std::ffi::OsString::from_vec(buffer));
+    let app = executable
+        .ancestors()
+        .find(|path| path.extension().is_some_and(|ext| ext == "app"))?;
+    let bundle = CFBundle::new(CFURL::from_path(app, true)?)?;
+    bundle
+        .info_dictionary()
+        .find(CFString::from_static_string("CFBundleIdentifier"))?
+        .downcast::<CFString>()
+        .map(|s| s.to_string())
+}
+
 trait AxKind {
     type Pod; // 実データ型（CGPoint/CGSize）
     const TYPE: AXValueType; // 対応する AXValueType
@@ -221,7 +437,7 @@ unsafe fn set_ax<T: Copy>(
         .ok_or_else(|| anyhow::anyhow!("Failed to create AXValue"))?;
 
     let err = AXUIElementSetAttributeValue(elem, key, ax_value.as_ptr());
-    if err == AXError::Success {
+    if err == AXError::SUCCESS {
         Ok(())
     } else {
         Err(anyhow::anyhow!("Failed to set ax attribute"))
@@ -237,9 +453,16 @@ pub unsafe fn set_cgsize(elem: AXUIElementRef, key: CFStringRef, s: CGSize) -> b
     set_ax(AXValueType::CGSize, elem, key, s).is_ok()
 }
 
-pub fn ensure_ax_trusted() -> bool {
+/// Accessibility の許可があるか。許可ダイアログは出さない
+pub fn is_trusted() -> bool {
+    unsafe { AXIsProcessTrustedWithOptions(std::ptr::null()) != 0 }
+}
+
+/// 許可がなければ、システム設定で許可するよう促すダイアログを出す
+///
+/// ダイアログの結果は待たずに現在の許可の有無を返す。許可されたかは `is_trusted` で確かめる
+pub fn request_trust() -> bool {
     unsafe {
-        // 許可ダイアログを出す
         let key = cfstring_ref(get_kAXTrustedCheckOptionPrompt());
         let dict = CFDictionary::from_CFType_pairs(&[(
             key.as_CFType(),
@@ -0,0 +1,533 @@
+use std::{
+    cell::{Cell, RefCell},
+    process,
+    sync::{Arc, mpsc},
+    time::Instant,
+};
+
+use core::{
+    command::Script,
+    frame::Frame,
+    logging,
+    mode::{Effect, Input, ResizeMode},
+    operation::{Operation, REGISTRY},
+    preferences::Preferences,
+    screen::ScreenSelector,
+    validation::ValidationReport,
+    window::{self, WindowId},
+};
+use dispatch2::{DispatchQueue, MainThreadBound};
+use global_hotkey::hotkey::HotKey;
+use objc2::{
+    DefinedClass, MainThreadMarker, MainThreadOnly, Message, define_class, msg_send,
+    rc::{Retained, Weak},
+    runtime::{AnyObject, NSObject},
+    sel,
+};
+use objc2_app_kit::{NSMenu, NSMenuItem};
+use objc2_foundation::{NSObjectProtocol, NSTimer};
+
+use crate::{
+    hotkey::{Dispatcher, Handler, HotkeyManager, MainThreadHandlers, SequenceProgress},
+    ipc::IpcServer,
+    menu::{self, MenuOperation, Warning},
+    placement::AutoPlacement,
+    watcher::SettingsWatcher,
+};
+
+/// 設定ファイルの変更を確認する間隔（秒）
+const WATCH_INTERVAL: f64 = 1.0;
+/// リサイズモードのタイムアウトを確認する間隔（秒）
+const MODE_TICK_INTERVAL: f64 = 0.1;
+/// Accessibility の許可を待つ間、許可を確認する間隔（秒）
+const PERMISSION_INTERVAL: f64 = 1.0;
+/// システム設定の Accessibility の画面
+const ACCESSIBILITY_SETTINGS_URL: &str =
+    "x-apple.systempreferences:com.apple.preference.security?Privacy_Accessibility";
+
+pub struct Ivars {
+    hotkeys: RefCell<HotkeyManager>,
+    watcher: RefCell<SettingsWatcher>,
+    menu: Retained<NSMenu>,
+    /// 現在反映している設定
+    preferences: RefCell<Arc<Preferences>>,
+    /// メニューに並べた操作。メニュー項目の tag がこの添字になる
+    operations: RefCell<Vec<Operation>>,
+    mode: RefCell<ResizeMode>,
+    /// リサイズモードに入ったときのウィンドウ。取り消すとこのウィンドウを元に戻す
+    mode_window: Cell<Option<WindowId>>,
+    /// リサイズモード中だけ動かすタイマー
+    mode_timer: RefCell<Option<Retained<NSTimer>>>,
+    /// キーの並びの続きを待つ間だけ動かすタイマー
+    sequence_timer: RefCell<Option<Retained<NSTimer>>>,
+    /// Accessibility の許可を待つ間だけ動かすタイマー。動いている間はホットキーを登録しない
+    permission_timer: RefCell<Option<Retained<NSTimer>>>,
+    /// `repeat_last` のため、メニューやリサイズモードからの実行もホットキーと同じものを通す。
+    /// リサイズモードの復元もここに入れ、押した順に実行する
+    dispatcher: Arc<Dispatcher>,
+    placement: RefCell<AutoPlacement>,
+    /// `resize` コマンドからの要求を受ける。`Controller` を作った後に始める
+    ipc: RefCell<Option<IpcServer>>,
+}
+
+define_class!(
+    // SAFETY:
+    // - NSObject にサブクラス化の要件はない
+    // - `Controller` は `Drop` を実装しない
+    #[unsafe(super(NSObject))]
+    #[thread_kind = MainThreadOnly]
+    #[ivars = Ivars]
+    pub struct Controller;
+
+    impl Controller {
+        /// メニューの "Open Log"。ログファイルを既定のアプリ（コンソール）で開く
+        #[unsafe(method(openLog:))]
+        fn open_log(&self, _sender: Option<&AnyObject>) {
+            let path = logging::log_path();
+            if let Err(err) = process::Command::new("open").arg(&path).spawn() {
+                log::error!("Failed to open {}: {err}", path.display());
+            }
+        }
+
+        /// 許可を待つ間のメニューの "Open Accessibility Settings…"
+        #[unsafe(method(openAccessibilitySettings:))]
+        fn open_accessibility_settings(&self, _sender: Option<&AnyObject>) {
+            if let Err(err) = process::Command::new("open")
+                .arg(ACCESSIBILITY_SETTINGS_URL)
+                .spawn()
+            {
+                log::error!("Failed to open the Accessibility settings: {err}");
+            }
+        }
+
+        /// メニューの "Reload Settings"
+        #[unsafe(method(reloadSettings:))]
+        fn reload_settings(&self, _sender: Option<&AnyObject>) {
+            self.ivars().watcher.borrow_mut().reset();
+            self.apply_settings(false);
+        }
+
+        /// メニューの操作の項目
+        #[unsafe(method(runOperation:))]
+        fn run_operation(&self, sender: &NSMenuItem) {
+            let Some(operation) = usize::try_from(sender.tag())
+                .ok()
+                .and_then(|index| self.ivars().operations.borrow().get(index).cloned())
+            else {
+                return;
+            };
+            let preferences = Arc::clone(&self.ivars().preferences.borrow());
+            let binding = preferences.binding_for(&operation);
+            self.ivars()
+                .dispatcher
+                .run(&preferences, &Script::from(operation), binding, None);
+        }
+
+        #[unsafe(method(checkSettings:))]
+        fn check_settings(&self, _timer: &NSTimer) {
+            if self.ivars().watcher.borrow_mut().changed() {
+                self.apply_settings(false);
+            }
+        }
+
+        #[unsafe(method(checkPermission:))]
+        fn check_permission(&self, _timer: &NSTimer) {
+            if !window::is_trusted() {
+                return;
+            }
+            if let Some(timer) = self.ivars().permission_timer.borrow_mut().take() {
+                timer.invalidate();
+            }
+            log::info!("Accessibility permission granted, enabling hotkeys");
+            self.apply_settings(true);
+        }
+
+        #[unsafe(method(modeTick:))]
+        fn mode_tick(&self, _timer: &NSTimer) {
+            self.handle_mode(Input::Tick);
+        }
+
+        #[unsafe(method(sequenceTimeout:))]
+        fn sequence_timeout(&self, _timer: &NSTimer) {
+            let progress = self.ivars().hotkeys.borrow_mut().expire_sequence();
+            self.apply_sequence_progress(progress);
+        }
+    }
+
+    unsafe impl NSObjectProtocol for Controller {}
+);
+
+impl Controller {
+    /// 設定を読み込んでホットキーとメニューに反映し、設定ファイルの監視を始める
+    pub fn new(
+        mtm: MainThreadMarker,
+        hotkeys: HotkeyManager,
+        watcher: SettingsWatcher,
+        menu: Retained<NSMenu>,
+    ) -> Retained<Self> {
+        let preferences = Preferences::default();
+        let dispatcher = hotkeys.dispatcher();
+        let placement = AutoPlacement::new(Arc::clone(&dispatcher));
+        let this = mtm.alloc::<Self>().set_ivars(Ivars {
+            hotkeys: RefCell::new(hotkeys),
+            watcher: RefCell::new(watcher),
+            menu,
+            mode: RefCell::new(ResizeMode::new(preferences.modal.timeout())),
+            mode_window: Cell::new(None),
+            preferences: RefCell::new(Arc::new(preferences)),
+            operations: RefCell::new(Vec::new()),
+            mode_timer: RefCell::new(None),
+            sequence_timer: RefCell::new(None),
+            permission_timer: RefCell::new(None),
+            dispatcher,
+            placement: RefCell::new(placement),
+            ipc: RefCell::new(None),
+        });
+        let this: Retained<Self> = unsafe { msg_send![super(this), init] };
+
+        let reload = this.dispatch(|this, reply: mpsc::Sender<Vec<String>>| {
+            this.ivars().watcher.borrow_mut().reset();
+            let warnings = this.apply_settings(false);
+            let _ = reply.send(
+                warnings
+                    .iter()
+                    .flat_map(|warning| {
+                        warning
+                            .lines
+                            .iter()
+                            .map(move |line| format!("{}: {line}", warning.title))
+                    })
+                    .collect(),
+            );
+        });
+        *this.ivars().ipc.borrow_mut() = Some(IpcServer::start(
+            Arc::clone(&this.ivars().dispatcher),
+            reload,
+        ));
+
+        if window::is_trusted() {
+            this.apply_settings(true);
+        } else {
+            this.wait_for_permission();
+        }
+        unsafe {
+            NSTimer::scheduledTimerWithTimeInterval_target_selector_userInfo_repeats(
+                WATCH_INTERVAL,
+                &this,
+                sel!(checkSettings:),
+                None,
+                true,
+            );
+        }
+        this
+    }
+
+    /// 許可を求めるダイアログを出し、許可されるまでメニューを "Permission required" にして待つ
+    ///
+    /// 許可されたら `checkPermission:` が設定を読み込んでホットキーを登録する
+    fn wait_for_permission(&self) {
+        log::warn!("Accessibility permission is required, waiting until it is granted");
+        window::request_trust();
+        menu::rebuild_permission_required(&self.ivars().menu, self, self.mtm());
+
+        let timer = unsafe {
+            NSTimer::scheduledTimerWithTimeInterval_target_selector_userInfo_repeats(
+                PERMISSION_INTERVAL,
+                self,
+                sel!(checkPermission:),
+                None,
+                true,
+            )
+        };
+        *self.ivars().permission_timer.borrow_mut() = Some(timer);
+    }
+
+    /// 設定を読み込み、ホットキーとメニューに反映する。メニューに出した設定の問題を返す
+    ///
+    /// 起動時以外は、設定に問題があれば現在のホットキーを残す。許可を待つ間は何もしない
+    fn apply_settings(&self, initial: bool) -> Vec<Warning> {
+        let ivars = self.ivars();
+        if ivars.permission_timer.borrow().is_some() {
+            return vec![Warning {
+                title: "Permission required".to_string(),
+                lines: vec!["Accessibility permission has not been granted yet".to_string()],
+            }];
+        }
+        let options = ivars.watcher.borrow().options().clone();
+        // 一時的なキーは登録し直しで外れるので、リサイズモードは確定して抜ける
+        if ivars.mode.borrow().is_active() {
+            self.handle_mode(Input::Commit);
+        }
+        let mut hotkeys = ivars.hotkeys.borrow_mut();
+        let mut warnings = Vec::new();
+
+        let applied = match Preferences::load(&options) {
+            Ok(preferences) if initial => {
+                let report = hotkeys.register_hotkeys(&preferences, self.main_thread_handlers());
+                push_report(&mut warnings, &report, "");
+                Some(preferences)
+            }
+            Ok(preferences) => match hotkeys.reload(&preferences, self.main_thread_handlers()) {
+                Ok(report) => {
+                    push_report(&mut warnings, &report, "");
+                    Some(preferences)
+                }
+                Err(report) => {
+                    log::warn!("Settings were not reloaded, keeping the current hotkeys");
+                    push_report(&mut warnings, &report, ", settings not reloaded");
+                    None
+                }
+            },
+            Err(err) if initial => {
+                log::warn!("Failed to load settings, using defaults: {err}");
+                let preferences = Preferences::default();
+                let report = hotkeys.register_hotkeys(&preferences, self.main_thread_handlers());
+                warnings.push(Warning {
+                    title: "Settings could not be loaded".to_string(),
+                    lines: vec![err.to_string()],
+                });
+                push_report(&mut warnings, &report, "");
+                Some(preferences)
+            }
+            Err(err) => {
+                log::warn!("Failed to reload settings, keeping the current ones: {err}");
+                warnings.push(Warning {
+                    title: "Settings could not be reloaded".to_string(),
+                    lines: vec![err.to_string()],
+                });
+                None
+            }
+        };
+        drop(hotkeys);
+
+        if let Some(preferences) = applied {
+            logging::set_level(preferences.log_level);
+            *ivars.mode.borrow_mut() = ResizeMode::new(preferences.modal.timeout());
+            *ivars.preferences.borrow_mut() = Arc::new(preferences);
+        }
+        let preferences = Arc::clone(&ivars.preferences.borrow());
+        ivars
+            .placement
+            .borrow_mut()
+            .update(Arc::clone(&preferences));
+        if let Some(ipc) = ivars.ipc.borrow().as_ref() {
+            ipc.update(Arc::clone(&preferences));
+        }
+        let (operations, items) = menu_operations(&preferences);
+        *ivars.operations.borrow_mut() = operations;
+
+        menu::rebuild(&ivars.menu, &warnings, &items, self, self.mtm());
+        warnings
+    }
+
+    /// リーダーキーでリサイズモードに入る。モード中なら確定して抜ける
+    fn toggle_mode(&self) {
+        if self.ivars().mode.borrow().is_active() {
+            self.handle_mode(Input::Commit);
+            return;
+        }
+        // ルールで無効にされているアプリではモードに入らない
+        let preferences = Arc::clone(&self.ivars().preferences.borrow());
+        let focus = self.ivars().dispatcher.focus();
+        let app = focus
+            .as_ref()
+            .map(|focus| focus.app.clone())
+            .or_else(window::focused_app);
+        if preferences.for_app(app.as_ref()).is_none() {
+            return;
+        }
+        // フレームの読み取りも AX を呼ぶので、メインスレッドではなく `Dispatcher` で行う
+        let enter = self
+            .dispatch(|this, (window, origin): (WindowId, Frame)| this.enter_mode(window, origin));
+        let window = focus.map(|focus| focus.window);
+        self.ivars()
+            .dispatcher
+            .enqueue("enter resize mode", move |backend| {
+                let window = match window {
+                    Some(window) => window,
+                    None => backend.focused()?,
+                };
+                enter((window, backend.frame(window)?));
+                Ok(())
+            });
+    }
+
+    /// `window` の `origin` を覚えてリサイズモードに入る
+    fn enter_mode(&self, window: WindowId, origin: Frame) {
+        self.ivars().mode_window.set(Some(window));
+        self.handle_mode(Input::Leader(origin));
+    }
+
+    /// リサイズモードの状態を進め、その結果を反映する
+    fn handle_mode(&self, input: Input) {
+        let ivars = self.ivars();
+        let effect = ivars.mode.borrow_mut().handle(input, Instant::now());
+
+        match effect {
+            Some(Effect::Enter) => {
+                let handle_mode = self.dispatch(Self::handle_mode);
+                ivars.hotkeys.borrow_mut().register_mode_keys(|input| {
+                    let handle_mode = Arc::clone(&handle_mode);
+                    let handler: Handler = Box::new(move || handle_mode(input.clone()));
+                    handler
+                });
+                let timer = unsafe {
+                    NSTimer::scheduledTimerWithTimeInterval_target_selector_userInfo_repeats(
+                        MODE_TICK_INTERVAL,
+                        self,
+                        sel!(modeTick:),
+                        None,
+                        true,
+                    )
+                };
+                *ivars.mode_timer.borrow_mut() = Some(timer);
+            }
+            Some(Effect::Run { script, count }) => {
+                let preferences = Arc::clone(&ivars.preferences.borrow());
+                ivars.dispatcher.run(&preferences, &script, None, count);
+            }
+            Some(Effect::Exit { restore }) => {
+                ivars.hotkeys.borrow_mut().unregister_mode_keys();
+                if let Some(timer) = ivars.mode_timer.borrow_mut().take() {
+                    timer.invalidate();
+                }
+                let window = ivars.mode_window.take();
+                if let Some(origin) = restore
+                    && let Some(window) = window
+                {
+                    ivars
+                        .dispatcher
+                        .enqueue("restore the window", move |backend| {
+                            backend.set_frame(window, origin)
+                        });
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// キーの並びのキーが押された
+    fn press_sequence_key(&self, key: HotKey) {
+        let progress = self.ivars().hotkeys.borrow_mut().press_sequence_key(key);
+        self.apply_sequence_progress(progress);
+    }
+
+    /// 続きを待つ間はタイマーを動かし、並びが揃ったら実行する
+    fn apply_sequence_progress(&self, progress: SequenceProgress) {
+        let ivars = self.ivars();
+        if let Some(timer) = ivars.sequence_timer.borrow_mut().take() {
+            timer.invalidate();
+        }
+
+        match progress {
+            SequenceProgress::Waiting(deadline) => {
+                let interval = deadline.saturating_duration_since(Instant::now());
+                let timer = unsafe {
+                    NSTimer::scheduledTimerWithTimeInterval_target_selector_userInfo_repeats(
+                        interval.as_secs_f64(),
+                        self,
+                        sel!(sequenceTimeout:),
+                        None,
+                        false,
+                    )
+                };
+                *ivars.sequence_timer.borrow_mut() = Some(timer);
+            }
+            // 実行は `Dispatcher` のスレッドに渡すので、メインスレッドは止まらない
+            SequenceProgress::Complete(job) => job(),
+            SequenceProgress::Idle => {}
+        }
+    }
+
+    fn main_thread_handlers(&self) -> MainThreadHandlers {
+        let toggle_mode = self.dispatch(|this, ()| this.toggle_mode());
+        MainThreadHandlers {
+            leader: Box::new(move || toggle_mode(())),
+            sequence_key: self.dispatch(Self::press_sequence_key),
+        }
+    }
+
+    /// ホットキーのスレッドから、メインスレッドでこの `Controller` に `f` を実行させる関数を作る
+    fn dispatch<A: Send + 'static>(
+        &self,
+        f: impl Fn(&Self, A) + Send + Sync + 'static,
+    ) -> Arc<dyn Fn(A) + Send + Sync> {
+        let this = Arc::new(MainThreadBound::new(
+            Weak::from_retained(&self.retain()),
+            self.mtm(),
+        ));
+        let f = Arc::new(f);
+
+        Arc::new(move |argument| {
+            let this = Arc::clone(&this);
+            let f = Arc::clone(&f);
+            DispatchQueue::main().exec_async(move || {
+                let mtm = MainThreadMarker::new().expect("dispatched to the main queue");
+                if let Some(this) = this.get(mtm).load() {
+                    f(&this, argument);
+                }
+            });
+        })
+    }
+}
+
+/// メニューに並べる操作
+///
+/// 引数を取る操作は、プリセットやディスプレイごとに 1 項目にする
+fn menu_operations(preferences: &Preferences) -> (Vec<Operation>, Vec<MenuOperation>) {
+    let mut operations = Vec::new();
+    let mut items = Vec::new();
+
+    for info in REGISTRY {
+        let entries: Vec<(Operation, String)> = match &info.operation {
+            Operation::ApplyPreset(_) => preferences
+                .presets
+                .iter()
+                .map(|preset| {
+                    (
+                        Operation::ApplyPreset(preset.name.clone()),
+                        format!("{} ({})", info.description, preset.name),
+                    )
+                })
+                .collect(),
+            Operation::MoveToScreen(_) => [ScreenSelector::Next, ScreenSelector::Previous]
+                .into_iter()
+                .map(|selector| {
+                    (
+                        Operation::MoveToScreen(selector),
+                        format!("{} ({selector})", info.description),
+                    )
+                })
+                .collect(),
+            operation => vec![(operation.clone(), info.description.to_string())],
+        };
+
+        for (operation, title) in entries {
+            let key = preferences
+                .binding_for(&operation)
+                .map(|binding| binding.key.clone());
+            items.push(MenuOperation {
+                category: info.category,
+                title,
+                key,
+            });
+            operations.push(operation);
+        }
+    }
+
+    (operations, items)
+}
+
+/// `report` の問題を出力し、メニューの警告に加える
+fn push_report(warnings: &mut Vec<Warning>, report: &ValidationReport, suffix: &str) {
+    if report.is_empty() {
+        return;
+    }
+    for issue in &report.issues {
+        log::warn!("{issue}");
+    }
+    warnings.push(Warning {
+        title: format!("{} binding problem(s){suffix}", report.issues.len()),
+        lines: report.issues.iter().map(ToString::to_string).collect(),
+    });
+}
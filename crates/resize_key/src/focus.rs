@@ -0,0 +1,80 @@
+use std::{
+    sync::{Arc, Mutex, RwLock, RwLockWriteGuard, mpsc},
+    thread,
+};
+
+use core::window::{self, AppIdentity, Subscription, WindowBackend, WindowEvent, WindowId};
+
+/// フォーカスされているウィンドウと、そのアプリ
+#[derive(Debug, Clone)]
+pub struct Focus {
+    pub window: WindowId,
+    pub app: AppIdentity,
+}
+
+/// ウィンドウの監視から、フォーカスされているウィンドウを覚えておく
+///
+/// ホットキーのスレッドは AX を呼ばずにこれを読むので、応答しないアプリがあっても止まらない
+pub struct FocusTracker {
+    /// 監視のスレッドと共有する
+    current: Arc<RwLock<Option<Focus>>>,
+    /// ほかのスレッドから読まれるのでロックに入れておく
+    _subscription: Mutex<Option<Subscription>>,
+}
+
+impl FocusTracker {
+    /// `backend` の監視を始める。番号は `backend` のものを使う
+    pub fn start(backend: Arc<dyn WindowBackend>) -> Self {
+        let current = Arc::new(RwLock::new(None));
+        let (events, received) = mpsc::channel();
+        let subscription = match backend.subscribe(events) {
+            Ok(subscription) => Some(subscription),
+            Err(err) => {
+                log::error!("Failed to watch the focused window: {err}");
+                None
+            }
+        };
+
+        let shared = Arc::clone(&current);
+        // 監視をやめると送る側が落ち、このスレッドも終わる
+        thread::spawn(move || {
+            // フォーカスが変わるまで通知は来ないので、始めに一度だけ調べる
+            if let Ok(window) = backend.focused()
+                && let Some(app) = window::focused_app()
+            {
+                write(&shared).get_or_insert(Focus { window, app });
+            }
+            for event in received {
+                let mut current = write(&shared);
+                match event {
+                    WindowEvent::FocusChanged { window, app, .. } => {
+                        *current = Some(Focus { window, app });
+                    }
+                    WindowEvent::WindowDestroyed { window, .. }
+                        if current.as_ref().is_some_and(|focus| focus.window == window) =>
+                    {
+                        *current = None;
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        Self {
+            current,
+            _subscription: Mutex::new(subscription),
+        }
+    }
+
+    /// 最後に知らされたフォーカス。まだ分からなければ `None`
+    pub fn current(&self) -> Option<Focus> {
+        self.current
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+}
+
+fn write(current: &RwLock<Option<Focus>>) -> RwLockWriteGuard<'_, Option<Focus>> {
+    current.write().unwrap_or_else(|err| err.into_inner())
+}
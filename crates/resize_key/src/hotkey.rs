@@ -1,85 +1,724 @@
-use std::{collections::HashMap, sync::Arc, thread};
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex, OnceLock, RwLock,
+        mpsc::{self, Sender},
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
-use core::frame::{Direction, Edge, move_window_nswindow_style, resize};
+use anyhow::{anyhow, bail};
+use core::{
+    ax_backend::AxBackend,
+    command::{Command, Script},
+    doctor::Check,
+    executor::{Executor, ExecutorOptions, Pending},
+    frame::Frame,
+    mode::Input,
+    outcome::OperationOutcome,
+    preferences::{Binding, Preferences},
+    screen::Screen,
+    sequence::{KeySequence, Outcome, SequenceMatcher},
+    validation::{self, Issue, ValidBinding, ValidModal, ValidationReport},
+    window::{self, AppIdentity, Subscription, WindowBackend, WindowEvent, WindowId, WindowInfo},
+};
 use global_hotkey::{GlobalHotKeyEvent, GlobalHotKeyManager, hotkey::HotKey};
 
-use crate::preferences::{Operation, Preferences};
+use crate::focus::{Focus, FocusTracker};
+
+pub type Handler = Box<dyn Fn() + Send + Sync + 'static>;
+
+/// 最後に実行した処理
+#[derive(Clone)]
+struct Invocation {
+    script: Script,
+    binding: Option<Binding>,
+    count: u32,
+}
+
+/// 続けて投入されたらまとめる実行の区別
+#[derive(PartialEq)]
+struct DispatchKey {
+    /// 投入した時点でフォーカスされていたウィンドウ
+    window: WindowId,
+    script: String,
+    /// バインディングのキー。メニューやリサイズモードからなら `None`
+    binding: Option<String>,
+}
+
+/// ホットキー・メニュー・リサイズモードからの実行をまとめる
+///
+/// 実行は `Executor` のスレッドで押した順に行い、呼び出した側は待たない。
+/// 同じウィンドウに対する移動・リサイズだけのスクリプトが続けて押されたら、
+/// 量を足して 1 回のフレーム更新にまとめる。
+/// 対象のウィンドウは投入した時点でフォーカスされていたもので、`watch_focus` で覚えたものを使う。
+/// `repeat_last` のために最後に実行したものを覚えておく
+pub struct Dispatcher {
+    last: Mutex<Option<Invocation>>,
+    backend: Arc<dyn WindowBackend>,
+    focus: OnceLock<FocusTracker>,
+    executor: Executor<DispatchKey>,
+}
+
+impl Default for Dispatcher {
+    fn default() -> Self {
+        let options = ExecutorOptions::default();
+        // 応答しないアプリの AX の呼び出しも、実行の時間切れと同じ時間で諦める
+        let backend: Arc<dyn WindowBackend> = Arc::new(AxBackend::with_timeout(options.timeout));
+        Self {
+            last: Mutex::new(None),
+            executor: Executor::new(Arc::clone(&backend), options),
+            focus: OnceLock::new(),
+            backend,
+        }
+    }
+}
+
+impl Dispatcher {
+    /// フォーカスされているウィンドウの監視を始める。Accessibility の許可を得てから呼ぶ
+    pub fn watch_focus(&self) {
+        self.focus
+            .get_or_init(|| FocusTracker::start(Arc::clone(&self.backend)));
+    }
+
+    /// 監視で知らされた、フォーカスされているウィンドウ。AX は呼ばない
+    pub fn focus(&self) -> Option<Focus> {
+        self.focus.get().and_then(FocusTracker::current)
+    }
+
+    /// `try_run` と同じく実行するが、終わるのを待たない。失敗はログに残る
+    pub fn run(
+        &self,
+        preferences: &Arc<Preferences>,
+        script: &Script,
+        binding: Option<&Binding>,
+        count: Option<u32>,
+    ) {
+        self.submit(preferences, script, binding, count);
+    }
+
+    /// `binding` の設定で `script` を実行し、実行した操作の結果を順に返す
+    ///
+    /// 最前面のアプリに当てはまるルールを反映してから実行し、ルールで無効にされていれば何もしない。
+    /// `count` は移動量・リサイズ量の倍数。`repeat_last` は直前の実行を、
+    /// `count` があればその倍数で、なければ直前と同じ倍数で繰り返す
+    pub fn try_run(
+        &self,
+        preferences: &Arc<Preferences>,
+        script: &Script,
+        binding: Option<&Binding>,
+        count: Option<u32>,
+    ) -> anyhow::Result<Vec<OperationOutcome>> {
+        self.submit(preferences, script, binding, count).wait()
+    }
+
+    /// 操作の後に `job` を実行する。リサイズモードの出入りなど、まとめない処理に使う
+    pub fn enqueue(
+        &self,
+        label: &str,
+        job: impl FnOnce(&dyn WindowBackend) -> anyhow::Result<()> + Send + 'static,
+    ) {
+        self.executor.submit(
+            None,
+            label,
+            1,
+            Box::new(move |backend, _| job(backend).map(|()| Vec::new())),
+        );
+    }
+
+    /// 実行と同じバックエンド。ウィンドウの読み書きは実行と同じスレッドで順に行う
+    ///
+    /// ウィンドウの番号も実行やフォーカスの監視と共通になる。`resize` コマンドからの要求や自動配置に使う
+    pub fn backend(self: &Arc<Self>) -> Arc<dyn WindowBackend> {
+        Arc::new(Queued(Arc::clone(self)))
+    }
+
+    /// 操作の後に `job` を実行し、その結果を待つ
+    fn call<T: Send + 'static>(
+        &self,
+        label: String,
+        job: impl FnOnce(&dyn WindowBackend) -> anyhow::Result<T> + Send + 'static,
+    ) -> anyhow::Result<T> {
+        let (sender, result) = mpsc::channel();
+        self.executor
+            .submit(
+                None,
+                label.as_str(),
+                1,
+                Box::new(move |backend, _| {
+                    let _ = sender.send(job(backend)?);
+                    Ok(Vec::new())
+                }),
+            )
+            .wait()?;
+        result.recv().map_err(|_| anyhow!("{label} was not run"))
+    }
+
+    fn submit(
+        &self,
+        preferences: &Arc<Preferences>,
+        script: &Script,
+        binding: Option<&Binding>,
+        count: Option<u32>,
+    ) -> Pending {
+        let previous = {
+            let mut last = self.last.lock().unwrap_or_else(|err| err.into_inner());
+            let previous = last.clone();
+            if !script.0.iter().all(Command::is_repeat_last) {
+                *last = Some(Invocation {
+                    script: script.clone(),
+                    binding: binding.cloned(),
+                    count: count.unwrap_or(1),
+                });
+            }
+            previous
+        };
+
+        // 対象のウィンドウが分からなければまとめない
+        let focus = self.focus();
+        let key = focus
+            .as_ref()
+            .filter(|_| script.accumulates())
+            .map(|focus| DispatchKey {
+                window: focus.window,
+                script: script.to_string(),
+                binding: binding.map(|binding| binding.key.clone()),
+            });
+        let execution = Execution {
+            preferences: Arc::clone(preferences),
+            script: script.clone(),
+            binding: binding.cloned(),
+            focus,
+            previous,
+            count,
+        };
+        self.executor.submit(
+            key,
+            script.to_string(),
+            count.unwrap_or(1),
+            Box::new(move |backend, times| execution.run(backend, times)),
+        )
+    }
+}
+
+/// `Dispatcher::backend` が返すバックエンド
+struct Queued(Arc<Dispatcher>);
+
+impl WindowBackend for Queued {
+    fn screens(&self) -> anyhow::Result<Vec<Screen>> {
+        self.0.backend.screens()
+    }
+
+    fn frame(&self, window: WindowId) -> anyhow::Result<Frame> {
+        self.0.call(
+            format!("get the frame of window {window}"),
+            move |backend| backend.frame(window),
+        )
+    }
+
+    fn set_frame(&self, window: WindowId, frame: Frame) -> anyhow::Result<()> {
+        self.0.call(
+            format!("set the frame of window {window}"),
+            move |backend| backend.set_frame(window, frame),
+        )
+    }
+
+    fn windows(&self) -> anyhow::Result<Vec<WindowInfo>> {
+        self.0.backend.windows()
+    }
+
+    fn focused(&self) -> anyhow::Result<WindowId> {
+        self.0.backend.focused()
+    }
+
+    fn subscribe(&self, events: Sender<WindowEvent>) -> anyhow::Result<Subscription> {
+        self.0.backend.subscribe(events)
+    }
+
+    fn checks(&self) -> Vec<Box<dyn Check + '_>> {
+        self.0.backend.checks()
+    }
+}
+
+/// `Executor` のスレッドで行う 1 回の実行
+struct Execution {
+    preferences: Arc<Preferences>,
+    script: Script,
+    binding: Option<Binding>,
+    /// 投入した時点でフォーカスされていたウィンドウ。分からなければ実行するときに調べる
+    focus: Option<Focus>,
+    /// `repeat_last` で繰り返す直前の実行
+    previous: Option<Invocation>,
+    count: Option<u32>,
+}
+
+impl Execution {
+    /// `backend` を通して実行する
+    ///
+    /// `times` はまとめた実行の倍数の合計。`repeat_last` はまとめないので、`count` で直前の倍数を使うか決める
+    fn run(self, backend: &dyn WindowBackend, times: u32) -> anyhow::Result<Vec<OperationOutcome>> {
+        let (window, app): (WindowId, Option<AppIdentity>) = match self.focus {
+            Some(focus) => (focus.window, Some(focus.app)),
+            None => (backend.focused()?, window::focused_app()),
+        };
+        let Some(preferences) = self.preferences.for_app(app.as_ref()) else {
+            return Ok(Vec::new());
+        };
+        let preferences = preferences.as_ref();
+
+        let mut outcomes = Vec::new();
+        for command in &self.script.0 {
+            let result = if command.is_repeat_last() {
+                let Some(previous) = &self.previous else {
+                    bail!("{command}: nothing to repeat");
+                };
+                previous.script.execute_times(
+                    backend,
+                    window,
+                    preferences,
+                    previous.binding.as_ref(),
+                    self.count.unwrap_or(previous.count),
+                )?
+            } else {
+                vec![command.execute_times(
+                    backend,
+                    window,
+                    preferences,
+                    self.binding.as_ref(),
+                    times,
+                )?]
+            };
+            for outcome in &result {
+                log::debug!("{command}: {outcome}");
+            }
+            outcomes.extend(result);
+        }
+        Ok(outcomes)
+    }
+}
+
+/// ホットキーのスレッドから呼ばれ、メインスレッドで処理するキーのハンドラー
+///
+/// 受け取った側でメインスレッドに処理を渡す
+pub struct MainThreadHandlers {
+    /// リサイズモードのリーダーキー
+    pub leader: Handler,
+    /// キーの並びに含まれるキー。メインスレッドで `HotkeyManager::press_sequence_key` に渡す
+    pub sequence_key: Arc<dyn Fn(HotKey) + Send + Sync>,
+}
+
+/// キーの並びが揃ったときに実行する処理
+pub type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// キーの並びの入力を進めた結果
+pub enum SequenceProgress {
+    /// 続きを待っている。この時刻を過ぎたら `HotkeyManager::expire_sequence` を呼ぶ
+    Waiting(Instant),
+    /// 並びが揃った。実行する処理
+    Complete(Job),
+    /// 何も待っていない
+    Idle,
+}
+
+/// キーの並びが揃ったときの処理。引数は途中で入力された回数
+type SequenceAction = Arc<dyn Fn(Option<u32>) + Send + Sync>;
+
+/// 1 組のバインディングから作ったキーの割り当て
+#[derive(Default)]
+struct Keymap {
+    /// 1 つのキーだけのバインディング
+    single: HashMap<HotKey, SequenceAction>,
+    /// 2 つ以上のキーからなる並び
+    sequences: Vec<KeySequence>,
+    /// `sequences` と同じ順の、揃ったときの処理
+    actions: Vec<SequenceAction>,
+}
+
+impl Keymap {
+    fn new(
+        preferences: &Arc<Preferences>,
+        dispatcher: &Arc<Dispatcher>,
+        valid: &[ValidBinding<'_>],
+    ) -> Self {
+        let mut keymap = Keymap::default();
+        for valid in valid {
+            let preferences = Arc::clone(preferences);
+            let dispatcher = Arc::clone(dispatcher);
+            let script = valid.script.clone();
+            let overrides = valid.binding.clone();
+            let action: SequenceAction = Arc::new(move |count| {
+                dispatcher.run(&preferences, &script, Some(&overrides), count);
+            });
+
+            if valid.keys.0.len() == 1 {
+                keymap.single.insert(valid.keys.first(), action);
+            } else {
+                keymap.sequences.push(valid.keys.clone());
+                keymap.actions.push(action);
+            }
+        }
+        keymap
+    }
+}
+
+/// グローバルのバインディングと、`bindings` を持つルールごとのキーの割り当て
+#[derive(Default)]
+struct Keymaps {
+    preferences: Arc<Preferences>,
+    global: Keymap,
+    /// ルールの位置と、そのルールの割り当て
+    rules: Vec<(usize, Keymap)>,
+}
+
+impl Keymaps {
+    /// `focus` のアプリで使う割り当て。フォーカスが分からなければグローバルの割り当て
+    fn active(&self, focus: Option<&Focus>) -> &Keymap {
+        if self.rules.is_empty() {
+            return &self.global;
+        }
+        focus
+            .and_then(|focus| self.preferences.rule_for(&focus.app))
+            .and_then(|index| self.rules.iter().find(|(rule, _)| *rule == index))
+            .map_or(&self.global, |(_, keymap)| keymap)
+    }
+}
+
+/// 登録に使う検証結果
+struct Validated<'a> {
+    bindings: Vec<ValidBinding<'a>>,
+    /// `bindings` を持つルールの位置と、そのバインディング
+    rules: Vec<(usize, Vec<ValidBinding<'a>>)>,
+    modal: Option<ValidModal>,
+}
+
+fn validate(preferences: &Preferences) -> (Validated<'_>, ValidationReport) {
+    let (bindings, mut report) = validation::validate(preferences);
+    let (rules, rules_report) = validation::validate_rules(preferences);
+    let (modal, modal_report) = validation::validate_modal(preferences);
+    report.issues.extend(rules_report.issues);
+    report.issues.extend(modal_report.issues);
+    let validated = Validated {
+        bindings,
+        rules,
+        modal,
+    };
+    (validated, report)
+}
 
 pub struct HotkeyManager {
     manager: GlobalHotKeyManager,
-    handlers: Arc<HashMap<u32, Box<dyn Fn() + Send + Sync + 'static>>>,
+    /// 現在 OS に登録しているホットキー
+    registered: Vec<HotKey>,
+    /// リサイズモード中だけ登録しているホットキー
+    temporary: Vec<HotKey>,
+    /// 現在のリサイズモードの設定
+    modal: Option<ValidModal>,
+    /// キーを押したときに使う割り当て
+    keymaps: Arc<Keymaps>,
+    /// 入力中の並びに使っている割り当ての、2 つ以上のキーからなる並び
+    sequences: SequenceMatcher,
+    /// `sequences` と同じ順の、揃ったときの処理
+    sequence_actions: Vec<SequenceAction>,
+    sequence_timeout: Duration,
+    /// 並びの続きを待つ間だけ登録しているホットキー
+    listening: Vec<HotKey>,
+    sequence_key: Option<Arc<dyn Fn(HotKey) + Send + Sync>>,
+    handlers: Arc<RwLock<HashMap<u32, Handler>>>,
+    dispatcher: Arc<Dispatcher>,
 }
 
 impl HotkeyManager {
     pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
         let manager = GlobalHotKeyManager::new()?;
-        let handlers = Arc::new(HashMap::new());
+        let handlers = Arc::new(RwLock::new(HashMap::new()));
+
+        Ok(Self {
+            manager,
+            registered: Vec::new(),
+            temporary: Vec::new(),
+            modal: None,
+            keymaps: Arc::new(Keymaps::default()),
+            sequences: SequenceMatcher::new(Vec::new(), Duration::ZERO),
+            sequence_actions: Vec::new(),
+            sequence_timeout: Duration::ZERO,
+            listening: Vec::new(),
+            sequence_key: None,
+            handlers,
+            dispatcher: Arc::new(Dispatcher::default()),
+        })
+    }
 
-        Ok(Self { manager, handlers })
+    /// バインディングの実行に使う `Dispatcher`
+    pub fn dispatcher(&self) -> Arc<Dispatcher> {
+        Arc::clone(&self.dispatcher)
     }
 
+    /// 有効なバインディングだけを登録し、それまでのホットキーと置き換える
+    ///
+    /// 登録できなかったものを報告し、登録できたものだけを使う
     pub fn register_hotkeys(
         &mut self,
         preferences: &Preferences,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let resize_step = preferences.resize_step;
-        let move_step = preferences.move_step;
+        main_thread: MainThreadHandlers,
+    ) -> ValidationReport {
+        // 初めての登録は Accessibility の許可を得てから行う
+        self.dispatcher.watch_focus();
+        let (validated, mut report) = validate(preferences);
+        self.replace(preferences, validated, main_thread, &mut report, false);
+        report
+    }
+
+    /// 設定を読み込み直したときに使う
+    ///
+    /// 設定に問題があるか、新しいキーを 1 つでも登録できなければ何も変更せず、
+    /// 現在のホットキーを残したまま `Err` を返す
+    pub fn reload(
+        &mut self,
+        preferences: &Preferences,
+        main_thread: MainThreadHandlers,
+    ) -> Result<ValidationReport, ValidationReport> {
+        let (validated, mut report) = validate(preferences);
+        if !report.is_empty() {
+            return Err(report);
+        }
+        if !self.replace(preferences, validated, main_thread, &mut report, true) {
+            return Err(report);
+        }
+        Ok(report)
+    }
+
+    /// リサイズモードのキーを登録する
+    ///
+    /// 押されたキーは `handler` が返すハンドラーで `Input` として受け取る
+    pub fn register_mode_keys(&mut self, handler: impl Fn(Input) -> Handler) {
+        let Some(modal) = &self.modal else {
+            return;
+        };
+        let keys = modal
+            .keys
+            .iter()
+            .map(|(hotkey, script)| (*hotkey, Input::Key(script.clone())))
+            .chain([(modal.cancel, Input::Cancel), (modal.commit, Input::Commit)])
+            .chain(
+                (0..)
+                    .zip(&modal.digits)
+                    .map(|(digit, hotkey)| (*hotkey, Input::Digit(digit))),
+            )
+            .map(|(hotkey, input)| (hotkey, handler(input)));
+
+        register_temporary(&self.manager, &self.handlers, &mut self.temporary, keys);
+    }
+
+    /// `register_mode_keys` で登録したキーを外す
+    pub fn unregister_mode_keys(&mut self) {
+        unregister_temporary(&self.manager, &self.handlers, &mut self.temporary);
+    }
+
+    /// キーの並びに含まれるキーが押されたときに、メインスレッドから呼ぶ
+    pub fn press_sequence_key(&mut self, key: HotKey) -> SequenceProgress {
+        // 並びの最初のキーなら、最前面のアプリで使う割り当てで待ち始める
+        if self.sequences.deadline().is_none() {
+            let keymap = self.keymaps.active(self.dispatcher.focus().as_ref());
+            self.sequences = SequenceMatcher::new(keymap.sequences.clone(), self.sequence_timeout);
+            self.sequence_actions = keymap.actions.clone();
+        }
+        let outcome = self.sequences.press(key, Instant::now());
+        self.advance_sequence(outcome)
+    }
+
+    /// 並びの続きを待つ時間が過ぎたときに、メインスレッドから呼ぶ
+    pub fn expire_sequence(&mut self) -> SequenceProgress {
+        let outcome = self.sequences.tick(Instant::now());
+        self.advance_sequence(outcome)
+    }
+
+    fn advance_sequence(&mut self, outcome: Option<Outcome>) -> SequenceProgress {
+        match outcome {
+            Some(Outcome::Pending { listen }) => {
+                unregister_temporary(&self.manager, &self.handlers, &mut self.listening);
+                if let Some(sequence_key) = &self.sequence_key {
+                    let keys = listen.into_iter().map(|hotkey| {
+                        let sequence_key = Arc::clone(sequence_key);
+                        let handler: Handler = Box::new(move || sequence_key(hotkey));
+                        (hotkey, handler)
+                    });
+                    register_temporary(&self.manager, &self.handlers, &mut self.listening, keys);
+                }
+            }
+            Some(Outcome::Matched { index, count }) => {
+                unregister_temporary(&self.manager, &self.handlers, &mut self.listening);
+                let action = Arc::clone(&self.sequence_actions[index]);
+                return SequenceProgress::Complete(Box::new(move || action(count)));
+            }
+            Some(Outcome::Reset) => {
+                unregister_temporary(&self.manager, &self.handlers, &mut self.listening);
+            }
+            // 回数の入力では待つ時間だけが延びる
+            None => {}
+        }
+
+        match self.sequences.deadline() {
+            Some(deadline) => SequenceProgress::Waiting(deadline),
+            None => SequenceProgress::Idle,
+        }
+    }
+
+    /// 登録済みのホットキーを `validated` で置き換える
+    ///
+    /// グローバルとルールのバインディングのキーをまとめて登録し、押されたときに
+    /// 最前面のアプリで使う割り当てを選ぶ。並びは最初のキーだけを登録し、
+    /// 続きのキーは最初のキーが押されてから登録する。
+    /// まだ持っていないキーを先に登録し、新しい設定で使わなくなったキーだけを後で外す。
+    /// `atomic` なら 1 つでも登録できなかったときに登録したキーを外して元のまま残し、`false` を返す。
+    /// 置き換えの間はハンドラーの表をロックし、新旧が混ざった状態でキーが処理されないようにする
+    fn replace(
+        &mut self,
+        preferences: &Preferences,
+        validated: Validated<'_>,
+        main_thread: MainThreadHandlers,
+        report: &mut ValidationReport,
+        atomic: bool,
+    ) -> bool {
+        let Validated {
+            bindings,
+            rules,
+            modal,
+        } = validated;
+        let shared = Arc::new(preferences.clone());
+        let keymaps = Arc::new(Keymaps {
+            preferences: Arc::clone(&shared),
+            global: Keymap::new(&shared, &self.dispatcher, &bindings),
+            rules: rules
+                .iter()
+                .map(|(rule, valid)| (*rule, Keymap::new(&shared, &self.dispatcher, valid)))
+                .collect(),
+        });
+
+        // 登録するキーと、登録できなかったときに報告するバインディング
+        let mut owners: Vec<(HotKey, Option<usize>, &ValidBinding<'_>)> = Vec::new();
+        owners.extend(
+            bindings
+                .iter()
+                .map(|valid| (valid.keys.first(), None, valid)),
+        );
+        for (rule, valid) in &rules {
+            owners.extend(
+                valid
+                    .iter()
+                    .map(|valid| (valid.keys.first(), Some(*rule), valid)),
+            );
+        }
+
+        self.unregister_mode_keys();
+        unregister_temporary(&self.manager, &self.handlers, &mut self.listening);
+        let mut handlers = self.handlers.write().unwrap_or_else(|err| err.into_inner());
 
-        type Handler = Box<dyn Fn() + Send + Sync + 'static>;
-        let mut handlers: HashMap<u32, Handler> = HashMap::new();
+        // 新しい設定で持つキーと、この置き換えで新しく登録したキー
+        let mut held: Vec<HotKey> = Vec::new();
+        let mut added: Vec<HotKey> = Vec::new();
+        let mut failed = false;
+        let mut hold = |manager: &GlobalHotKeyManager, hotkey: HotKey| {
+            if held.contains(&hotkey) {
+                return Ok(());
+            }
+            if !self.registered.contains(&hotkey) {
+                manager.register(hotkey)?;
+                added.push(hotkey);
+            }
+            held.push(hotkey);
+            Ok::<(), global_hotkey::Error>(())
+        };
+
+        let modal = modal.and_then(|modal| {
+            if let Err(err) = hold(&self.manager, modal.leader) {
+                failed = true;
+                report.push(Issue::Modal {
+                    key: preferences.modal.leader.clone().unwrap_or_default(),
+                    message: format!(
+                        "failed to register the leader key (it may be used by another app): {err}"
+                    ),
+                });
+                return None;
+            }
+            Some(modal)
+        });
+        let leader = modal.as_ref().map(|modal| modal.leader);
 
-        for binding in &preferences.bindings {
-            let op: Operation = binding.operation.as_str().into();
-            let key = binding.key.as_str();
-            let Ok(hotkey): Result<HotKey, _> = key.parse() else {
+        let mut bound: Vec<HotKey> = Vec::new();
+        for (hotkey, _, _) in &owners {
+            let hotkey = *hotkey;
+            if Some(hotkey) == leader || bound.contains(&hotkey) {
                 continue;
-            };
+            }
+            if let Err(err) = hold(&self.manager, hotkey) {
+                failed = true;
+                for (_, rule, valid) in owners.iter().filter(|(other, _, _)| *other == hotkey) {
+                    let issue = Issue::RegisterFailed {
+                        index: valid.index,
+                        key: valid.binding.key.clone(),
+                        message: err.to_string(),
+                    };
+                    report.push(match rule {
+                        Some(rule) => issue.in_rule(*rule),
+                        None => issue,
+                    });
+                }
+                continue;
+            }
+            bound.push(hotkey);
+        }
 
-            let handler: Handler = match op {
-                Operation::MoveLeft => Box::new(move || {
-                    let _ = move_window_nswindow_style(&Direction::Left, move_step);
-                }),
-                Operation::MoveRight => Box::new(move || {
-                    let _ = move_window_nswindow_style(&Direction::Right, move_step);
-                }),
-                Operation::MoveUp => Box::new(move || {
-                    let _ = move_window_nswindow_style(&Direction::Up, move_step);
-                }),
-                Operation::MoveDown => Box::new(move || {
-                    let _ = move_window_nswindow_style(&Direction::Down, move_step);
-                }),
-                Operation::ResizeLeftToLeft => Box::new(move || {
-                    let _ = resize(Edge::Left, -resize_step);
-                }),
-                Operation::ResizeLeftToRight => Box::new(move || {
-                    let _ = resize(Edge::Left, resize_step);
-                }),
-                Operation::ResizeRightToLeft => Box::new(move || {
-                    let _ = resize(Edge::Right, -resize_step);
-                }),
-                Operation::ResizeRightToRight => Box::new(move || {
-                    let _ = resize(Edge::Right, resize_step);
-                }),
-                Operation::ResizeTopToTop => Box::new(move || {
-                    let _ = resize(Edge::Top, resize_step);
-                }),
-                Operation::ResizeTopToBottom => Box::new(move || {
-                    let _ = resize(Edge::Top, -resize_step);
-                }),
-                Operation::ResizeBottomToBottom => Box::new(move || {
-                    let _ = resize(Edge::Bottom, resize_step);
-                }),
-                Operation::ResizeBottomToTop => Box::new(move || {
-                    let _ = resize(Edge::Bottom, -resize_step);
-                }),
-            };
+        if atomic && failed {
+            if let Err(err) = self.manager.unregister_all(&added) {
+                log::error!("Failed to unregister hotkeys: {err}");
+            }
+            return false;
+        }
 
+        let stale: Vec<HotKey> = self
+            .registered
+            .iter()
+            .filter(|hotkey| !held.contains(hotkey))
+            .copied()
+            .collect();
+        if let Err(err) = self.manager.unregister_all(&stale) {
+            log::error!("Failed to unregister hotkeys: {err}");
+        }
+        self.registered = held;
+        handlers.clear();
+
+        let MainThreadHandlers {
+            leader: leader_handler,
+            sequence_key,
+        } = main_thread;
+        if let Some(leader) = leader {
+            handlers.insert(leader.id(), leader_handler);
+        }
+        for hotkey in bound {
+            let keymaps = Arc::clone(&keymaps);
+            let dispatcher = Arc::clone(&self.dispatcher);
+            let sequence_key = Arc::clone(&sequence_key);
+            let handler: Handler = Box::new(move || {
+                match keymaps
+                    .active(dispatcher.focus().as_ref())
+                    .single
+                    .get(&hotkey)
+                {
+                    Some(action) => action(None),
+                    None => sequence_key(hotkey),
+                }
+            });
             handlers.insert(hotkey.id(), handler);
-            self.manager.register(hotkey)?;
         }
 
-        self.handlers = Arc::new(handlers);
-        Ok(())
+        self.modal = modal;
+        self.sequences = SequenceMatcher::new(Vec::new(), Duration::ZERO);
+        self.sequence_actions = Vec::new();
+        self.sequence_timeout = preferences.sequence_timeout();
+        self.sequence_key = Some(sequence_key);
+        self.keymaps = keymaps;
+        true
     }
 
     pub fn start_monitoring(&self) {
@@ -88,13 +727,51 @@ impl HotkeyManager {
         thread::spawn(move || {
             let rx = GlobalHotKeyEvent::receiver();
             for event in rx {
-                if event.state == global_hotkey::HotKeyState::Pressed {
-                    if let Some(handler) = handlers.get(&event.id) {
-                        handler();
-                    }
+                if event.state == global_hotkey::HotKeyState::Pressed
+                    && let Some(handler) = handlers
+                        .read()
+                        .unwrap_or_else(|err| err.into_inner())
+                        .get(&event.id)
+                {
+                    handler();
                 }
             }
         });
     }
 }
 
+/// `keys` を一時的に登録し、登録できたものを `registered` に加える
+fn register_temporary(
+    manager: &GlobalHotKeyManager,
+    handlers: &RwLock<HashMap<u32, Handler>>,
+    registered: &mut Vec<HotKey>,
+    keys: impl IntoIterator<Item = (HotKey, Handler)>,
+) {
+    let mut handlers = handlers.write().unwrap_or_else(|err| err.into_inner());
+    for (hotkey, handler) in keys {
+        if let Err(err) = manager.register(hotkey) {
+            log::warn!("Failed to register \"{hotkey}\": {err}");
+            continue;
+        }
+        registered.push(hotkey);
+        handlers.insert(hotkey.id(), handler);
+    }
+}
+
+/// `register_temporary` で登録したキーを外す
+fn unregister_temporary(
+    manager: &GlobalHotKeyManager,
+    handlers: &RwLock<HashMap<u32, Handler>>,
+    registered: &mut Vec<HotKey>,
+) {
+    if registered.is_empty() {
+        return;
+    }
+    let mut handlers = handlers.write().unwrap_or_else(|err| err.into_inner());
+    if let Err(err) = manager.unregister_all(registered) {
+        log::error!("Failed to unregister temporary hotkeys: {err}");
+    }
+    for hotkey in registered.drain(..) {
+        handlers.remove(&hotkey.id());
+    }
+}
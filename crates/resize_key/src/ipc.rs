@@ -0,0 +1,87 @@
+use std::{
+    sync::{Arc, RwLock, mpsc},
+    thread,
+};
+
+use anyhow::anyhow;
+use core::{
+    command::Script,
+    ipc::{self, Host, Server},
+    operation::Operation,
+    outcome::OperationOutcome,
+    preferences::Preferences,
+};
+
+use crate::hotkey::Dispatcher;
+
+/// メインスレッドで設定を読み込み直し、設定の問題を送り返す関数
+pub type Reload = Arc<dyn Fn(mpsc::Sender<Vec<String>>) + Send + Sync>;
+
+/// `resize` コマンドからの要求を受けるサーバー
+///
+/// 待ち受けに失敗してもアプリはそのまま動かす
+pub struct IpcServer {
+    /// 要求を処理するスレッドと共有する現在の設定
+    preferences: Arc<RwLock<Arc<Preferences>>>,
+}
+
+impl IpcServer {
+    pub fn start(dispatcher: Arc<Dispatcher>, reload: Reload) -> Self {
+        let preferences = Arc::new(RwLock::new(Arc::new(Preferences::default())));
+        let backend = dispatcher.backend();
+        let host = Arc::new(AppHost {
+            preferences: Arc::clone(&preferences),
+            dispatcher,
+            reload,
+        });
+
+        let path = ipc::socket_path();
+        match Server::bind(&path, backend, host) {
+            Ok(server) => {
+                thread::spawn(move || server.serve());
+            }
+            Err(err) => log::error!("Failed to start the IPC server: {err:#}"),
+        }
+        Self { preferences }
+    }
+
+    pub fn update(&self, preferences: Arc<Preferences>) {
+        *self
+            .preferences
+            .write()
+            .unwrap_or_else(|err| err.into_inner()) = preferences;
+    }
+}
+
+struct AppHost {
+    preferences: Arc<RwLock<Arc<Preferences>>>,
+    dispatcher: Arc<Dispatcher>,
+    reload: Reload,
+}
+
+impl Host for AppHost {
+    fn preferences(&self) -> Arc<Preferences> {
+        Arc::clone(
+            &self
+                .preferences
+                .read()
+                .unwrap_or_else(|err| err.into_inner()),
+        )
+    }
+
+    /// メニューから実行したときと同じく、バインディングの設定で実行する
+    fn run(&self, operation: Operation) -> anyhow::Result<Vec<OperationOutcome>> {
+        let preferences = self.preferences();
+        let binding = preferences.binding_for(&operation);
+        self.dispatcher
+            .try_run(&preferences, &Script::from(operation), binding, None)
+    }
+
+    fn reload(&self) -> anyhow::Result<Vec<String>> {
+        let (sender, warnings) = mpsc::channel();
+        (self.reload)(sender);
+        warnings
+            .recv()
+            .map_err(|_| anyhow!("Settings were not reloaded"))
+    }
+}
@@ -1,16 +1,21 @@
+mod controller;
+mod focus;
 mod hotkey;
-mod preferences;
-use std::ffi::c_void;
+mod ipc;
+mod menu;
+mod placement;
+mod watcher;
+use std::{ffi::c_void, path::PathBuf};
 
-use crate::hotkey::HotkeyManager;
-use objc2::{AnyThread, MainThreadMarker, MainThreadOnly, rc::Retained, sel};
-use objc2_app_kit::{
-    NSApplication, NSApplicationActivationPolicy, NSImage, NSMenu, NSMenuItem, NSStatusBar,
+use crate::{controller::Controller, hotkey::HotkeyManager, watcher::SettingsWatcher};
+use core::{
+    config::LoadOptions,
+    logging::{self, LogLevel, Output},
 };
+use objc2::{AnyThread, MainThreadMarker, rc::Retained};
+use objc2_app_kit::{NSApplication, NSApplicationActivationPolicy, NSImage, NSMenu, NSStatusBar};
 use objc2_foundation::{NSData, NSSize, ns_string};
 
-use crate::preferences::Preferences;
-
 const IMAGE_BYTES: &[u8] = include_bytes!("assets/mono_1.png");
 const ICON_SIZE: f64 = 24.0;
 
@@ -25,23 +30,57 @@ fn load_embedded_image() -> Option<Retained<NSImage>> {
     }
 }
 
+/// 起動引数の `--<name> <value>` / `--<name>=<value>`
+///
+/// Finder から起動した場合に渡される `-psn_…` などの引数は無視する
+fn arg_value(name: &str) -> Option<String> {
+    let flag = format!("--{name}");
+    let mut args = std::env::args_os()
+        .skip(1)
+        .filter_map(|arg| arg.into_string().ok());
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next();
+        }
+        if let Some(value) = arg
+            .strip_prefix(&flag)
+            .and_then(|rest| rest.strip_prefix('='))
+        {
+            return Some(value.to_string());
+        }
+    }
+    None
+}
+
+/// ログファイルに書き始める。書けなければ標準エラー出力に書く
+///
+/// 水準は設定を読み込んだ後に `Controller` が反映する
+fn init_logging() {
+    if let Err(err) = logging::init(Output::File(logging::log_path()), LogLevel::default()) {
+        eprintln!("Failed to open the log file: {err:#}");
+        let _ = logging::init(Output::Stderr, LogLevel::default());
+    }
+    logging::install_panic_hook();
+}
+
 fn main() {
+    init_logging();
     let mtm = MainThreadMarker::new().unwrap();
     let app = NSApplication::sharedApplication(mtm);
-    let preferences = Preferences::new();
-
-    let Ok(mut hotkey_manager) = HotkeyManager::new() else {
-        eprintln!("Failed to create HotkeyManager");
-        return;
+    let load_options = LoadOptions {
+        config: arg_value("config").map(PathBuf::from),
+        profile: arg_value("profile"),
     };
-    let Ok(_) = hotkey_manager.register_hotkeys(&preferences) else {
-        eprintln!("Failed to register hotkeys");
+
+    let Ok(hotkey_manager) = HotkeyManager::new() else {
+        log::error!("Failed to create HotkeyManager");
         return;
     };
     hotkey_manager.start_monitoring();
 
     app.setActivationPolicy(NSApplicationActivationPolicy::Accessory);
 
+    let menu = NSMenu::new(mtm);
     unsafe {
         let status_bar = NSStatusBar::systemStatusBar();
         let item = status_bar.statusItemWithLength(20.);
@@ -55,16 +94,16 @@ fn main() {
             }
         }
 
-        let menu = NSMenu::new(mtm);
-        let quit = NSMenuItem::initWithTitle_action_keyEquivalent(
-            NSMenuItem::alloc(mtm),
-            ns_string!("Quit"),
-            Some(sel!(terminate:)),
-            ns_string!("q"),
-        );
-        menu.addItem(&quit);
         item.setMenu(Some(&menu));
     }
 
+    // 設定の読み込みと監視、メニューの更新は `Controller` が行う
+    let _controller = Controller::new(
+        mtm,
+        hotkey_manager,
+        SettingsWatcher::new(load_options),
+        menu,
+    );
+
     app.run();
 }
@@ -0,0 +1,187 @@
+use core::operation::{Category, REGISTRY};
+use objc2::{MainThreadMarker, MainThreadOnly, runtime::AnyObject, sel};
+use objc2_app_kit::{NSMenu, NSMenuItem};
+use objc2_foundation::{NSString, ns_string};
+
+/// メニューの先頭に表示する警告
+pub struct Warning {
+    pub title: String,
+    pub lines: Vec<String>,
+}
+
+/// メニューから実行できる操作
+pub struct MenuOperation {
+    pub category: Category,
+    pub title: String,
+    /// 割り当てられているキー
+    pub key: Option<String>,
+}
+
+/// ステータスバーのメニューを作り直す
+///
+/// `target` は `runOperation:`（`operations` の添字を tag に持つ）、
+/// "Reload Settings" の `reloadSettings:` と "Open Log" の `openLog:` を受け取るオブジェクト
+pub fn rebuild(
+    menu: &NSMenu,
+    warnings: &[Warning],
+    operations: &[MenuOperation],
+    target: &AnyObject,
+    mtm: MainThreadMarker,
+) {
+    menu.removeAllItems();
+
+    for warning in warnings {
+        add_warning_items(menu, &warning.title, &warning.lines, mtm);
+    }
+    if !warnings.is_empty() {
+        menu.addItem(&NSMenuItem::separatorItem(mtm));
+    }
+
+    add_operation_items(menu, operations, target, mtm);
+    menu.addItem(&NSMenuItem::separatorItem(mtm));
+
+    unsafe {
+        let reload = NSMenuItem::initWithTitle_action_keyEquivalent(
+            NSMenuItem::alloc(mtm),
+            ns_string!("Reload Settings"),
+            Some(sel!(reloadSettings:)),
+            ns_string!("r"),
+        );
+        reload.setTarget(Some(target));
+        menu.addItem(&reload);
+    }
+    add_app_items(menu, target, mtm);
+}
+
+/// Accessibility の許可を待つ間のメニュー
+///
+/// 許可されるまで操作は並べない。`target` は "Open Accessibility Settings" の
+/// `openAccessibilitySettings:` と "Open Log" の `openLog:` を受け取るオブジェクト
+pub fn rebuild_permission_required(menu: &NSMenu, target: &AnyObject, mtm: MainThreadMarker) {
+    menu.removeAllItems();
+
+    add_warning_items(
+        menu,
+        "Permission required",
+        &[
+            "Allow ResizeKey in System Settings > Privacy & Security > Accessibility.".to_string(),
+            "Hotkeys are enabled as soon as it is allowed.".to_string(),
+        ],
+        mtm,
+    );
+    unsafe {
+        let settings = NSMenuItem::initWithTitle_action_keyEquivalent(
+            NSMenuItem::alloc(mtm),
+            ns_string!("Open Accessibility Settings…"),
+            Some(sel!(openAccessibilitySettings:)),
+            ns_string!(""),
+        );
+        settings.setTarget(Some(target));
+        menu.addItem(&settings);
+        menu.addItem(&NSMenuItem::separatorItem(mtm));
+    }
+    add_app_items(menu, target, mtm);
+}
+
+/// どのメニューにも並べる "Open Log" と "Quit"
+fn add_app_items(menu: &NSMenu, target: &AnyObject, mtm: MainThreadMarker) {
+    unsafe {
+        let open_log = NSMenuItem::initWithTitle_action_keyEquivalent(
+            NSMenuItem::alloc(mtm),
+            ns_string!("Open Log"),
+            Some(sel!(openLog:)),
+            ns_string!("l"),
+        );
+        open_log.setTarget(Some(target));
+        menu.addItem(&open_log);
+        menu.addItem(&NSMenuItem::separatorItem(mtm));
+
+        let quit = NSMenuItem::initWithTitle_action_keyEquivalent(
+            NSMenuItem::alloc(mtm),
+            ns_string!("Quit"),
+            Some(sel!(terminate:)),
+            ns_string!("q"),
+        );
+        menu.addItem(&quit);
+    }
+}
+
+/// 分類ごとのサブメニューに操作を並べて `menu` に追加する
+fn add_operation_items(
+    menu: &NSMenu,
+    operations: &[MenuOperation],
+    target: &AnyObject,
+    mtm: MainThreadMarker,
+) {
+    // `REGISTRY` に現れる順。同じ分類が離れて現れても 1 つにまとめる
+    let mut categories: Vec<Category> = Vec::new();
+    for info in REGISTRY {
+        if !categories.contains(&info.category) {
+            categories.push(info.category);
+        }
+    }
+
+    for category in categories {
+        let submenu = NSMenu::new(mtm);
+        for (index, operation) in operations.iter().enumerate() {
+            if operation.category != category {
+                continue;
+            }
+            let title = match &operation.key {
+                Some(key) => format!("{}  ({key})", operation.title),
+                None => operation.title.clone(),
+            };
+            unsafe {
+                let item = NSMenuItem::initWithTitle_action_keyEquivalent(
+                    NSMenuItem::alloc(mtm),
+                    &NSString::from_str(&title),
+                    Some(sel!(runOperation:)),
+                    ns_string!(""),
+                );
+                item.setTarget(Some(target));
+                item.setTag(index as _);
+                submenu.addItem(&item);
+            }
+        }
+        if submenu.numberOfItems() == 0 {
+            continue;
+        }
+
+        let header = unsafe {
+            NSMenuItem::initWithTitle_action_keyEquivalent(
+                NSMenuItem::alloc(mtm),
+                &NSString::from_str(category.title()),
+                None,
+                ns_string!(""),
+            )
+        };
+        header.setSubmenu(Some(&submenu));
+        menu.addItem(&header);
+    }
+}
+
+/// 警告の見出しと、その詳細を並べたサブメニューを `menu` に追加する
+fn add_warning_items(menu: &NSMenu, title: &str, lines: &[String], mtm: MainThreadMarker) {
+    unsafe {
+        let header = NSMenuItem::initWithTitle_action_keyEquivalent(
+            NSMenuItem::alloc(mtm),
+            &NSString::from_str(&format!("⚠ {title}")),
+            None,
+            ns_string!(""),
+        );
+
+        let submenu = NSMenu::new(mtm);
+        for line in lines {
+            let item = NSMenuItem::initWithTitle_action_keyEquivalent(
+                NSMenuItem::alloc(mtm),
+                &NSString::from_str(line),
+                None,
+                ns_string!(""),
+            );
+            submenu.addItem(&item);
+        }
+
+        header.setSubmenu(Some(&submenu));
+        menu.addItem(&header);
+    }
+}
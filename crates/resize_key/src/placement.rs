@@ -0,0 +1,73 @@
+use std::{
+    sync::{Arc, RwLock, mpsc},
+    thread,
+};
+
+use core::{
+    placement,
+    preferences::Preferences,
+    window::{Subscription, WindowEvent},
+};
+
+use crate::hotkey::Dispatcher;
+
+/// ルールの `place` に従って、新しく開いたウィンドウを配置する
+///
+/// `place` を書いたルールがある間だけウィンドウを監視する。
+/// 配置はホットキーからの実行と同じく `Dispatcher` のスレッドで行う
+pub struct AutoPlacement {
+    dispatcher: Arc<Dispatcher>,
+    /// 監視のスレッドと共有する現在の設定
+    preferences: Arc<RwLock<Arc<Preferences>>>,
+    subscription: Option<Subscription>,
+}
+
+impl AutoPlacement {
+    pub fn new(dispatcher: Arc<Dispatcher>) -> Self {
+        Self {
+            dispatcher,
+            preferences: Arc::new(RwLock::new(Arc::new(Preferences::default()))),
+            subscription: None,
+        }
+    }
+
+    /// 設定を反映し、必要に応じて監視を始める・やめる
+    pub fn update(&mut self, preferences: Arc<Preferences>) {
+        let enabled = preferences.rules.iter().any(|rule| rule.place.is_some());
+        *self
+            .preferences
+            .write()
+            .unwrap_or_else(|err| err.into_inner()) = preferences;
+
+        if !enabled {
+            self.subscription = None;
+        } else if self.subscription.is_none() {
+            match self.start() {
+                Ok(subscription) => self.subscription = Some(subscription),
+                Err(err) => log::error!("Failed to watch new windows: {err}"),
+            }
+        }
+    }
+
+    fn start(&self) -> anyhow::Result<Subscription> {
+        let (events, received) = mpsc::channel();
+        let subscription = self.dispatcher.backend().subscribe(events)?;
+        let dispatcher = Arc::clone(&self.dispatcher);
+        let preferences = Arc::clone(&self.preferences);
+
+        // 監視をやめると送る側が落ち、このスレッドも終わる
+        thread::spawn(move || {
+            for event in received {
+                if !matches!(event, WindowEvent::WindowCreated { .. }) {
+                    continue;
+                }
+                let current =
+                    Arc::clone(&preferences.read().unwrap_or_else(|err| err.into_inner()));
+                dispatcher.enqueue("place a new window", move |backend| {
+                    placement::place_new_window(backend, &current, &event).map(|_| ())
+                });
+            }
+        });
+        Ok(subscription)
+    }
+}
@@ -0,0 +1,49 @@
+use std::{path::PathBuf, time::SystemTime};
+
+use core::config::{LoadOptions, layer_paths};
+
+/// 設定ファイルの変更を更新日時で検出する
+///
+/// 読み込むファイルそのものが変わる場合（新しく作られた、優先度の高いファイルが増えた等）も変更とみなす
+pub struct SettingsWatcher {
+    options: LoadOptions,
+    snapshot: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl SettingsWatcher {
+    pub fn new(options: LoadOptions) -> Self {
+        let snapshot = take_snapshot(&options);
+        Self { options, snapshot }
+    }
+
+    pub fn options(&self) -> &LoadOptions {
+        &self.options
+    }
+
+    /// 前回の確認から変更があれば `true` を返す
+    pub fn changed(&mut self) -> bool {
+        let snapshot = take_snapshot(&self.options);
+        if snapshot == self.snapshot {
+            return false;
+        }
+        self.snapshot = snapshot;
+        true
+    }
+
+    /// 現在の状態を基準にする（手動で読み込み直したときに使う）
+    pub fn reset(&mut self) {
+        self.snapshot = take_snapshot(&self.options);
+    }
+}
+
+fn take_snapshot(options: &LoadOptions) -> Vec<(PathBuf, Option<SystemTime>)> {
+    layer_paths(options)
+        .into_iter()
+        .map(|path| {
+            let modified = std::fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            (path, modified)
+        })
+        .collect()
+}
@@ -1,99 +1,250 @@
-use std::str::FromStr;
+use std::{path::PathBuf, str::FromStr};
 
-use clap::Parser;
+use clap::{ArgAction, Parser, Subcommand};
+use core::{
+    command::Script,
+    frame::{Direction, Edge},
+    logging::LogLevel,
+    operation::Operation,
+    screen::ScreenSelector,
+    step::Step,
+};
+
+use crate::{output::Format, target::WindowSelector};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub(crate) struct Args {
-    /// Move the whole window: --move <dir> <step>
-    /// <dir> = right|left|up|down, <step> = integer (points)
-    #[arg(long = "move", value_names = ["DIR", "STEP"], num_args = 2)]
+    /// Same as `move <dir> <step>` (kept for existing scripts)
+    #[arg(long = "move", value_names = ["DIR", "STEP"], num_args = 2, hide = true)]
     pub(crate) r#move: Option<Vec<String>>,
 
-    /// Resize by moving an edge: --resize <edge> <delta>
-    /// <edge> = left|right|top|bottom, <delta> = integer (points; sign = direction)
-    #[arg(long = "resize", value_names = ["EDGE", "DELTA"], num_args = 2, allow_hyphen_values = true)]
+    /// Same as `resize <edge> <delta>` (kept for existing scripts)
+    #[arg(
+        long = "resize",
+        value_names = ["EDGE", "DELTA"],
+        num_args = 2,
+        allow_hyphen_values = true,
+        hide = true
+    )]
     pub(crate) resize: Option<Vec<String>>,
-}
 
-pub(crate) enum Direction {
-    Right,
-    Left,
-    Up,
-    Down,
+    /// Output format
+    #[arg(long, global = true, value_enum, default_value_t = Format::Text)]
+    pub(crate) format: Format,
+
+    /// Window to act on: "focused", an id from list-windows, or conditions like app=Safari,title=^Inbox
+    #[arg(
+        long,
+        global = true,
+        value_name = "SELECTOR",
+        default_value = "focused"
+    )]
+    pub(crate) window: WindowSelector,
+
+    /// Settings file or directory to use instead of the default search paths
+    #[arg(long, global = true, value_name = "PATH")]
+    pub(crate) config: Option<PathBuf>,
+
+    /// Profile to layer on top of the settings file (profiles/<NAME>.json next to it)
+    #[arg(long, global = true, value_name = "NAME")]
+    pub(crate) profile: Option<String>,
+
+    /// Fail instead of showing the Accessibility permission dialog when permission is missing
+    #[arg(long, global = true)]
+    pub(crate) no_prompt: bool,
+
+    /// Log more to stderr (-v info, -vv debug, -vvv trace)
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub(crate) verbose: u8,
+
+    /// Commands to run, e.g. "move left 50; resize right +10%; place left_half; screen next"
+    #[arg(
+        value_name = "COMMAND",
+        trailing_var_arg = true,
+        allow_hyphen_values = true
+    )]
+    pub(crate) script: Vec<String>,
+
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
 }
 
-impl From<Direction> for core::frame::Direction {
-    fn from(value: Direction) -> Self {
-        match value {
-            Direction::Right => core::frame::Direction::Right,
-            Direction::Left => core::frame::Direction::Left,
-            Direction::Up => core::frame::Direction::Up,
-            Direction::Down => core::frame::Direction::Down,
+impl Args {
+    /// `-v` の数に応じた標準エラー出力へのログの水準。指定がなければ警告以上
+    pub(crate) fn log_level(&self) -> LogLevel {
+        match self.verbose {
+            0 => LogLevel::Warn,
+            1 => LogLevel::Info,
+            2 => LogLevel::Debug,
+            _ => LogLevel::Trace,
         }
     }
 }
 
-pub(crate) enum Edge {
-    Left,
-    Right,
-    Top,
-    Bottom,
+#[derive(Subcommand, Debug)]
+pub(crate) enum Command {
+    /// Move the whole window: move <dir> <step>
+    Move {
+        /// right|left|up|down
+        direction: Direction,
+        /// Points, "5%" of screen, "10%w" of window or "1/12" of screen
+        #[arg(allow_hyphen_values = true)]
+        step: Step,
+    },
+    /// Resize by moving an edge: resize <edge> <delta>
+    Resize {
+        /// left|right|top|bottom
+        edge: Edge,
+        /// Same units as the step of `move` (sign = direction)
+        #[arg(allow_hyphen_values = true)]
+        delta: Step,
+    },
+    /// Set the frame; omitted values keep the current ones
+    ///
+    /// Values are points ("100", "=-1440" for a negative position), a percentage of the
+    /// visible frame of the window's screen ("50%") or relative to the current frame ("+20", "-10%")
+    Set(FrameValues),
+    /// Print the frame (--format text|json|csv|shell)
+    Get,
+    /// Apply a named preset from settings: place <name>
+    #[command(alias = "preset")]
+    Place {
+        name: String,
+        /// Display to place the window on (current, main, next, previous or 1-based index)
+        #[arg(long)]
+        screen: Option<ScreenSelector>,
+    },
+    /// List the open windows with their ids
+    ListWindows,
+    /// List the connected displays; the first one is the main display
+    ListScreens,
+    /// Run an operation with the step and options from settings: run <operation>
+    Run { operation: Operation },
+    /// List every operation with its default key
+    Operations,
+    /// Print window events (focus, move, resize, create, destroy) as JSON lines
+    Watch,
+    /// Check permissions, settings, hotkeys, window access and the running app
+    Doctor,
+    /// Inspect the settings file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
 }
 
-impl From<Edge> for core::frame::Edge {
-    fn from(value: Edge) -> Self {
-        match value {
-            Edge::Left => core::frame::Edge::Left,
-            Edge::Right => core::frame::Edge::Right,
-            Edge::Top => core::frame::Edge::Top,
-            Edge::Bottom => core::frame::Edge::Bottom,
-        }
-    }
+#[derive(Subcommand, Debug)]
+pub(crate) enum ConfigCommand {
+    /// Load the settings file and report errors and invalid bindings
+    Check,
+    /// Print which settings file is used and where it was found
+    Path,
+    /// Print the settings files, or the merged result with --effective
+    Print {
+        /// Print the built-in defaults merged with the settings and profile files
+        #[arg(long)]
+        effective: bool,
+    },
 }
 
-pub(crate) enum Action {
-    Move(Direction, f64),
-    Resize(Edge, f64),
+/// `set` の値。省略した値は現在のフレームのまま
+#[derive(clap::Args, Debug, Clone, Copy)]
+pub(crate) struct FrameValues {
+    /// Left edge: points, "50%" of the screen, or relative "+20" / "-10%"
+    #[arg(long, allow_hyphen_values = true)]
+    pub(crate) x: Option<FrameValue>,
+    /// Top edge: points, "50%" of the screen, or relative "+20" / "-10%"
+    #[arg(long, allow_hyphen_values = true)]
+    pub(crate) y: Option<FrameValue>,
+    /// Width: points, "50%" of the screen, or relative "+20" / "-10%"
+    #[arg(long, allow_hyphen_values = true)]
+    pub(crate) w: Option<FrameValue>,
+    /// Height: points, "50%" of the screen, or relative "+20" / "-10%"
+    #[arg(long, allow_hyphen_values = true)]
+    pub(crate) h: Option<FrameValue>,
+}
+
+/// `set` の値 1 つ
+///
+/// 符号を付けると現在の値からの相対指定になる。負の座標は `=-1440` のように書く
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum FrameValue {
+    Points(f64),
+    /// 画面の可視領域に対する割合。位置なら可視領域の端から
+    ScreenPercent(f64),
+    /// 現在の値に足すポイント
+    Relative(f64),
+    /// 現在の値に足す、可視領域に対する割合
+    RelativePercent(f64),
 }
 
-impl FromStr for Direction {
+impl FromStr for FrameValue {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_ascii_lowercase().as_str() {
-            "r" | "right" => Ok(Direction::Right),
-            "l" | "left" => Ok(Direction::Left),
-            "u" | "up" => Ok(Direction::Up),
-            "d" | "down" => Ok(Direction::Down),
-            _ => Err(format!("invalid dir: {s}")),
+        let s = s.trim();
+        let number = |value: &str| -> Result<f64, String> {
+            value
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid value: {s}"))
+        };
+        let (absolute, value) = match s.strip_prefix('=') {
+            Some(value) => (true, value),
+            None => (!s.starts_with(['+', '-']), s),
+        };
+
+        match (absolute, value.strip_suffix('%')) {
+            (true, Some(percent)) => Ok(FrameValue::ScreenPercent(number(percent)?)),
+            (true, None) => Ok(FrameValue::Points(number(value)?)),
+            (false, Some(percent)) => Ok(FrameValue::RelativePercent(number(percent)?)),
+            (false, None) => Ok(FrameValue::Relative(number(value)?)),
         }
     }
 }
 
-impl FromStr for Edge {
-    type Err = String;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_ascii_lowercase().as_str() {
-            "l" | "left" => Ok(Edge::Left),
-            "r" | "right" => Ok(Edge::Right),
-            "t" | "top" => Ok(Edge::Top),
-            "b" | "bottom" => Ok(Edge::Bottom),
-            _ => Err(format!("invalid edge: {s}")),
+impl FrameValue {
+    /// `current` は現在の値、`origin` と `length` は可視領域のその軸の始点と長さ（大きさなら `origin` は 0）
+    pub(crate) fn resolve(self, current: f64, origin: f64, length: f64) -> f64 {
+        match self {
+            FrameValue::Points(points) => points,
+            FrameValue::ScreenPercent(percent) => origin + length * percent / 100.0,
+            FrameValue::Relative(points) => current + points,
+            FrameValue::RelativePercent(percent) => current + length * percent / 100.0,
         }
     }
 }
 
+/// フレームを変える操作
+pub(crate) enum Edit {
+    Move(Direction, Step),
+    Resize(Edge, Step),
+    Set(FrameValues),
+    Place(String, ScreenSelector),
+}
+
+pub(crate) enum Action {
+    /// `--window` のウィンドウを変える
+    Edit(Edit),
+    Get,
+    ListWindows,
+    ListScreens,
+    Run(Operation),
+    Script(Script),
+    Operations,
+    Watch,
+    Doctor,
+    Config(ConfigCommand),
+}
+
 pub(crate) fn parse_move(vals: &[String]) -> Result<Action, String> {
     if vals.len() != 2 {
         return Err("needs: --move <dir> <step>".into());
     }
     let dir = Direction::from_str(&vals[0])?;
-    let step: f64 = vals[1]
-        .parse()
-        .map_err(|_| "STEP must be a floating point number")?;
-    Ok(Action::Move(dir, step))
+    let step = Step::from_str(&vals[1]).map_err(|err| format!("STEP: {err}"))?;
+    Ok(Action::Edit(Edit::Move(dir, step)))
 }
 
 pub(crate) fn parse_resize(vals: &[String]) -> Result<Action, String> {
@@ -101,8 +252,60 @@ pub(crate) fn parse_resize(vals: &[String]) -> Result<Action, String> {
         return Err("needs: --resize <edge> <delta>".into());
     }
     let edge = Edge::from_str(&vals[0])?;
-    let delta: f64 = vals[1]
-        .parse()
-        .map_err(|_| "DELTA must be a floating point number")?;
-    Ok(Action::Resize(edge, delta))
+    let delta = Step::from_str(&vals[1]).map_err(|err| format!("DELTA: {err}"))?;
+    Ok(Action::Edit(Edit::Resize(edge, delta)))
+}
+
+pub(crate) fn parse_script(words: &[String]) -> Result<Action, String> {
+    let source = words.join(" ");
+    source.parse().map(Action::Script).map_err(|err| {
+        let annotated = err.annotate(&source).replace('\n', "\n  ");
+        format!("{err}\n  {annotated}")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_frame_values() {
+        let cases = [
+            ("100", FrameValue::Points(100.0)),
+            (" 100 ", FrameValue::Points(100.0)),
+            ("50%", FrameValue::ScreenPercent(50.0)),
+            ("+20", FrameValue::Relative(20.0)),
+            ("-20", FrameValue::Relative(-20.0)),
+            ("-10%", FrameValue::RelativePercent(-10.0)),
+            ("=-1440", FrameValue::Points(-1440.0)),
+            ("= 50 %", FrameValue::ScreenPercent(50.0)),
+            ("=+20%", FrameValue::ScreenPercent(20.0)),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(input.parse::<FrameValue>(), Ok(expected), "{input}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_frame_values() {
+        for input in ["", "abc", "=", "+", "+ 20", "%", "50%%", "10pt", "=-"] {
+            assert!(input.parse::<FrameValue>().is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn resolves_frame_values() {
+        // 可視領域の横方向が x = 100 から 1000pt、現在の値が 300
+        let cases = [
+            ("50", 50.0),
+            ("50%", 600.0),
+            ("+20", 320.0),
+            ("-10%", 200.0),
+            ("=-1440", -1440.0),
+        ];
+        for (input, expected) in cases {
+            let value: FrameValue = input.parse().unwrap();
+            assert_eq!(value.resolve(300.0, 100.0, 1000.0), expected, "{input}");
+        }
+    }
 }
@@ -0,0 +1,111 @@
+use anyhow::bail;
+use core::{
+    config::{LoadOptions, layer_paths, profile_path, resolve, search_dirs},
+    preferences::Preferences,
+    validation::{validate, validate_modal, validate_rules},
+};
+
+use crate::args::ConfigCommand;
+
+pub(crate) fn run(command: ConfigCommand, options: &LoadOptions) -> anyhow::Result<()> {
+    match command {
+        ConfigCommand::Check => check(options),
+        ConfigCommand::Path => path(options),
+        ConfigCommand::Print { effective } => print(options, effective),
+    }
+}
+
+fn load(options: &LoadOptions) -> anyhow::Result<Preferences> {
+    match Preferences::load(options) {
+        Ok(preferences) => Ok(preferences),
+        Err(err) => {
+            eprintln!("error: {err}");
+            bail!("settings could not be loaded");
+        }
+    }
+}
+
+fn check(options: &LoadOptions) -> anyhow::Result<()> {
+    let preferences = load(options)?;
+
+    let paths = layer_paths(options);
+    if paths.is_empty() {
+        println!("No user settings found, using the built-in defaults");
+    }
+    for path in &paths {
+        println!("Loaded {}", path.display());
+    }
+    if let Some(resolved) = resolve(options.config.as_deref()) {
+        for ignored in &resolved.ignored {
+            eprintln!(
+                "warning: {} is ignored because {} takes precedence",
+                ignored.display(),
+                resolved.path.display()
+            );
+        }
+    }
+
+    let (_, mut report) = validate(&preferences);
+    report.issues.extend(validate_rules(&preferences).1.issues);
+    report.issues.extend(validate_modal(&preferences).1.issues);
+    for issue in &report.issues {
+        eprintln!("warning: {issue}");
+    }
+    if !report.is_empty() {
+        bail!("{} binding problem(s)", report.issues.len());
+    }
+
+    println!("OK");
+    Ok(())
+}
+
+fn path(options: &LoadOptions) -> anyhow::Result<()> {
+    let resolved = resolve(options.config.as_deref());
+    let profile = options
+        .profile()
+        .and_then(|profile| profile_path(resolved.as_ref(), &profile));
+
+    let Some(resolved) = resolved else {
+        eprintln!("No user settings found, using the built-in defaults. Searched:");
+        for (source, dir) in search_dirs() {
+            eprintln!("  {} ({source})", dir.display());
+        }
+        if let Some(profile) = profile {
+            println!("{}", profile.display());
+            eprintln!("source: --profile");
+            return Ok(());
+        }
+        bail!("no settings file");
+    };
+
+    println!("{}", resolved.path.display());
+    eprintln!("source: {}", resolved.source);
+    if !resolved.path.is_file() {
+        eprintln!("warning: {} does not exist", resolved.path.display());
+    }
+    for ignored in &resolved.ignored {
+        eprintln!("ignored: {}", ignored.display());
+    }
+    if let Some(profile) = profile {
+        println!("{}", profile.display());
+        eprintln!("source: --profile");
+    }
+    Ok(())
+}
+
+fn print(options: &LoadOptions, effective: bool) -> anyhow::Result<()> {
+    if effective {
+        let preferences = load(options)?;
+        println!("{}", serde_json::to_string_pretty(&preferences)?);
+        return Ok(());
+    }
+
+    println!("// built-in defaults");
+    println!("{}", serde_json::to_string_pretty(&Preferences::default())?);
+    for path in layer_paths(options) {
+        println!();
+        println!("// {}", path.display());
+        print!("{}", std::fs::read_to_string(&path)?);
+    }
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use anyhow::bail;
+use core::{
+    ax_backend::AxBackend,
+    config::LoadOptions,
+    doctor::{AppCheck, Check, ConfigCheck, HotkeyCheck, Report, Status, WindowAccessCheck},
+    ipc::{self, Client, RemoteBackend},
+    window::WindowBackend,
+};
+
+use crate::output::Format;
+
+/// 診断をすべて行って結果を出力する。失敗があればエラーにする
+pub(crate) fn run(format: Format, options: &LoadOptions) -> anyhow::Result<()> {
+    let path = ipc::socket_path();
+    // 起動しているアプリがあれば、ウィンドウの操作もアプリを通したものを確かめる
+    let backend: Box<dyn WindowBackend> = match Client::connect(&path) {
+        Ok(client) => Box::new(RemoteBackend::new(client)),
+        Err(_) => Box::new(AxBackend::default()),
+    };
+
+    let mut checks: Vec<Box<dyn Check + '_>> = vec![
+        Box::new(ConfigCheck::new(options.clone())),
+        Box::new(HotkeyCheck::new(options.clone())),
+        Box::new(AppCheck::new(path)),
+    ];
+    checks.extend(backend.checks());
+    checks.push(Box::new(WindowAccessCheck::new(backend.as_ref())));
+    let report = Report::run(&checks);
+
+    match format {
+        Format::Text => print(&report),
+        Format::Json => println!("{}", serde_json::to_string(&report)?),
+        Format::Csv | Format::Shell => unreachable!("only get takes csv and shell"),
+    }
+
+    let failures = report.failures();
+    if failures > 0 {
+        bail!("{failures} check(s) failed");
+    }
+    Ok(())
+}
+
+fn print(report: &Report) {
+    for finding in &report.findings {
+        let status = match finding.diagnosis.status {
+            Status::Pass => "ok",
+            Status::Warn => "warn",
+            Status::Fail => "FAIL",
+            Status::Skip => "skip",
+        };
+        let mut lines = finding.diagnosis.detail.lines();
+        println!(
+            "[{status:^4}] {}: {}",
+            finding.name,
+            lines.next().unwrap_or_default()
+        );
+        for line in lines {
+            println!("       {line}");
+        }
+        if let Some(hint) = &finding.diagnosis.hint {
+            println!("       hint: {hint}");
+        }
+    }
+}
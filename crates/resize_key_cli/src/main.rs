@@ -1,31 +1,170 @@
 mod args;
+mod config;
+mod doctor;
+mod output;
+mod target;
+mod watch;
+mod window;
 
 use clap::Parser;
 use core::{
-    frame::{move_window, resize},
-    window::ensure_ax_trusted,
+    ax_backend::AxBackend,
+    config::LoadOptions,
+    ipc::{self, Client, Method, Reply},
+    logging::{self, Output},
+    operation::{Operation, REGISTRY},
+    outcome::OperationOutcome,
+    preferences::Preferences,
+    window::WindowBackend,
 };
 
-use crate::args::{Action, Args, parse_move, parse_resize};
+use crate::{
+    args::{Action, Args, Command, Edit, parse_move, parse_resize, parse_script},
+    output::Format,
+};
 
 fn main() -> anyhow::Result<()> {
-    if !ensure_ax_trusted() {
-        eprintln!("Enable Accessibility permission for this app, then run again.");
-        return Ok(());
-    }
-
     let args = Args::parse();
+    logging::init(Output::Stderr, args.log_level())?;
 
-    let action = match (args.r#move, args.resize) {
-        (Some(m), None) => parse_move(&m).map_err(anyhow::Error::msg)?,
-        (None, Some(r)) => parse_resize(&r).map_err(anyhow::Error::msg)?,
-        (None, None) => return Err(anyhow::Error::msg("specify either --move or --resize")),
-        (Some(_), Some(_)) => return Err(anyhow::Error::msg("use only one of --move or --resize")),
+    let options = LoadOptions {
+        config: args.config,
+        profile: args.profile,
     };
+    let script = (!args.script.is_empty()).then_some(args.script);
+    let action = match (args.r#move, args.resize, script, args.command) {
+        (Some(m), None, None, None) => parse_move(&m).map_err(anyhow::Error::msg)?,
+        (None, Some(r), None, None) => parse_resize(&r).map_err(anyhow::Error::msg)?,
+        (None, None, Some(words), None) => parse_script(&words).map_err(anyhow::Error::msg)?,
+        (None, None, None, Some(Command::Move { direction, step })) => {
+            Action::Edit(Edit::Move(direction, step))
+        }
+        (None, None, None, Some(Command::Resize { edge, delta })) => {
+            Action::Edit(Edit::Resize(edge, delta))
+        }
+        (None, None, None, Some(Command::Set(values))) => Action::Edit(Edit::Set(values)),
+        (None, None, None, Some(Command::Get)) => Action::Get,
+        (None, None, None, Some(Command::Place { name, screen })) => {
+            Action::Edit(Edit::Place(name, screen.unwrap_or_default()))
+        }
+        (None, None, None, Some(Command::ListWindows)) => Action::ListWindows,
+        (None, None, None, Some(Command::ListScreens)) => Action::ListScreens,
+        (None, None, None, Some(Command::Run { operation })) => Action::Run(operation),
+        (None, None, None, Some(Command::Operations)) => Action::Operations,
+        (None, None, None, Some(Command::Watch)) => Action::Watch,
+        (None, None, None, Some(Command::Doctor)) => Action::Doctor,
+        (None, None, None, Some(Command::Config { command })) => Action::Config(command),
+        (None, None, None, None) => {
+            return Err(anyhow::Error::msg("specify commands or a subcommand"));
+        }
+        _ => {
+            return Err(anyhow::Error::msg(
+                "use only one of --move, --resize, commands or a subcommand",
+            ));
+        }
+    };
+
+    if matches!(args.format, Format::Csv | Format::Shell) && !matches!(action, Action::Get) {
+        return Err(anyhow::Error::msg(
+            "--format csv and --format shell are only supported by get",
+        ));
+    }
+    if matches!(
+        action,
+        Action::Edit(_) | Action::Get | Action::ListWindows | Action::ListScreens
+    ) {
+        return window::run(action, &args.window, args.format, &options, !args.no_prompt);
+    }
+    if !args.window.is_focused() {
+        return Err(anyhow::Error::msg(
+            "--window applies only to move, resize, set, get and place",
+        ));
+    }
+
+    // 設定の確認や一覧の表示だけなら Accessibility の許可はいらない。診断は許可を確かめるだけでダイアログを出さない
+    match action {
+        Action::Config(command) => return config::run(command, &options),
+        Action::Doctor => return doctor::run(args.format, &options),
+        Action::Operations => {
+            print_operations();
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    // アプリが起動していればアプリに実行させ、Accessibility の許可はアプリのものを使う
+    if let Action::Run(operation) = &action
+        && let Some(mut client) = connect_app(&options)
+    {
+        let outcomes = match client.request(Method::RunOperation {
+            operation: operation.clone(),
+        })? {
+            Reply::Outcomes { outcomes } => outcomes,
+            reply => {
+                return Err(anyhow::anyhow!(
+                    "Unexpected response from ResizeKey: {reply:?}"
+                ));
+            }
+        };
+        return output::outcomes(args.format, &outcomes);
+    }
+
+    window::check_trust(!args.no_prompt)?;
 
     match action {
-        Action::Move(direction, step) => move_window(&direction.into(), step)?,
-        Action::Resize(edge, delta) => resize(edge.into(), delta)?,
+        Action::Run(operation) => output::outcomes(args.format, &[run(operation, &options)?])?,
+        Action::Script(script) => {
+            let preferences = Preferences::load(&options)?;
+            let backend = AxBackend::default();
+            let outcomes = script.execute(&backend, backend.focused()?, &preferences, None)?;
+            output::outcomes(args.format, &outcomes)?;
+        }
+        Action::Watch => watch::run()?,
+        Action::Edit(_)
+        | Action::Get
+        | Action::ListWindows
+        | Action::ListScreens
+        | Action::Config(_)
+        | Action::Doctor
+        | Action::Operations => unreachable!(),
     }
     Ok(())
 }
+
+/// 起動している ResizeKey につなぐ
+///
+/// `--config` や `--profile` はアプリの設定と異なるかもしれないので、指定されていればつながない
+fn connect_app(options: &LoadOptions) -> Option<Client> {
+    if options.config.is_some() || options.profile.is_some() {
+        return None;
+    }
+    Client::connect(&ipc::socket_path()).ok()
+}
+
+/// 設定の step や、`operation` のバインディングの設定を使って実行する
+fn run(operation: Operation, options: &LoadOptions) -> anyhow::Result<OperationOutcome> {
+    let preferences = Preferences::load(options)?;
+    let binding = preferences.binding_for(&operation);
+    let operation_options = preferences.options_for(&operation, binding);
+    let backend = AxBackend::default();
+    operation.execute(
+        &backend,
+        backend.focused()?,
+        &preferences.presets,
+        &operation_options,
+    )
+}
+
+fn print_operations() {
+    for info in REGISTRY {
+        let id = match info.parameter {
+            Some(parameter) => format!("{}:<{parameter}>", info.id),
+            None => info.id.to_string(),
+        };
+        println!(
+            "{id:<28} {:<20} {}",
+            info.default_key.unwrap_or("-"),
+            info.description
+        );
+    }
+}
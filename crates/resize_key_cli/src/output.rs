@@ -0,0 +1,140 @@
+use clap::ValueEnum;
+use core::{
+    frame::Frame,
+    outcome::OperationOutcome,
+    screen::Screen,
+    window::{AppIdentity, WindowId, WindowInfo},
+};
+use serde::Serialize;
+
+/// `--format` で選ぶ出力の形式
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum Format {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON document per run
+    Json,
+    /// "x,y,w,h" (get only)
+    Csv,
+    /// WINDOW=, X=, Y=, W= and H= lines for `eval` (get only)
+    Shell,
+}
+
+/// `--window` で選んだウィンドウのフレームを変えた結果
+#[derive(Serialize)]
+pub(crate) struct Change {
+    #[serde(flatten)]
+    pub(crate) app: AppIdentity,
+    #[serde(flatten)]
+    pub(crate) outcome: OperationOutcome,
+}
+
+/// `list-screens` の 1 行
+#[derive(Serialize)]
+struct ScreenEntry<'a> {
+    /// `--screen` に書く 1 始まりの番号
+    index: usize,
+    main: bool,
+    #[serde(flatten)]
+    screen: &'a Screen,
+}
+
+pub(crate) fn change(format: Format, change: &Change) -> anyhow::Result<()> {
+    match format {
+        Format::Text => match change.outcome.window {
+            Some(window) => println!("{}: {}", label(window, &change.app), change.outcome),
+            None => println!("{}", change.outcome),
+        },
+        Format::Json => println!("{}", serde_json::to_string(change)?),
+        Format::Csv | Format::Shell => unreachable!("only get takes csv and shell"),
+    }
+    Ok(())
+}
+
+/// `run` やコマンドの並びで実行した操作の結果。1 つのコマンドにつき 1 つ
+pub(crate) fn outcomes(format: Format, outcomes: &[OperationOutcome]) -> anyhow::Result<()> {
+    match format {
+        Format::Text => {
+            for outcome in outcomes {
+                println!("{outcome}");
+            }
+        }
+        Format::Json => println!("{}", serde_json::to_string(outcomes)?),
+        Format::Csv | Format::Shell => unreachable!("only get takes csv and shell"),
+    }
+    Ok(())
+}
+
+pub(crate) fn window(format: Format, info: &WindowInfo) -> anyhow::Result<()> {
+    let Frame { x, y, w, h } = info.frame;
+    match format {
+        Format::Text => println!("{}: {}", label(info.window, &info.app), info.frame),
+        Format::Json => println!("{}", serde_json::to_string(info)?),
+        Format::Csv => println!("{x},{y},{w},{h}"),
+        Format::Shell => {
+            println!("WINDOW={}", info.window);
+            println!("X={x}");
+            println!("Y={y}");
+            println!("W={w}");
+            println!("H={h}");
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn windows(format: Format, windows: &[WindowInfo]) -> anyhow::Result<()> {
+    match format {
+        Format::Text => {
+            for info in windows {
+                println!("{}: {}", label(info.window, &info.app), info.frame);
+            }
+        }
+        Format::Json => println!("{}", serde_json::to_string(windows)?),
+        Format::Csv | Format::Shell => unreachable!("only get takes csv and shell"),
+    }
+    Ok(())
+}
+
+pub(crate) fn screens(format: Format, screens: &[Screen]) -> anyhow::Result<()> {
+    let entries: Vec<ScreenEntry> = screens
+        .iter()
+        .enumerate()
+        .map(|(index, screen)| ScreenEntry {
+            index: index + 1,
+            main: index == 0,
+            screen,
+        })
+        .collect();
+
+    match format {
+        Format::Text => {
+            for entry in &entries {
+                println!(
+                    "{} (display {}{}): {}, visible {}",
+                    entry.index,
+                    entry.screen.id,
+                    if entry.main { ", main" } else { "" },
+                    entry.screen.frame,
+                    entry.screen.visible_frame
+                );
+            }
+        }
+        Format::Json => println!("{}", serde_json::to_string(&entries)?),
+        Format::Csv | Format::Shell => unreachable!("only get takes csv and shell"),
+    }
+    Ok(())
+}
+
+/// `12 Safari "Inbox"` のようなウィンドウの名前
+fn label(window: WindowId, app: &AppIdentity) -> String {
+    let mut label = window.to_string();
+    if let Some(name) = &app.name {
+        label.push(' ');
+        label.push_str(name);
+    }
+    if let Some(title) = app.title.as_ref().filter(|title| !title.is_empty()) {
+        label.push_str(&format!(" {title:?}"));
+    }
+    label
+}
@@ -0,0 +1,111 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::anyhow;
+use core::{
+    rule::{AppMatcher, TitlePattern},
+    window::{AppIdentity, WindowBackend, WindowId, WindowInfo},
+};
+
+/// `--window` で指定する操作対象のウィンドウ
+///
+/// `focused`、`list-windows` の番号、または `app=Safari,title=^Inbox` のような条件で書く
+#[derive(Debug, Clone, Default)]
+pub(crate) enum WindowSelector {
+    #[default]
+    Focused,
+    Id(WindowId),
+    /// 条件に当てはまる最初のウィンドウ
+    Matching(AppMatcher),
+}
+
+impl FromStr for WindowSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("focused") {
+            return Ok(WindowSelector::Focused);
+        }
+        if let Ok(id) = s.parse() {
+            return Ok(WindowSelector::Id(id));
+        }
+
+        let mut matcher = AppMatcher::default();
+        for condition in s.split(',') {
+            let Some((key, value)) = condition.split_once('=') else {
+                return Err(format!(
+                    "invalid window: {s} (use focused, an id or app=/bundle=/title=)"
+                ));
+            };
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "app" | "name" => matcher.name = Some(value.to_string()),
+                "bundle" | "bundle_id" => matcher.bundle_id = Some(value.to_string()),
+                "title" => {
+                    let pattern = TitlePattern::new(value);
+                    if let Some(reason) = pattern.error() {
+                        return Err(format!("invalid title pattern \"{value}\": {reason}"));
+                    }
+                    matcher.title = Some(pattern);
+                }
+                other => return Err(format!("invalid window condition: {other}")),
+            }
+        }
+        Ok(WindowSelector::Matching(matcher))
+    }
+}
+
+impl fmt::Display for WindowSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WindowSelector::Focused => f.write_str("focused"),
+            WindowSelector::Id(id) => write!(f, "{id}"),
+            WindowSelector::Matching(matcher) => {
+                let conditions: Vec<String> = [
+                    ("app", matcher.name.as_deref()),
+                    ("bundle", matcher.bundle_id.as_deref()),
+                    ("title", matcher.title.as_ref().map(TitlePattern::as_str)),
+                ]
+                .into_iter()
+                .filter_map(|(key, value)| value.as_ref().map(|value| format!("{key}={value}")))
+                .collect();
+                f.write_str(&conditions.join(","))
+            }
+        }
+    }
+}
+
+impl WindowSelector {
+    pub(crate) fn is_focused(&self) -> bool {
+        matches!(self, WindowSelector::Focused)
+    }
+
+    /// 対象のウィンドウを探す
+    pub(crate) fn resolve(&self, backend: &dyn WindowBackend) -> anyhow::Result<WindowInfo> {
+        let id = match self {
+            WindowSelector::Focused => backend.focused()?,
+            WindowSelector::Id(id) => *id,
+            WindowSelector::Matching(matcher) => {
+                return backend
+                    .windows()?
+                    .into_iter()
+                    .find(|info| matcher.matches(&info.app))
+                    .ok_or_else(|| anyhow!("No window matches {self}"));
+            }
+        };
+
+        match backend
+            .windows()?
+            .into_iter()
+            .find(|info| info.window == id)
+        {
+            Some(info) => Ok(info),
+            // 一覧に出てこないウィンドウ（シートなど）でもフレームが読めれば操作できる
+            None => Ok(WindowInfo {
+                window: id,
+                app: AppIdentity::default(),
+                frame: backend.frame(id)?,
+            }),
+        }
+    }
+}
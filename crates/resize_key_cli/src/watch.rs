@@ -0,0 +1,34 @@
+use std::{
+    io::{self, Write},
+    sync::mpsc,
+};
+
+use core::{
+    ax_backend::AxBackend,
+    window::{WindowBackend, WindowEvent},
+};
+
+/// ウィンドウの変化を 1 行 1 つの JSON で出力し続ける
+///
+/// 出力先が閉じられたら終わる
+pub(crate) fn run() -> anyhow::Result<()> {
+    let backend = AxBackend::default();
+    let (sender, events) = mpsc::channel();
+    let _subscription = backend.subscribe(sender)?;
+
+    let mut stdout = io::stdout().lock();
+    for event in events {
+        // アプリの起動はウィンドウの変化ではないので出さない
+        if matches!(event, WindowEvent::AppLaunched { .. }) {
+            continue;
+        }
+        let line = serde_json::to_string(&event)?;
+        if writeln!(stdout, "{line}")
+            .and_then(|_| stdout.flush())
+            .is_err()
+        {
+            break;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use std::time::Instant;
+
+use anyhow::bail;
+use core::{
+    ax_backend::AxBackend,
+    config::LoadOptions,
+    frame::Frame,
+    ipc::RemoteBackend,
+    outcome::OperationOutcome,
+    placement,
+    preferences::Preferences,
+    preset,
+    screen::{ScreenSelector, select_among},
+    window::{WindowBackend, WindowInfo, is_trusted, request_trust},
+};
+
+use crate::{
+    args::{Action, Edit, FrameValue},
+    output::{self, Change, Format},
+    target::WindowSelector,
+};
+
+/// 起動している ResizeKey を通して操作する。起動していないか、`--config` や `--profile` が
+/// 指定されていれば AX API を直接使う
+///
+/// `prompt` は `check_trust` を参照
+pub(crate) fn backend(
+    options: &LoadOptions,
+    prompt: bool,
+) -> anyhow::Result<Box<dyn WindowBackend>> {
+    if let Some(client) = crate::connect_app(options) {
+        return Ok(Box::new(RemoteBackend::new(client)));
+    }
+    check_trust(prompt)?;
+    Ok(Box::new(AxBackend::default()))
+}
+
+/// Accessibility の許可がなければエラーにする
+///
+/// `prompt` なら許可を求めるダイアログも出す。`--no-prompt` ではダイアログを出さない
+pub(crate) fn check_trust(prompt: bool) -> anyhow::Result<()> {
+    let trusted = if prompt {
+        request_trust()
+    } else {
+        is_trusted()
+    };
+    if !trusted {
+        bail!("Enable Accessibility permission for this app, then run again.");
+    }
+    Ok(())
+}
+
+/// ウィンドウやディスプレイを扱う `action` を実行して結果を出力する
+pub(crate) fn run(
+    action: Action,
+    selector: &WindowSelector,
+    format: Format,
+    options: &LoadOptions,
+    prompt: bool,
+) -> anyhow::Result<()> {
+    let backend = backend(options, prompt)?;
+    let backend = backend.as_ref();
+
+    match action {
+        Action::Edit(edit) => {
+            let target = selector.resolve(backend)?;
+            let outcome = apply(backend, &target, edit, options)?;
+            output::change(
+                format,
+                &Change {
+                    app: target.app,
+                    outcome,
+                },
+            )
+        }
+        Action::Get => output::window(format, &selector.resolve(backend)?),
+        Action::ListWindows => output::windows(format, &backend.windows()?),
+        Action::ListScreens => output::screens(format, &backend.screens()?),
+        _ => unreachable!("not a window action"),
+    }
+}
+
+fn apply(
+    backend: &dyn WindowBackend,
+    target: &WindowInfo,
+    edit: Edit,
+    options: &LoadOptions,
+) -> anyhow::Result<OperationOutcome> {
+    let started = Instant::now();
+    let current = target.frame;
+    let screens = backend.screens()?;
+    let main = screens.first().map_or(0, |screen| screen.id);
+    let screen = select_among(&screens, main, ScreenSelector::Current, &current)?;
+    let preferences = Preferences::load(options)?;
+
+    let requested = match edit {
+        Edit::Move(direction, step) => {
+            let step = step.resolve(direction.axis(), &screen.visible_frame, &current);
+            current.moved(&direction, step)
+        }
+        Edit::Resize(edge, delta) => {
+            let delta = delta.resolve(edge.axis(), &screen.visible_frame, &current);
+            current.resized(edge, delta)
+        }
+        Edit::Set(values) => {
+            let visible = screen.visible_frame;
+            let resolve = |value: Option<FrameValue>, current: f64, origin: f64, length: f64| {
+                value.map_or(current, |value| value.resolve(current, origin, length))
+            };
+            Frame::new(
+                resolve(values.x, current.x, visible.x, visible.w),
+                resolve(values.y, current.y, visible.y, visible.h),
+                resolve(values.w, current.w, 0.0, visible.w),
+                resolve(values.h, current.h, 0.0, visible.h),
+            )
+        }
+        Edit::Place(name, screen) => {
+            let preset = preset::find(&preferences.presets, &name)?;
+            return placement::place(
+                backend,
+                target.window,
+                preset,
+                screen,
+                preferences.constraint,
+            );
+        }
+    };
+    let applied = preferences
+        .constraint
+        .apply(requested, &screen.visible_frame);
+    backend.set_frame(target.window, applied)?;
+
+    let after = backend.frame(target.window)?;
+    Ok(OperationOutcome::new(
+        Some(target.window),
+        current,
+        requested,
+        applied,
+        after,
+        started,
+    ))
+}